@@ -8,8 +8,16 @@ pub enum Error {
     BincodeEncode(#[from] bincode::error::EncodeError),
     #[error("Error while decoding using bincode: {0}")]
     BincodeDecode(#[from] bincode::error::DecodeError),
+    #[error("Error while parsing json: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("Service Error: {0}")]
     IpcResponseError(String),
+    #[error(
+        "Service update required, protocol version {requested} is not supported, service supports up to {supported}"
+    )]
+    UnsupportedVersion { requested: u32, supported: u32 },
+    #[error("Message of {len} bytes exceeds the maximum of {max} bytes")]
+    FrameTooLarge { len: usize, max: usize },
 }
 
 pub type Result<T = ()> = core::result::Result<T, Error>;
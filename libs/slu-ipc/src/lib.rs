@@ -1,32 +1,108 @@
 pub mod error;
 pub mod messages;
+pub mod rate_limit;
 
 use std::{
-    io::{BufRead, Write},
-    sync::Arc,
+    io::{BufRead, Read, Write},
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
+use bincode::error::DecodeError;
 use interprocess::os::windows::{
     named_pipe::{
         DuplexPipeStream, PipeListenerOptions,
         pipe_mode::Bytes,
-        tokio::{DuplexPipeStream as AsyncDuplexPipeStream, PipeListenerOptionsExt},
+        tokio::{DuplexPipeStream as AsyncDuplexPipeStream, PipeListener, PipeListenerOptionsExt},
     },
-    security_descriptor::{AsSecurityDescriptorMutExt, SecurityDescriptor},
+    security_descriptor::SecurityDescriptor,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+    sync::mpsc::error::TrySendError,
 };
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tracing::Instrument;
+use widestring::{U16CStr, u16cstr};
 
 use crate::{
-    error::Result,
-    messages::{IpcResponse, SvcAction, SvcMessage},
+    error::{Error, Result},
+    messages::{
+        IdentifiedResponse, IpcResponse, SvcAction, SvcEvent, SvcMessage, SvcMessageHeader,
+    },
+    rate_limit::RateLimiter,
 };
 
-/// https://learn.microsoft.com/en-us/windows/win32/secauthz/security-descriptor-control
-static SE_DACL_PROTECTED: u16 = 4096u16;
+/// Protected DACL giving full access to SYSTEM and the administrators and read/write access
+/// to the interactive users. Services, network logons and sandboxed processes can't connect.
+/// https://learn.microsoft.com/en-us/windows/win32/secauthz/security-descriptor-string-format
+const PIPE_SDDL: &U16CStr = u16cstr!("D:P(A;;GA;;;SY)(A;;GA;;;BA)(A;;GRGW;;;IU)");
 
 // const END_OF_TRANSMISSION: u8 = 0x04;
 const END_OF_TRANSMISSION_BLOCK: u8 = 0x17;
 
+/// first byte of the length-prefixed frames, bincode varints only use up to 254
+/// so no message of the delimited format starts with it
+const FRAME_MARKER: u8 = 0xFF;
+/// longer frames are rejected before allocating their payload, the service can set its own limit
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+/// first frame of a client asking for a nonce to sign its message with, instead of
+/// sending the raw token. Not a valid bincode message, so older services reject it.
+/// Clients send their protocol version after it, as 4 little-endian bytes.
+const CHALLENGE_REQUEST: &[u8] = b"slu-challenge";
+/// connections handled at the same time by the service, the rest wait to be accepted
+const MAX_CONCURRENT_CONNECTIONS: usize = 8;
+/// clients that connect and then stall are dropped after it, instead of keeping their slot
+const REQUEST_IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// time given on shutdown to the connections being handled, idle ones are not waited
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// events queued for each subscriber, once full the subscriber is considered stalled and dropped
+const SUBSCRIBER_QUEUE_LEN: usize = 64;
+
+/// Wire format of a message. `Delimited` is the format used before the length prefix,
+/// it is still read and answered for peers not yet updated, to be removed on the next release.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Framing {
+    #[default]
+    LengthPrefixed,
+    Delimited,
+}
+
+/// Limits applied to each connection accepted by the service
+#[derive(Debug, Clone, Copy)]
+pub struct ServiceIpcLimits {
+    /// connections are kept open between requests, up to this time without receiving any
+    pub idle_timeout: Duration,
+    /// larger messages abort the connection without reading them
+    pub max_message_len: usize,
+}
+
+impl Default for ServiceIpcLimits {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(30),
+            max_message_len: MAX_FRAME_LEN,
+        }
+    }
+}
+
+static SERVICE_RATE_LIMITER: LazyLock<RateLimiter> = LazyLock::new(RateLimiter::default);
+static APP_LAST_SEEN: Mutex<Option<Instant>> = Mutex::new(None);
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+/// set once the service didn't answer a length-prefixed handshake, so the next connections
+/// don't wait for the timeout again
+static DELIMITED_SERVICE: AtomicBool = AtomicBool::new(false);
+
+/// Connection subscribed to the service events, see `ServiceIpc::emit`
+struct Subscriber {
+    /// json of the events to be written
+    events: tokio::sync::mpsc::Sender<Vec<u8>>,
+    /// set when the queue overflows, the connection is closed without writing the rest
+    overflowed: Arc<AtomicBool>,
+}
+
 pub trait IPC {
     const PATH: &'static str;
 
@@ -34,11 +110,12 @@ pub trait IPC {
     async fn server_process_id() -> Result<u32> {
         let stream = AsyncDuplexPipeStream::connect_by_path(Self::PATH).await?;
         let pid = stream.server_process_id()?;
-        write_to_ipc_stream(&stream, &[]).await?;
+        write_to_ipc_stream(&stream, &[], Framing::LengthPrefixed).await?;
         Ok(pid)
     }
 
-    /// returns the server process id
+    /// Checks that the server is listening, the empty payload is answered
+    /// by the server without being processed as a command.
     fn test_connection() -> Result<()> {
         let stream = DuplexPipeStream::connect_by_path(Self::PATH)?;
         let response = send_to_ipc_stream(&stream, &[])?;
@@ -58,87 +135,599 @@ impl IPC for ServiceIpc {
     const PATH: &'static str = r"\\.\pipe\seelen-ui-service";
 }
 
+/// Returned by `ServiceIpc::start`, the listener is kept running if dropped
+pub struct ServiceIpcHandle {
+    stop: Arc<tokio::sync::Notify>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ServiceIpcHandle {
+    /// Stops accepting connections, closing the pipe, and waits for the connections being
+    /// handled up to `SHUTDOWN_DRAIN_TIMEOUT`
+    pub async fn shutdown(self) {
+        // subscribers write the events already queued and then close their connection
+        SUBSCRIBERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        self.stop.notify_one();
+        if let Err(err) = self.task.await {
+            log::error!("IPC listener task failed: {err}");
+        }
+    }
+}
+
 impl ServiceIpc {
-    pub fn start<R, F>(cb: F) -> Result<()>
+    /// `is_trusted_peer` receives the process id of each client once connected, connections of
+    /// untrusted processes are dropped before reading anything from them.
+    pub fn start<R, F, P>(
+        cb: F,
+        is_trusted_peer: P,
+        limits: ServiceIpcLimits,
+    ) -> Result<ServiceIpcHandle>
     where
         R: Future<Output = IpcResponse> + Send + Sync,
         F: Fn(SvcAction) -> R + Send + Sync + 'static,
+        P: Fn(u32) -> bool + Send + Sync + 'static,
     {
-        let mut sd = SecurityDescriptor::new()?;
-        unsafe { sd.set_dacl(std::ptr::null_mut(), false)? };
-        sd.set_control(SE_DACL_PROTECTED, SE_DACL_PROTECTED)?;
-
-        let listener = PipeListenerOptions::new()
-            .path(Self::PATH)
-            .security_descriptor(Some(sd))
-            .create_tokio_duplex::<Bytes>()?;
+        let listener = create_listener(Self::PATH)?;
 
-        tokio::spawn(async move {
+        let stop = Arc::new(tokio::sync::Notify::new());
+        let stop_signal = stop.clone();
+        let task = tokio::spawn(async move {
             let callback = Arc::new(cb);
-            while let Ok(stream) = listener.accept().await {
+            let slots = Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT_CONNECTIONS));
+            loop {
+                let next_connection = async {
+                    let slot = slots.clone().acquire_owned().await.ok()?;
+                    let stream = listener.accept().await.ok()?;
+                    Some((slot, stream))
+                };
+                let next = tokio::select! {
+                    _ = stop_signal.notified() => None,
+                    next = next_connection => next,
+                };
+                let Some((slot, stream)) = next else {
+                    break;
+                };
+                match stream.client_process_id() {
+                    Ok(pid) if is_trusted_peer(pid) => {}
+                    Ok(pid) => {
+                        log::warn!("IPC connection from untrusted process {pid} dropped");
+                        continue;
+                    }
+                    Err(err) => {
+                        log::warn!("IPC connection dropped, client process unknown: {err}");
+                        continue;
+                    }
+                }
+
                 let callback = callback.clone();
                 tokio::spawn(async move {
-                    if let Err(err) = Self::process_connection(&stream, callback).await
-                        && let Err(send_err) =
-                            Self::response_to_client(&stream, IpcResponse::Err(err.to_string()))
-                                .await
-                    {
-                        log::error!(
-                            "Failed to send error response: {send_err} || Original error: {err}"
-                        );
+                    let _slot = slot;
+                    let mut framing = Framing::default();
+                    match Self::process_connection(&stream, &mut framing, callback, limits).await {
+                        Ok(()) => {}
+                        // the rest of the message is never read, so the connection can't be reused
+                        Err(err @ Error::FrameTooLarge { .. }) => {
+                            log::warn!("IPC connection aborted: {err}");
+                        }
+                        Err(err) => {
+                            if let Err(send_err) = Self::response_to_client(
+                                &stream,
+                                framing,
+                                IpcResponse::Err(err.to_string()),
+                            )
+                            .await
+                            {
+                                log::error!(
+                                    "Failed to send error response: {send_err} || Original error: {err}"
+                                );
+                            }
+                        }
                     }
                 });
             }
+
+            drop(listener);
+            let all_slots = MAX_CONCURRENT_CONNECTIONS as u32;
+            if tokio::time::timeout(SHUTDOWN_DRAIN_TIMEOUT, slots.acquire_many(all_slots))
+                .await
+                .is_err()
+            {
+                log::debug!("IPC listener stopped with connections still open");
+            }
         });
-        Ok(())
+        Ok(ServiceIpcHandle { stop, task })
     }
 
+    /// Handles the requests of the client until it disconnects or stays idle for the limit.
+    /// `framing` is set to the format of the last request, so the response uses the same one.
     async fn process_connection<F, R>(
         stream: &AsyncDuplexPipeStream<Bytes>,
+        framing: &mut Framing,
         cb: Arc<F>,
+        limits: ServiceIpcLimits,
     ) -> Result<()>
     where
         R: Future<Output = IpcResponse> + Send + Sync,
         F: Fn(SvcAction) -> R + Send + Sync + 'static,
     {
-        let data = read_from_ipc_stream(stream).await?;
+        let max_len = limits.max_message_len;
+        // kept for the whole connection, a read can buffer the start of the next request
+        let mut reader = BufReader::new(stream);
+        let (mut data, request_framing) = read_request(&mut reader, max_len).await?;
+        *framing = request_framing;
+
+        let challenge: [u8; 32] = rand::random();
+        let mut nonce = None;
+        if let Some(response) = challenge_response(&data, &challenge) {
+            Self::response_to_client(stream, *framing, response).await?;
+            let (signed, signed_framing) = read_request(&mut reader, max_len).await?;
+            (data, *framing) = (signed, signed_framing);
+            nonce = Some(challenge);
+        }
+
+        let nonce = nonce.as_ref().map(|nonce| nonce.as_slice());
+        while Self::process_request(stream, *framing, &data, nonce, &cb).await? {
+            let next_request = read_from_ipc_stream(&mut reader, max_len);
+            (data, *framing) = match tokio::time::timeout(limits.idle_timeout, next_request).await {
+                Ok(Err(err)) if is_disconnection(&err) => return Ok(()),
+                Ok(request) => request?,
+                Err(_) => {
+                    log::trace!("Closing idle IPC connection");
+                    return Ok(());
+                }
+            };
+        }
+        Ok(())
+    }
+
+    /// Answers a single request, returns false if the connection should be closed
+    async fn process_request<F, R>(
+        stream: &AsyncDuplexPipeStream<Bytes>,
+        framing: Framing,
+        data: &[u8],
+        nonce: Option<&[u8]>,
+        cb: &Arc<F>,
+    ) -> Result<bool>
+    where
+        R: Future<Output = IpcResponse> + Send + Sync,
+        F: Fn(SvcAction) -> R + Send + Sync + 'static,
+    {
         if data.is_empty() {
-            return Self::response_to_client(stream, IpcResponse::Success).await;
+            Self::response_to_client(stream, framing, IpcResponse::Success).await?;
+            return Ok(true);
         }
 
-        let message: SvcMessage = bincode::decode_from_slice(&data, bincode::config::standard())?.0;
-        if !message.is_signature_valid() {
-            Self::response_to_client(
+        let header: SvcMessageHeader =
+            bincode::decode_from_slice(data, bincode::config::standard())?.0;
+        let message = match Self::decode_message(data, &header) {
+            Ok(message) => message,
+            Err(response) => {
+                Self::response_to_client(stream, framing, response).await?;
+                return Ok(true);
+            }
+        };
+        let id = message.id;
+        // older clients don't send ids
+        let request_id = id.map_or_else(|| "-".to_owned(), |id| id.to_string());
+        if !message.is_signature_valid(nonce) {
+            Self::identified_response_to_client(
                 stream,
+                framing,
+                id,
                 IpcResponse::Err("Unauthorized connection".to_owned()),
             )
             .await?;
-            return Ok(());
+            return Ok(false);
+        }
+
+        let client_id = stream.client_process_id()?;
+        if !SERVICE_RATE_LIMITER.check(client_id) {
+            log::warn!(
+                "IPC client {client_id} exceeded the rate limit, command {request_id} dropped"
+            );
+            Self::identified_response_to_client(
+                stream,
+                framing,
+                id,
+                IpcResponse::Err("429: Too many requests".to_owned()),
+            )
+            .await?;
+            return Ok(false);
+        }
+
+        if matches!(message.action, SvcAction::Subscribe) {
+            log::trace!("IPC client {client_id} subscribed to the service events");
+            Self::identified_response_to_client(stream, framing, id, IpcResponse::Success).await?;
+            Self::stream_events(stream, framing).await?;
+            return Ok(false);
+        }
+
+        log::trace!("IPC command {request_id} received: {:?}", message.action);
+        let action_name = message.action.name();
+        let span = tracing::info_span!(
+            "ipc_request",
+            request_id = request_id.as_str(),
+            action = action_name,
+            client_pid = client_id,
+            duration_ms = tracing::field::Empty,
+        );
+
+        let started_at = Instant::now();
+        let response = cb(message.action).instrument(span.clone()).await;
+        let duration_ms = started_at.elapsed().as_millis() as u64;
+        span.record("duration_ms", duration_ms);
+
+        tracing::info!(
+            parent: &span,
+            request_id = request_id.as_str(),
+            action = action_name,
+            client_pid = client_id,
+            duration_ms,
+            success = !matches!(response, IpcResponse::Err(_)),
+            "IPC request handled"
+        );
+        if let IpcResponse::Err(err) = &response {
+            log::warn!("IPC command {request_id} ({action_name}) failed: {err}");
+        }
+
+        Self::identified_response_to_client(stream, framing, id, response).await?;
+        Ok(true)
+    }
+
+    /// Messages of newer clients are answered with the returned response instead. Their frame
+    /// was fully read, so the connection can still be used.
+    fn decode_message(
+        data: &[u8],
+        header: &SvcMessageHeader,
+    ) -> std::result::Result<SvcMessage, IpcResponse> {
+        if header.version > SvcMessage::VERSION {
+            log::warn!(
+                "IPC message with unsupported protocol version {} received",
+                header.version
+            );
+            return Err(IpcResponse::UnsupportedVersion(SvcMessage::VERSION));
         }
+        // same protocol version but unknown action, sent by a newer build that didn't bump it
+        SvcMessage::decode(data, header).map_err(|err| {
+            log::warn!("IPC message with an unsupported action received: {err}");
+            IpcResponse::Err(format!("Unsupported action: {err}"))
+        })
+    }
 
-        log::trace!("IPC command received: {:?}", message.action);
-        Self::response_to_client(stream, cb(message.action).await).await?;
+    /// Queues the event for all the subscribed connections, stalled subscribers are dropped
+    pub fn emit(event: &SvcEvent) {
+        let payload = match serde_json::to_vec(event) {
+            Ok(payload) => payload,
+            Err(err) => {
+                log::error!("Failed to serialize service event: {err}");
+                return;
+            }
+        };
+
+        let mut subscribers = SUBSCRIBERS.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(
+            |subscriber| match subscriber.events.try_send(payload.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    subscriber.overflowed.store(true, Ordering::Relaxed);
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        );
+    }
+
+    /// Writes the emitted events to the connection until it is closed, the subscriber
+    /// stalls or the listener shuts down
+    async fn stream_events(stream: &AsyncDuplexPipeStream<Bytes>, framing: Framing) -> Result<()> {
+        let (events, mut queue) = tokio::sync::mpsc::channel(SUBSCRIBER_QUEUE_LEN);
+        let overflowed = Arc::new(AtomicBool::new(false));
+        SUBSCRIBERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Subscriber {
+                events,
+                overflowed: overflowed.clone(),
+            });
+
+        // subscribers don't send anything else, so the read only ends once the client is gone.
+        // Events are rare, a closed connection would otherwise keep its slot until the next one.
+        let disconnected = async {
+            let mut reader = stream;
+            let mut buf = [0u8; 64];
+            while matches!(reader.read(&mut buf).await, Ok(1..)) {}
+        };
+        tokio::pin!(disconnected);
+
+        loop {
+            let event = tokio::select! {
+                _ = &mut disconnected => {
+                    log::trace!("IPC event subscriber disconnected");
+                    break;
+                }
+                event = queue.recv() => event,
+            };
+            let Some(event) = event else {
+                break;
+            };
+            if overflowed.load(Ordering::Relaxed) {
+                log::warn!("IPC event subscriber stalled, closing its connection");
+                break;
+            }
+            Self::write_response(stream, framing, event).await?;
+        }
         Ok(())
     }
 
     async fn response_to_client(
         stream: &AsyncDuplexPipeStream<Bytes>,
+        framing: Framing,
         res: IpcResponse,
     ) -> Result<()> {
         let message = bincode::encode_to_vec(&res, bincode::config::standard())?;
-        write_to_ipc_stream(stream, &message).await
+        Self::write_response(stream, framing, message).await
     }
 
-    pub async fn send(message: SvcAction) -> Result<()> {
-        let stream = AsyncDuplexPipeStream::connect_by_path(Self::PATH).await?;
-        let data = bincode::encode_to_vec(
-            &SvcMessage {
-                token: SvcMessage::signature().to_string(),
-                action: message,
-            },
+    /// Echoes the id of the request, if it had one
+    async fn identified_response_to_client(
+        stream: &AsyncDuplexPipeStream<Bytes>,
+        framing: Framing,
+        id: Option<u64>,
+        response: IpcResponse,
+    ) -> Result<()> {
+        let Some(id) = id else {
+            return Self::response_to_client(stream, framing, response).await;
+        };
+        let message = bincode::encode_to_vec(
+            IdentifiedResponse { id, response },
             bincode::config::standard(),
         )?;
-        async_send_to_ipc_stream(&stream, &data).await?.ok()
+        Self::write_response(stream, framing, message).await
+    }
+
+    async fn write_response(
+        stream: &AsyncDuplexPipeStream<Bytes>,
+        framing: Framing,
+        message: Vec<u8>,
+    ) -> Result<()> {
+        tokio::time::timeout(
+            REQUEST_IO_TIMEOUT,
+            write_to_ipc_stream(stream, &message, framing),
+        )
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))?
+    }
+
+    pub async fn send(message: SvcAction) -> Result<()> {
+        Self::send_with_response(message).await.map(|_| ())
+    }
+
+    /// Sends the action on a new connection and returns the payload answered by the service,
+    /// empty if the action doesn't produce any output. Use `SvcChannel` for frequent requests.
+    pub async fn send_with_response(message: SvcAction) -> Result<String> {
+        SvcConnection::open().await?.send(message).await?.data()
+    }
+}
+
+/// Connection to the service that already went through the challenge
+struct SvcConnection {
+    /// the same reader is used for all the responses, a read can buffer the start of the
+    /// next frame, like events written one after another
+    reader: BufReader<AsyncDuplexPipeStream<Bytes>>,
+    /// format understood by the service, `Delimited` for services before the length prefix
+    framing: Framing,
+    /// none for services of the previous version, that expect the raw token
+    nonce: Option<Vec<u8>>,
+    /// highest protocol version of the service, none for services that don't tell it
+    protocol: Option<u32>,
+}
+
+impl SvcConnection {
+    async fn open() -> Result<Self> {
+        Self::open_at(ServiceIpc::PATH).await
+    }
+
+    async fn open_at(path: &str) -> Result<Self> {
+        if !DELIMITED_SERVICE.load(Ordering::Relaxed) {
+            match Self::negotiate(path, Framing::LengthPrefixed).await {
+                // services before the length prefix wait for a delimiter that never comes
+                Err(err) if is_timeout(&err) => {
+                    log::debug!("IPC service only reads delimited messages");
+                    DELIMITED_SERVICE.store(true, Ordering::Relaxed);
+                }
+                result => return result,
+            }
+        }
+        Self::negotiate(path, Framing::Delimited).await
+    }
+
+    /// Fails only if the service doesn't answer in time, older handshakes are tried otherwise
+    async fn negotiate(path: &str, framing: Framing) -> Result<Self> {
+        let mut reader = Self::connect(path).await?;
+        let request = [CHALLENGE_REQUEST, &SvcMessage::VERSION.to_le_bytes()[..]].concat();
+        match Self::handshake(&mut reader, &request, framing).await {
+            Ok(IpcResponse::Handshake { nonce, protocol }) => {
+                return Ok(Self {
+                    reader,
+                    framing,
+                    nonce: Some(nonce),
+                    protocol: Some(protocol),
+                });
+            }
+            Err(err) if is_timeout(&err) => return Err(err),
+            _ => {}
+        }
+
+        // services without negotiation reject the request and drop the connection
+        let mut reader = Self::connect(path).await?;
+        match Self::handshake(&mut reader, CHALLENGE_REQUEST, framing).await {
+            Ok(IpcResponse::Challenge(nonce)) => {
+                return Ok(Self {
+                    reader,
+                    framing,
+                    nonce: Some(nonce),
+                    protocol: None,
+                });
+            }
+            Err(err) if is_timeout(&err) => return Err(err),
+            _ => {}
+        }
+
+        // and the ones before them don't know the challenge either
+        Ok(Self {
+            reader: Self::connect(path).await?,
+            framing,
+            nonce: None,
+            protocol: None,
+        })
+    }
+
+    async fn handshake(
+        reader: &mut BufReader<AsyncDuplexPipeStream<Bytes>>,
+        request: &[u8],
+        framing: Framing,
+    ) -> Result<IpcResponse> {
+        let exchange = async_send_to_ipc_stream(reader, request, framing);
+        tokio::time::timeout(REQUEST_IO_TIMEOUT, exchange)
+            .await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))?
+    }
+
+    async fn connect(path: &str) -> Result<BufReader<AsyncDuplexPipeStream<Bytes>>> {
+        let stream = AsyncDuplexPipeStream::connect_by_path(path).await?;
+        Ok(BufReader::new(stream))
+    }
+
+    async fn send(&mut self, action: SvcAction) -> Result<IpcResponse> {
+        static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+        let id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+
+        let mut message = match &self.nonce {
+            Some(nonce) => SvcMessage::new_signed(action, nonce),
+            None => SvcMessage::new(action),
+        }
+        .with_id(id);
+        // older services reject the message as a whole if its version is newer than theirs
+        if let Some(protocol) = self.protocol {
+            message = message.for_protocol(protocol)?;
+        }
+        log::trace!("Sending IPC command {id}");
+        let data = bincode::encode_to_vec(message, bincode::config::standard())?;
+        write_to_ipc_stream(self.reader.get_ref(), &data, self.framing).await?;
+        let (buf, _) = read_from_ipc_stream(&mut self.reader, MAX_FRAME_LEN).await?;
+        Self::decode_response(&buf, id)
+    }
+
+    /// Services of the previous version and errors on the handshake are answered without id.
+    /// Both layouts can overlap, so a response is only taken as identified when its id matches.
+    fn decode_response(buf: &[u8], id: u64) -> Result<IpcResponse> {
+        let config = bincode::config::standard();
+        let identified = bincode::decode_from_slice::<IdentifiedResponse, _>(buf, config)
+            .ok()
+            .filter(|(_, read)| *read == buf.len())
+            .map(|(identified, _)| identified);
+        match identified {
+            Some(identified) if identified.id == id => Ok(identified.response),
+            other => match (Self::decode_whole(buf), other) {
+                (Ok(response), _) => Ok(response),
+                (Err(_), Some(identified)) => Err(Error::IpcResponseError(format!(
+                    "Response to command {} received while waiting for command {id}",
+                    identified.id
+                ))),
+                (Err(err), None) => Err(err.into()),
+            },
+        }
+    }
+
+    /// A shorter response can be the start of an identified one, so all the bytes must be read
+    fn decode_whole(buf: &[u8]) -> std::result::Result<IpcResponse, DecodeError> {
+        let (response, read) = bincode::decode_from_slice(buf, bincode::config::standard())?;
+        match read == buf.len() {
+            true => Ok(response),
+            false => Err(DecodeError::Other("trailing data after the response")),
+        }
+    }
+}
+
+/// Connection subscribed to the events of the service
+pub struct SvcEventStream {
+    connection: SvcConnection,
+}
+
+impl SvcEventStream {
+    pub async fn open() -> Result<Self> {
+        let mut connection = SvcConnection::open().await?;
+        connection.send(SvcAction::Subscribe).await?.ok()?;
+        Ok(Self { connection })
+    }
+
+    /// Waits for the next event, fails once the service closes the connection
+    pub async fn next(&mut self) -> Result<SvcEvent> {
+        let (buf, _) = read_from_ipc_stream(&mut self.connection.reader, MAX_FRAME_LEN).await?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+}
+
+/// Keeps a connection to the service open between requests, avoiding the connection and
+/// challenge round trips on frequent actions like window positioning. The connection is
+/// opened on the first request and again after the service closes it.
+#[derive(Default)]
+pub struct SvcChannel {
+    connection: tokio::sync::Mutex<Option<SvcConnection>>,
+}
+
+impl SvcChannel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends the action and returns the payload answered by the service. Requests are sent one
+    /// at a time, if the kept connection was closed the action is sent again on a new one.
+    pub async fn request(&self, action: SvcAction) -> Result<String> {
+        self.send(action).await?.data()
+    }
+
+    /// Sends the actions as a single `SvcAction::Batch`, returning the result of each action run
+    pub async fn request_batch(
+        &self,
+        actions: Vec<SvcAction>,
+        stop_on_error: bool,
+    ) -> Result<Vec<Result<String>>> {
+        let response = self
+            .send(SvcAction::Batch {
+                actions,
+                stop_on_error,
+            })
+            .await?;
+        Ok(response.batch_results())
+    }
+
+    async fn send(&self, action: SvcAction) -> Result<IpcResponse> {
+        let mut connection = self.connection.lock().await;
+        if let Some(current) = connection.as_mut() {
+            match current.send(action.clone()).await {
+                Ok(response) => return Ok(response),
+                // closed by the service after being idle, or restarted
+                Err(err) if is_disconnection(&err) => {
+                    log::trace!("Service connection closed, reconnecting");
+                }
+                // not sent, the service doesn't know the action
+                Err(err @ Error::UnsupportedVersion { .. }) => return Err(err),
+                Err(err) => {
+                    *connection = None;
+                    return Err(err);
+                }
+            }
+        }
+
+        *connection = None;
+        let mut reconnected = SvcConnection::open().await?;
+        let response = reconnected.send(action).await?;
+        *connection = Some(reconnected);
+        Ok(response)
     }
 }
 
@@ -155,24 +744,22 @@ impl AppIpc {
     where
         F: Fn(Vec<String>) -> IpcResponse + Send + Sync + 'static,
     {
-        let mut sd = SecurityDescriptor::new()?;
-        unsafe { sd.set_dacl(std::ptr::null_mut(), false)? };
-        sd.set_control(SE_DACL_PROTECTED, SE_DACL_PROTECTED)?;
-
-        let listener = PipeListenerOptions::new()
-            .path(Self::PATH)
-            .security_descriptor(Some(sd))
-            .create_tokio_duplex::<Bytes>()?;
+        let listener = create_listener(Self::PATH)?;
 
         tokio::spawn(async move {
             let callback = Arc::new(cb);
             while let Ok(stream) = listener.accept().await {
                 let callback = callback.clone();
                 tokio::spawn(async move {
-                    if let Err(err) = Self::process_connection(&stream, callback).await
-                        && let Err(send_err) =
-                            Self::response_to_client(&stream, IpcResponse::Err(err.to_string()))
-                                .await
+                    let mut framing = Framing::default();
+                    if let Err(err) =
+                        Self::process_connection(&stream, &mut framing, callback).await
+                        && let Err(send_err) = Self::response_to_client(
+                            &stream,
+                            framing,
+                            IpcResponse::Err(err.to_string()),
+                        )
+                        .await
                     {
                         log::error!(
                             "Failed to send error response: {send_err} || Original error: {err}"
@@ -184,61 +771,216 @@ impl AppIpc {
         Ok(())
     }
 
-    async fn process_connection<F>(stream: &AsyncDuplexPipeStream<Bytes>, cb: Arc<F>) -> Result<()>
+    /// `framing` is set to the format of the request, so the response uses the same one
+    async fn process_connection<F>(
+        stream: &AsyncDuplexPipeStream<Bytes>,
+        framing: &mut Framing,
+        cb: Arc<F>,
+    ) -> Result<()>
     where
         F: Fn(Vec<String>) -> IpcResponse,
     {
-        let data = read_from_ipc_stream(stream).await?;
+        let (data, request_framing) =
+            read_from_ipc_stream(&mut BufReader::new(stream), MAX_FRAME_LEN).await?;
+        *framing = request_framing;
         if data.is_empty() {
-            return Self::response_to_client(stream, IpcResponse::Success).await;
+            return Self::response_to_client(stream, *framing, IpcResponse::Success).await;
         }
 
         let message: Vec<String> =
             bincode::serde::decode_from_slice(&data, bincode::config::standard())?.0;
         log::trace!("IPC command received: {message:?}");
-        Self::response_to_client(stream, cb(message)).await?;
+        Self::response_to_client(stream, *framing, cb(message)).await?;
         Ok(())
     }
 
     async fn response_to_client(
         stream: &AsyncDuplexPipeStream<Bytes>,
+        framing: Framing,
         res: IpcResponse,
     ) -> Result<()> {
         let message = bincode::encode_to_vec(&res, bincode::config::standard())?;
-        write_to_ipc_stream(stream, &message).await
+        write_to_ipc_stream(stream, &message, framing).await
     }
 
     pub async fn send(message: Vec<String>) -> Result<()> {
+        Self::send_with_response(message).await.map(|_| ())
+    }
+
+    /// Spawns a thread that pings the app every `interval`, updating `AppIpc::last_seen` on success.
+    pub fn start_heartbeat(interval: Duration) {
+        std::thread::spawn(move || {
+            loop {
+                if Self::test_connection().is_ok() {
+                    *APP_LAST_SEEN.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+                }
+                std::thread::sleep(interval);
+            }
+        });
+    }
+
+    /// Last time the heartbeat reached the app, `None` if it was never reached.
+    pub fn last_seen() -> Option<Instant> {
+        *APP_LAST_SEEN.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The app is considered dead if no heartbeat was received for `2 * interval`
+    pub fn is_alive(heartbeat_interval: Duration) -> bool {
+        Self::last_seen().is_some_and(|seen| seen.elapsed() <= heartbeat_interval * 2)
+    }
+
+    /// Sends the message and returns the payload answered by the app
+    pub async fn send_with_response(message: Vec<String>) -> Result<String> {
         let stream = AsyncDuplexPipeStream::connect_by_path(Self::PATH).await?;
         let data = bincode::encode_to_vec(&message, bincode::config::standard())?;
-        async_send_to_ipc_stream(&stream, &data).await?.ok()
+        async_send_to_ipc_stream(&mut BufReader::new(stream), &data, Framing::LengthPrefixed)
+            .await?
+            .data()
+    }
+}
+
+/// Answer to `data` if it is a `CHALLENGE_REQUEST`. Clients that send their protocol version
+/// with it get the one of the service, the previous ones only know `IpcResponse::Challenge`.
+fn challenge_response(data: &[u8], nonce: &[u8]) -> Option<IpcResponse> {
+    let protocol = data.strip_prefix(CHALLENGE_REQUEST)?;
+    if protocol.is_empty() {
+        return Some(IpcResponse::Challenge(nonce.to_vec()));
+    }
+    let client_protocol = u32::from_le_bytes(protocol.try_into().ok()?);
+    log::trace!("IPC client connected with protocol version {client_protocol}");
+    Some(IpcResponse::Handshake {
+        nonce: nonce.to_vec(),
+        protocol: SvcMessage::VERSION,
+    })
+}
+
+/// Listener of the pipe with access restricted by `PIPE_SDDL`
+fn create_listener(path: &'static str) -> Result<PipeListener<Bytes, Bytes>> {
+    let sd = SecurityDescriptor::deserialize(PIPE_SDDL)?;
+    Ok(PipeListenerOptions::new()
+        .path(path)
+        .security_descriptor(Some(sd))
+        .create_tokio_duplex::<Bytes>()?)
+}
+
+/// Removes the delimiter of a message read with `read_until`, a message without it means
+/// the other side closed the connection before finishing the message.
+fn strip_end_of_transmission(mut buf: Vec<u8>) -> Result<Vec<u8>> {
+    if buf.pop() != Some(END_OF_TRANSMISSION_BLOCK) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "connection closed before the end of the message",
+        )
+        .into());
+    }
+    Ok(buf)
+}
+
+fn check_frame_len(len: usize, max: usize) -> Result<usize> {
+    if len > max {
+        return Err(Error::FrameTooLarge { len, max });
+    }
+    Ok(len)
+}
+
+/// Delimited messages are read up to `max` bytes plus the delimiter, so a peer that never
+/// sends it can't make us buffer an unbounded amount of data.
+fn check_delimited_len(buf: &[u8], max: usize) -> Result<()> {
+    if buf.last() != Some(&END_OF_TRANSMISSION_BLOCK) && buf.len() > max {
+        return Err(Error::FrameTooLarge {
+            len: buf.len(),
+            max,
+        });
+    }
+    Ok(())
+}
+
+fn encode_frame(buf: &[u8], framing: Framing) -> Result<Vec<u8>> {
+    let mut frame = Vec::with_capacity(buf.len() + 5);
+    match framing {
+        Framing::LengthPrefixed => {
+            let len = check_frame_len(buf.len(), MAX_FRAME_LEN)? as u32;
+            frame.push(FRAME_MARKER);
+            frame.extend_from_slice(&len.to_le_bytes());
+            frame.extend_from_slice(buf);
+        }
+        Framing::Delimited => {
+            frame.extend_from_slice(buf);
+            frame.push(END_OF_TRANSMISSION_BLOCK);
+        }
     }
+    Ok(frame)
+}
+
+/// The other end of the pipe was closed, like a client done or a service closing an idle connection
+fn is_disconnection(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::Io(err) if matches!(
+            err.kind(),
+            std::io::ErrorKind::UnexpectedEof
+                | std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::NotConnected
+        )
+    )
+}
+
+fn is_timeout(err: &Error) -> bool {
+    matches!(err, Error::Io(err) if err.kind() == std::io::ErrorKind::TimedOut)
 }
 
-async fn read_from_ipc_stream(stream: &AsyncDuplexPipeStream<Bytes>) -> Result<Vec<u8>> {
-    let mut reader = BufReader::new(stream);
+/// Reads a frame of a client, failing if it is not received in time
+async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_len: usize,
+) -> Result<(Vec<u8>, Framing)> {
+    tokio::time::timeout(REQUEST_IO_TIMEOUT, read_from_ipc_stream(reader, max_len))
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))?
+}
+
+/// Reads the next frame. The reader must be kept for the whole connection, anything
+/// buffered after the frame belongs to the next one.
+async fn read_from_ipc_stream<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_len: usize,
+) -> Result<(Vec<u8>, Framing)> {
+    if reader.fill_buf().await?.first() == Some(&FRAME_MARKER) {
+        reader.consume(1);
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len).await?;
+        let mut buf = vec![0u8; check_frame_len(u32::from_le_bytes(len) as usize, max_len)?];
+        reader.read_exact(&mut buf).await?;
+        return Ok((buf, Framing::LengthPrefixed));
+    }
+
     let mut buf = Vec::new();
     reader
+        .take(max_len as u64 + 1)
         .read_until(END_OF_TRANSMISSION_BLOCK, &mut buf)
         .await?;
-    buf.pop();
-    Ok(buf)
+    check_delimited_len(&buf, max_len)?;
+    Ok((strip_end_of_transmission(buf)?, Framing::Delimited))
 }
 
-async fn write_to_ipc_stream(stream: &AsyncDuplexPipeStream<Bytes>, buf: &[u8]) -> Result<()> {
+async fn write_to_ipc_stream(
+    stream: &AsyncDuplexPipeStream<Bytes>,
+    buf: &[u8],
+    framing: Framing,
+) -> Result<()> {
     let mut writter = BufWriter::new(stream);
-    writter.write_all(buf).await?;
-    writter.write_all(&[END_OF_TRANSMISSION_BLOCK]).await?;
+    writter.write_all(&encode_frame(buf, framing)?).await?;
     writter.flush().await?;
     Ok(())
 }
 
 async fn async_send_to_ipc_stream(
-    stream: &AsyncDuplexPipeStream<Bytes>,
+    reader: &mut BufReader<AsyncDuplexPipeStream<Bytes>>,
     buf: &[u8],
+    framing: Framing,
 ) -> Result<IpcResponse> {
-    write_to_ipc_stream(stream, buf).await?;
-    let buf = read_from_ipc_stream(stream).await?;
+    write_to_ipc_stream(reader.get_ref(), buf, framing).await?;
+    let (buf, _) = read_from_ipc_stream(reader, MAX_FRAME_LEN).await?;
     let response: IpcResponse = bincode::decode_from_slice(&buf, bincode::config::standard())?.0;
     Ok(response)
 }
@@ -246,15 +988,323 @@ async fn async_send_to_ipc_stream(
 /// blocking version to test connections without needed of tokio runtime
 fn send_to_ipc_stream(stream: &DuplexPipeStream<Bytes>, buf: &[u8]) -> Result<IpcResponse> {
     let mut writter = std::io::BufWriter::new(stream);
-    writter.write_all(buf)?;
-    writter.write_all(&[END_OF_TRANSMISSION_BLOCK])?;
+    writter.write_all(&encode_frame(buf, Framing::LengthPrefixed)?)?;
     writter.flush()?;
 
     let mut reader = std::io::BufReader::new(stream);
-    let mut buf = Vec::new();
-    reader.read_until(END_OF_TRANSMISSION_BLOCK, &mut buf)?;
-    buf.pop();
+    let is_length_prefixed = reader.fill_buf()?.first() == Some(&FRAME_MARKER);
+    let buf = match is_length_prefixed {
+        true => {
+            reader.consume(1);
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            let len = check_frame_len(u32::from_le_bytes(len) as usize, MAX_FRAME_LEN)?;
+            let mut buf = vec![0u8; len];
+            reader.read_exact(&mut buf)?;
+            buf
+        }
+        false => {
+            let mut buf = Vec::new();
+            (&mut reader)
+                .take(MAX_FRAME_LEN as u64 + 1)
+                .read_until(END_OF_TRANSMISSION_BLOCK, &mut buf)?;
+            check_delimited_len(&buf, MAX_FRAME_LEN)?;
+            strip_end_of_transmission(buf)?
+        }
+    };
 
     let response: IpcResponse = bincode::decode_from_slice(&buf, bincode::config::standard())?.0;
     Ok(response)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<T: bincode::Encode>(value: T) -> Vec<u8> {
+        bincode::encode_to_vec(value, bincode::config::standard()).unwrap()
+    }
+
+    #[test]
+    fn protocol_versions_are_exchanged_on_the_challenge() {
+        let nonce = [7u8; 32];
+        let request = [CHALLENGE_REQUEST, &3u32.to_le_bytes()[..]].concat();
+        let response = challenge_response(&request, &nonce);
+        assert!(matches!(
+            response,
+            Some(IpcResponse::Handshake { nonce: sent, protocol })
+                if sent == nonce && protocol == SvcMessage::VERSION
+        ));
+
+        // clients of the previous version
+        let response = challenge_response(CHALLENGE_REQUEST, &nonce);
+        assert!(matches!(response, Some(IpcResponse::Challenge(sent)) if sent == nonce));
+
+        let message = encode(SvcMessage::new(SvcAction::Stop));
+        assert!(challenge_response(&message, &nonce).is_none());
+        let malformed = [CHALLENGE_REQUEST, &b"v9"[..]].concat();
+        assert!(challenge_response(&malformed, &nonce).is_none());
+    }
+
+    fn decode_message(data: &[u8]) -> std::result::Result<SvcMessage, IpcResponse> {
+        let header = bincode::decode_from_slice(data, bincode::config::standard())
+            .unwrap()
+            .0;
+        ServiceIpc::decode_message(data, &header)
+    }
+
+    #[test]
+    fn messages_of_newer_clients_are_answered() {
+        let message = decode_message(&encode(SvcMessage::new(SvcAction::GetStatus)));
+        assert!(message.is_ok_and(|message| matches!(message.action, SvcAction::GetStatus)));
+
+        let mut newer = SvcMessage::new(SvcAction::GetStatus);
+        newer.version = SvcMessage::VERSION + 1;
+        let response = decode_message(&encode(newer)).unwrap_err();
+        assert!(matches!(
+            response,
+            IpcResponse::UnsupportedVersion(version) if version == SvcMessage::VERSION
+        ));
+
+        // same version but an action added without bumping it
+        let mut unknown = encode((SvcMessage::signature(), SvcMessage::VERSION, Some(1u64)));
+        unknown.extend([250, 1, 2, 3]);
+        let response = decode_message(&unknown).unwrap_err();
+        assert!(matches!(response, IpcResponse::Err(err) if err.starts_with("Unsupported action")));
+    }
+
+    #[tokio::test]
+    async fn restricted_pipes_accept_the_current_user() {
+        const PATH: &str = r"\\.\pipe\slu-ipc-test-restricted";
+        let listener = create_listener(PATH).unwrap();
+        let (server, client) = tokio::join!(
+            listener.accept(),
+            AsyncDuplexPipeStream::<Bytes>::connect_by_path(PATH)
+        );
+        let (server, _client) = (server.unwrap(), client.unwrap());
+        assert_eq!(server.client_process_id().unwrap(), std::process::id());
+    }
+
+    /// Answers like the services before the length prefix, one delimited message per connection
+    async fn serve_delimited_only(listener: PipeListener<Bytes, Bytes>) {
+        while let Ok(stream) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = Vec::new();
+                let mut reader = BufReader::new(&stream);
+                if reader
+                    .read_until(END_OF_TRANSMISSION_BLOCK, &mut buf)
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                let response = match buf.starts_with(CHALLENGE_REQUEST) {
+                    true => IpcResponse::Err("invalid message".to_owned()),
+                    false => IpcResponse::Success,
+                };
+                write_to_ipc_stream(&stream, &encode(response), Framing::Delimited)
+                    .await
+                    .unwrap();
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn services_reading_delimited_messages_are_reached() {
+        const PATH: &str = r"\\.\pipe\slu-ipc-test-delimited";
+        tokio::spawn(serve_delimited_only(create_listener(PATH).unwrap()));
+
+        let mut connection = SvcConnection::open_at(PATH).await.unwrap();
+        assert_eq!(connection.framing, Framing::Delimited);
+        assert_eq!(connection.nonce, None);
+        let response = connection.send(SvcAction::GetStatus).await;
+        assert!(matches!(response, Ok(IpcResponse::Success)));
+    }
+
+    #[tokio::test]
+    async fn disconnected_subscribers_release_their_connection() {
+        const PATH: &str = r"\\.\pipe\slu-ipc-test-subscriber";
+        let listener = create_listener(PATH).unwrap();
+        let (server, client) = tokio::join!(
+            listener.accept(),
+            AsyncDuplexPipeStream::<Bytes>::connect_by_path(PATH)
+        );
+        let (server, client) = (server.unwrap(), client.unwrap());
+
+        drop(client);
+        let streaming = ServiceIpc::stream_events(&server, Framing::LengthPrefixed);
+        let ended = tokio::time::timeout(Duration::from_secs(5), streaming).await;
+        assert!(ended.is_ok_and(|result| result.is_ok()));
+    }
+
+    #[test]
+    fn frames_are_prefixed_with_their_length() {
+        let frame = encode_frame(b"abc", Framing::LengthPrefixed).unwrap();
+        assert_eq!(frame, [FRAME_MARKER, 3, 0, 0, 0, b'a', b'b', b'c']);
+
+        let frame = encode_frame(b"abc", Framing::Delimited).unwrap();
+        assert_eq!(frame, [b'a', b'b', b'c', END_OF_TRANSMISSION_BLOCK]);
+    }
+
+    #[test]
+    fn oversized_frames_are_not_encoded() {
+        assert!(check_frame_len(MAX_FRAME_LEN, MAX_FRAME_LEN).is_ok());
+        let payload = vec![0u8; MAX_FRAME_LEN + 1];
+        let err = encode_frame(&payload, Framing::LengthPrefixed).unwrap_err();
+        let Error::FrameTooLarge { len, max } = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!((len, max), (MAX_FRAME_LEN + 1, MAX_FRAME_LEN));
+    }
+
+    #[tokio::test]
+    async fn oversized_frames_are_rejected_before_reading_them() {
+        let frame = encode_frame(&[0u8; 64], Framing::LengthPrefixed).unwrap();
+        let err = read_from_ipc_stream(&mut frame.as_slice(), 32)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::FrameTooLarge { len: 64, max: 32 }));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_clients_time_out() {
+        let (mut client, server) = tokio::io::duplex(64);
+        // the client stops in the middle of the frame, keeping the connection open
+        client
+            .write_all(&[FRAME_MARKER, 5, 0, 0, 0, b'a'])
+            .await
+            .unwrap();
+
+        let mut reader = BufReader::new(server);
+        let started_at = tokio::time::Instant::now();
+        let err = read_request(&mut reader, MAX_FRAME_LEN).await.unwrap_err();
+        assert!(matches!(&err, Error::Io(err) if err.kind() == std::io::ErrorKind::TimedOut));
+        assert!(started_at.elapsed() >= REQUEST_IO_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn delimited_reads_are_bounded() {
+        // a peer that never sends the delimiter is cut after the limit
+        let unterminated = vec![b'a'; 100];
+        let err = read_from_ipc_stream(&mut unterminated.as_slice(), 10)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::FrameTooLarge { len: 11, max: 10 }));
+
+        // the limit doesn't count the delimiter
+        let mut exact = vec![b'a'; 10];
+        exact.push(END_OF_TRANSMISSION_BLOCK);
+        let (buf, _) = read_from_ipc_stream(&mut exact.as_slice(), 10)
+            .await
+            .unwrap();
+        assert_eq!(buf.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn delimited_messages_must_be_complete() {
+        let mut complete = &b"message\x17"[..];
+        let (buf, framing) = read_from_ipc_stream(&mut complete, MAX_FRAME_LEN)
+            .await
+            .unwrap();
+        assert_eq!(buf, b"message");
+        assert_eq!(framing, Framing::Delimited);
+
+        // the peer closed the connection in the middle of the message
+        let mut truncated = &b"mess"[..];
+        let err = read_from_ipc_stream(&mut truncated, MAX_FRAME_LEN)
+            .await
+            .unwrap_err();
+        assert!(is_disconnection(&err));
+    }
+
+    #[test]
+    fn responses_are_matched_by_their_id() {
+        let buf = encode(IdentifiedResponse {
+            id: 7,
+            response: IpcResponse::Data("ok".to_owned()),
+        });
+        let response = SvcConnection::decode_response(&buf, 7).unwrap();
+        assert!(matches!(response, IpcResponse::Data(data) if data == "ok"));
+    }
+
+    #[test]
+    fn responses_without_id_are_accepted() {
+        // services of the previous version and errors on the handshake
+        for response in [
+            IpcResponse::Success,
+            IpcResponse::Data("ab".to_owned()),
+            IpcResponse::Err("Unauthorized connection".to_owned()),
+        ] {
+            let buf = encode(&response);
+            let decoded = SvcConnection::decode_response(&buf, 7).unwrap();
+            assert_eq!(encode(decoded), buf);
+        }
+    }
+
+    #[test]
+    fn responses_to_another_command_are_rejected() {
+        // also a valid `UnsupportedVersion(1)` prefix, that must not be taken as the response
+        let buf = encode(IdentifiedResponse {
+            id: 3,
+            response: IpcResponse::Data("late".to_owned()),
+        });
+        let err = SvcConnection::decode_response(&buf, 7).unwrap_err();
+        assert!(matches!(err, Error::IpcResponseError(msg) if msg.contains("command 3")));
+    }
+
+    #[tokio::test]
+    async fn buffered_frames_are_read_one_by_one() {
+        let mut data = encode_frame(b"first", Framing::LengthPrefixed).unwrap();
+        data.extend(encode_frame(b"second", Framing::Delimited).unwrap());
+        data.extend(encode_frame(b"third", Framing::LengthPrefixed).unwrap());
+
+        // the first read buffers all the frames, as when several are written at once
+        let mut reader = BufReader::new(data.as_slice());
+        for (expected, framing) in [
+            (b"first".as_slice(), Framing::LengthPrefixed),
+            (b"second".as_slice(), Framing::Delimited),
+            (b"third".as_slice(), Framing::LengthPrefixed),
+        ] {
+            let frame = read_from_ipc_stream(&mut reader, MAX_FRAME_LEN)
+                .await
+                .unwrap();
+            assert_eq!(frame, (expected.to_vec(), framing));
+        }
+        let end = read_from_ipc_stream(&mut reader, MAX_FRAME_LEN).await;
+        assert!(end.is_err_and(|err| is_disconnection(&err)));
+    }
+
+    #[tokio::test]
+    async fn events_written_together_are_all_received() {
+        let events = [
+            SvcEvent::TaskStatusChanged { enabled: true },
+            SvcEvent::ServiceStopping,
+        ];
+        // answer of the subscription followed by the events, read on the same connection
+        let subscribed = encode(IdentifiedResponse {
+            id: 1,
+            response: IpcResponse::Success,
+        });
+        let mut data = encode_frame(&subscribed, Framing::LengthPrefixed).unwrap();
+        for event in &events {
+            let json = serde_json::to_vec(event).unwrap();
+            data.extend(encode_frame(&json, Framing::LengthPrefixed).unwrap());
+        }
+
+        let mut reader = BufReader::new(data.as_slice());
+        let (buf, _) = read_from_ipc_stream(&mut reader, MAX_FRAME_LEN)
+            .await
+            .unwrap();
+        assert!(matches!(
+            SvcConnection::decode_response(&buf, 1),
+            Ok(IpcResponse::Success)
+        ));
+        for expected in &events {
+            let (buf, _) = read_from_ipc_stream(&mut reader, MAX_FRAME_LEN)
+                .await
+                .unwrap();
+            let event: SvcEvent = serde_json::from_slice(&buf).unwrap();
+            assert_eq!(&event, expected);
+        }
+    }
+}
@@ -1,11 +1,17 @@
 use std::collections::HashMap;
 
 use bincode::{Decode, Encode};
+use hmac::{Hmac, Mac};
 use seelen_core::rect::Rect;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 
 use crate::error::{Error, Result};
 
-/// Seelen UI Service Actions
+/// Seelen UI Service Actions.
+///
+/// The index of each variant is its tag on the wire, new actions must be added at the end
+/// so services of previous versions keep decoding the actions they know.
 #[allow(dead_code)]
 #[derive(Debug, Clone, Encode, Decode)]
 pub enum SvcAction {
@@ -38,35 +44,611 @@ pub enum SvcAction {
     SetForeground(isize),
     StartShortcutRegistration,
     StopShortcutRegistration,
+    /// Runs the actions in order answering with `IpcResponse::Batch`, positions are applied
+    /// at once if all the actions are window positions. Batches can't be nested.
+    Batch {
+        actions: Vec<SvcAction>,
+        /// skip the remaining actions after the first failure
+        stop_on_error: bool,
+    },
+    /// Answers with the path of the executable that owns the window
+    GetProcessPath {
+        hwnd: isize,
+    },
+    /// Answers with the `ServiceStatus` of the service as json
+    GetStatus,
+    /// Replaces the snap zones used by `SetWindowSnapZone`
+    RegisterSnapZones {
+        #[bincode(with_serde)]
+        zones: HashMap<u32, Rect>,
+        /// used to move the windows to the zones, 0 to place them without animation
+        animation_duration: u64,
+        easing: String,
+    },
+    /// Moves the window to a zone registered with `RegisterSnapZones` or `RegisterSnapZone`
+    SetWindowSnapZone {
+        hwnd: isize,
+        zone_id: u32,
+    },
+    /// Adds or replaces a single snap zone, kept on the service until it stops.
+    /// Rects are rescaled if the dpi of their monitor changes.
+    RegisterSnapZone {
+        zone_id: u32,
+        #[bincode(with_serde)]
+        rect: Rect,
+        monitor: isize,
+    },
+    /// Turns the connection into a stream of `SvcEvent`, no more actions are read from it
+    Subscribe,
+    /// Same as `SetWindowPosition` but the offsets of the rect from the top-left corner of the
+    /// monitor and its size are logical pixels, scaled by the service to the dpi of the monitor
+    SetWindowPositionOnMonitor {
+        hwnd: isize,
+        #[bincode(with_serde)]
+        rect: Rect,
+        flags: u32,
+        monitor: isize,
+    },
+}
+
+impl SvcAction {
+    /// Names of all the actions, as returned by `name`
+    pub const NAMES: &[&str] = &[
+        "Stop",
+        "SetStartup",
+        "SetShortcutsConfig",
+        "ShowWindow",
+        "ShowWindowAsync",
+        "SetWindowPosition",
+        "DeferWindowPositions",
+        "SetForeground",
+        "StartShortcutRegistration",
+        "StopShortcutRegistration",
+        "Batch",
+        "GetProcessPath",
+        "GetStatus",
+        "RegisterSnapZones",
+        "SetWindowSnapZone",
+        "RegisterSnapZone",
+        "Subscribe",
+        "SetWindowPositionOnMonitor",
+    ];
+
+    /// Name of the action, used on logs to avoid dumping the whole payload
+    pub fn name(&self) -> &'static str {
+        match self {
+            SvcAction::Stop => "Stop",
+            SvcAction::SetStartup(_) => "SetStartup",
+            SvcAction::SetShortcutsConfig(_) => "SetShortcutsConfig",
+            SvcAction::ShowWindow { .. } => "ShowWindow",
+            SvcAction::ShowWindowAsync { .. } => "ShowWindowAsync",
+            SvcAction::SetWindowPosition { .. } => "SetWindowPosition",
+            SvcAction::DeferWindowPositions { .. } => "DeferWindowPositions",
+            SvcAction::SetForeground(_) => "SetForeground",
+            SvcAction::StartShortcutRegistration => "StartShortcutRegistration",
+            SvcAction::StopShortcutRegistration => "StopShortcutRegistration",
+            SvcAction::Batch { .. } => "Batch",
+            SvcAction::GetProcessPath { .. } => "GetProcessPath",
+            SvcAction::GetStatus => "GetStatus",
+            SvcAction::RegisterSnapZones { .. } => "RegisterSnapZones",
+            SvcAction::SetWindowSnapZone { .. } => "SetWindowSnapZone",
+            SvcAction::RegisterSnapZone { .. } => "RegisterSnapZone",
+            SvcAction::Subscribe => "Subscribe",
+            SvcAction::SetWindowPositionOnMonitor { .. } => "SetWindowPositionOnMonitor",
+        }
+    }
+
+    /// Protocol version that introduced the action, older services can't decode it
+    pub fn min_version(&self) -> u32 {
+        match self {
+            SvcAction::Batch { actions, .. } => {
+                actions.iter().map(SvcAction::min_version).fold(2, u32::max)
+            }
+            SvcAction::GetProcessPath { .. } => 4,
+            SvcAction::GetStatus => 5,
+            SvcAction::RegisterSnapZones { .. } | SvcAction::SetWindowSnapZone { .. } => 6,
+            SvcAction::RegisterSnapZone { .. } => 7,
+            SvcAction::Subscribe => 8,
+            SvcAction::SetWindowPositionOnMonitor { .. } => 9,
+            _ => 1,
+        }
+    }
+}
+
+/// Pushed by the service to the connections subscribed with `SvcAction::Subscribe`, as json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SvcEvent {
+    /// the task that runs the service on logon was enabled or disabled
+    TaskStatusChanged { enabled: bool },
+    /// last event sent before the service exits
+    ServiceStopping,
+    /// an action that keeps running after being answered finished, like animated placements
+    ActionCompleted {
+        action: String,
+        error: Option<String>,
+    },
+}
+
+/// Answer of `SvcAction::GetStatus`, sent as json so fields can be added without a version bump
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceStatus {
+    /// semver of the service build
+    pub version: String,
+    /// commit of the service build, none if it was built outside of a git checkout
+    pub git_hash: Option<String>,
+    /// unix timestamp in seconds of the start of the service process
+    pub started_at: u64,
+    pub uptime_secs: u64,
+    /// actions handled by the service, see `SvcAction::NAMES`
+    pub actions: Vec<String>,
+}
+
+impl ServiceStatus {
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// False for services of another build, usually left running by an in-place update.
+    /// The commits are only compared if both builds know theirs.
+    pub fn is_same_build(&self, version: &str, git_hash: Option<&str>) -> bool {
+        self.version == version
+            && match (self.git_hash.as_deref(), git_hash) {
+                (Some(service_hash), Some(hash)) => service_hash == hash,
+                _ => true,
+            }
+    }
 }
 
 #[derive(Debug, Clone, Encode, Decode)]
 pub struct SvcMessage {
     pub token: String,
+    /// protocol version of the client, should be encoded before the action
+    /// so it can be read even if the action is unknown by the service.
+    pub version: u32,
+    /// set by the client to correlate the logs of the service, echoed with `IdentifiedResponse`
+    pub id: Option<u64>,
     pub action: SvcAction,
 }
 
+/// Leading fields of `SvcMessage`, decoded first to check the protocol version.
+#[derive(Debug, Clone, Decode)]
+pub struct SvcMessageHeader {
+    pub token: String,
+    pub version: u32,
+}
+
+/// Layout of `SvcMessage` before the request ids, sent by clients up to version 2
+#[derive(Debug, Clone, Decode)]
+struct LegacySvcMessage {
+    token: String,
+    version: u32,
+    action: SvcAction,
+}
+
+/// Response to a message with an id, messages without it are answered with a bare `IpcResponse`
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct IdentifiedResponse {
+    pub id: u64,
+    pub response: IpcResponse,
+}
+
+/// Tokens signed with the challenge of the connection start with it,
+/// the rest are the raw secret sent by clients of the previous version.
+const HMAC_TOKEN_PREFIX: &str = "hmac-sha256:";
+
+fn connection_mac(nonce: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SvcMessage::signature().as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(nonce);
+    mac
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
 impl SvcMessage {
+    /// Protocol version understood by this build, bump it on any change to `SvcAction`
+    /// and register the new actions on `SvcAction::min_version`
+    pub const VERSION: u32 = 9;
+    /// first version whose messages carry an id
+    const FIRST_VERSION_WITH_ID: u32 = 3;
+
+    /// Message carrying the raw secret, only for services that don't send a challenge
+    pub fn new(action: SvcAction) -> Self {
+        Self {
+            token: Self::signature().to_string(),
+            version: Self::VERSION,
+            id: None,
+            action,
+        }
+    }
+
+    /// Message signed with the challenge sent by the service, so the secret never crosses
+    /// the pipe and the message is useless on any other connection.
+    pub fn new_signed(action: SvcAction, nonce: &[u8]) -> Self {
+        let tag = connection_mac(nonce).finalize().into_bytes();
+        Self {
+            token: format!("{HMAC_TOKEN_PREFIX}{}", hex::encode(tag)),
+            version: Self::VERSION,
+            id: None,
+            action,
+        }
+    }
+
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Sends the message as `protocol`, the version negotiated with an older service that
+    /// rejects newer messages. Fails if the action is not known by that version.
+    pub fn for_protocol(mut self, protocol: u32) -> Result<Self> {
+        let required = self.action.min_version();
+        if required > protocol {
+            return Err(Error::UnsupportedVersion {
+                requested: required,
+                supported: protocol,
+            });
+        }
+        self.version = self.version.min(protocol);
+        Ok(self)
+    }
+
+    /// Decodes a message of any supported version, `header` being its already decoded header
+    pub fn decode(data: &[u8], header: &SvcMessageHeader) -> Result<Self> {
+        let config = bincode::config::standard();
+        if header.version >= Self::FIRST_VERSION_WITH_ID {
+            return Ok(bincode::decode_from_slice(data, config)?.0);
+        }
+        let legacy: LegacySvcMessage = bincode::decode_from_slice(data, config)?.0;
+        Ok(Self {
+            token: legacy.token,
+            version: legacy.version,
+            id: None,
+            action: legacy.action,
+        })
+    }
+
     pub fn signature() -> &'static str {
         std::env!("SLU_SERVICE_CONNECTION_TOKEN")
     }
 
-    pub fn is_signature_valid(&self) -> bool {
-        self.token == SvcMessage::signature()
+    /// `nonce` is the challenge sent on this connection, if any. Comparisons are constant time.
+    pub fn is_signature_valid(&self, nonce: Option<&[u8]>) -> bool {
+        match (self.token.strip_prefix(HMAC_TOKEN_PREFIX), nonce) {
+            (Some(tag), Some(nonce)) => {
+                hex::decode(tag).is_ok_and(|tag| connection_mac(nonce).verify_slice(&tag).is_ok())
+            }
+            // signed for another connection
+            (Some(_), None) => false,
+            (None, _) => {
+                let is_valid =
+                    constant_time_eq(self.token.as_bytes(), SvcMessage::signature().as_bytes());
+                if is_valid {
+                    log::warn!("IPC client sent the raw connection token, this is deprecated");
+                }
+                is_valid
+            }
+        }
     }
 }
 
 #[derive(Debug, Clone, Encode, Decode)]
 pub enum IpcResponse {
     Success,
+    /// success with a payload for the client
+    Data(String),
     Err(String),
+    /// the message was sent by a newer client, contains the version supported by the server
+    UnsupportedVersion(u32),
+    /// nonce to sign the message of this connection with, answer to `CHALLENGE_REQUEST`
+    Challenge(Vec<u8>),
+    /// response of each action of `SvcAction::Batch` by index, shorter than the batch
+    /// if it stopped on an error
+    Batch(Vec<IpcResponse>),
+    /// answer to a `CHALLENGE_REQUEST` sent with the protocol version of the client,
+    /// `protocol` being the highest version supported by the service
+    Handshake {
+        nonce: Vec<u8>,
+        protocol: u32,
+    },
 }
 
 impl IpcResponse {
     pub fn ok(self) -> Result<()> {
+        self.data().map(|_| ())
+    }
+
+    /// returns the payload of the response, empty on `Success`
+    pub fn data(self) -> Result<String> {
         match self {
-            IpcResponse::Success => Ok(()),
+            IpcResponse::Success => Ok(String::new()),
+            IpcResponse::Data(data) => Ok(data),
             IpcResponse::Err(err) => Err(Error::IpcResponseError(err)),
+            IpcResponse::UnsupportedVersion(supported) => Err(Error::UnsupportedVersion {
+                requested: SvcMessage::VERSION,
+                supported,
+            }),
+            IpcResponse::Challenge(_) | IpcResponse::Handshake { .. } => Err(
+                Error::IpcResponseError("Unexpected challenge response".to_owned()),
+            ),
+            // use `batch_results` to know the result of each action
+            IpcResponse::Batch(responses) => {
+                for (index, res) in responses.into_iter().enumerate() {
+                    if let Err(err) = res.ok() {
+                        return Err(Error::IpcResponseError(format!(
+                            "Action {index} of the batch failed: {err}"
+                        )));
+                    }
+                }
+                Ok(String::new())
+            }
+        }
+    }
+
+    /// Result of each action of a batch, a single result for other responses
+    pub fn batch_results(self) -> Vec<Result<String>> {
+        match self {
+            IpcResponse::Batch(responses) => responses.into_iter().map(IpcResponse::data).collect(),
+            other => vec![other.data()],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<T: Encode>(value: T) -> Vec<u8> {
+        bincode::encode_to_vec(value, bincode::config::standard()).unwrap()
+    }
+
+    fn decode_message(data: &[u8]) -> Result<SvcMessage> {
+        let header: SvcMessageHeader =
+            bincode::decode_from_slice(data, bincode::config::standard())?.0;
+        SvcMessage::decode(data, &header)
+    }
+
+    #[test]
+    fn messages_are_decoded_with_their_id() {
+        let data = encode(SvcMessage::new(SvcAction::SetForeground(42)).with_id(5));
+        let message = decode_message(&data).unwrap();
+        assert_eq!(
+            (message.version, message.id),
+            (SvcMessage::VERSION, Some(5))
+        );
+        assert!(matches!(message.action, SvcAction::SetForeground(42)));
+    }
+
+    #[test]
+    fn messages_of_clients_without_ids_are_decoded() {
+        // layout of the clients up to version 2
+        let data = encode((SvcMessage::signature(), 2u32, SvcAction::SetForeground(42)));
+        let message = decode_message(&data).unwrap();
+        assert_eq!((message.version, message.id), (2, None));
+        assert!(matches!(message.action, SvcAction::SetForeground(42)));
+    }
+
+    #[test]
+    fn unknown_actions_are_told_apart_from_unknown_versions() {
+        // message of a newer client with an action this build doesn't know
+        let mut data = encode((SvcMessage::signature(), SvcMessage::VERSION, Some(1u64)));
+        data.extend([250, 1, 2, 3]);
+
+        let header: SvcMessageHeader =
+            bincode::decode_from_slice(&data, bincode::config::standard())
+                .unwrap()
+                .0;
+        assert_eq!(header.version, SvcMessage::VERSION);
+        assert!(SvcMessage::decode(&data, &header).is_err());
+    }
+
+    #[test]
+    fn messages_are_downgraded_to_the_service_protocol() {
+        let message = SvcMessage::new(SvcAction::GetStatus)
+            .for_protocol(5)
+            .unwrap();
+        assert_eq!(message.version, 5);
+        // newer services get the version of the client
+        let message = SvcMessage::new(SvcAction::Stop)
+            .for_protocol(u32::MAX)
+            .unwrap();
+        assert_eq!(message.version, SvcMessage::VERSION);
+
+        let err = SvcMessage::new(SvcAction::Subscribe)
+            .for_protocol(7)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UnsupportedVersion {
+                requested: 8,
+                supported: 7
+            }
+        ));
+    }
+
+    #[test]
+    fn batches_require_the_version_of_their_newest_action() {
+        let batch = |actions| SvcAction::Batch {
+            actions,
+            stop_on_error: false,
+        };
+        assert_eq!(batch(vec![]).min_version(), 2);
+        assert_eq!(batch(vec![SvcAction::Stop]).min_version(), 2);
+        assert_eq!(
+            batch(vec![SvcAction::Stop, SvcAction::GetStatus]).min_version(),
+            5
+        );
+    }
+
+    #[test]
+    fn messages_signed_for_the_connection_are_valid() {
+        let nonce = [7u8; 32];
+        let message = SvcMessage::new_signed(SvcAction::Stop, &nonce);
+        assert!(message.token.starts_with(HMAC_TOKEN_PREFIX));
+        assert!(message.is_signature_valid(Some(&nonce)));
+        // replayed on another connection
+        assert!(!message.is_signature_valid(Some(&[8u8; 32])));
+        assert!(!message.is_signature_valid(None));
+    }
+
+    #[test]
+    fn tampered_tokens_are_invalid() {
+        let nonce = [7u8; 32];
+        let mut message = SvcMessage::new_signed(SvcAction::Stop, &nonce);
+        let last = message.token.pop().unwrap();
+        message.token.push(if last == '0' { '1' } else { '0' });
+        assert!(!message.is_signature_valid(Some(&nonce)));
+
+        message.token = format!("{HMAC_TOKEN_PREFIX}not-hex");
+        assert!(!message.is_signature_valid(Some(&nonce)));
+    }
+
+    #[test]
+    fn raw_tokens_are_still_accepted() {
+        // clients of the previous version, with or without a challenge sent
+        let message = SvcMessage::new(SvcAction::Stop);
+        assert!(message.is_signature_valid(None));
+        assert!(message.is_signature_valid(Some(&[7u8; 32])));
+
+        let mut wrong = SvcMessage::new(SvcAction::Stop);
+        wrong.token.push('x');
+        assert!(!wrong.is_signature_valid(None));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(constant_time_eq(b"abc", b"abc"));
+    }
+
+    fn status(version: &str, git_hash: Option<&str>) -> ServiceStatus {
+        ServiceStatus {
+            version: version.to_owned(),
+            git_hash: git_hash.map(str::to_owned),
+            started_at: 1_700_000_000,
+            uptime_secs: 60,
+            actions: vec!["Stop".to_owned(), "GetStatus".to_owned()],
+        }
+    }
+
+    #[test]
+    fn status_is_parsed_from_json() {
+        let expected = status("2.3.0", Some("abc123"));
+        let json = serde_json::to_string(&expected).unwrap();
+        assert_eq!(ServiceStatus::from_json(&json).unwrap(), expected);
+        assert!(ServiceStatus::from_json("{\"version\": 2}").is_err());
+    }
+
+    #[test]
+    fn builds_are_compared_by_version_and_commit() {
+        let service = status("2.3.0", Some("abc123"));
+        assert!(service.is_same_build("2.3.0", Some("abc123")));
+        assert!(!service.is_same_build("2.3.1", Some("abc123")));
+        assert!(!service.is_same_build("2.3.0", Some("def456")));
+        // builds outside of a git checkout only compare the version
+        assert!(service.is_same_build("2.3.0", None));
+        assert!(status("2.3.0", None).is_same_build("2.3.0", Some("abc123")));
+        assert!(!status("2.3.0", None).is_same_build("2.4.0", None));
+    }
+
+    /// Tag of each action on the wire, these can never change
+    #[test]
+    fn action_tags_are_stable() {
+        let rect = Rect::default();
+        let actions = [
+            SvcAction::Stop,
+            SvcAction::SetStartup(true),
+            SvcAction::SetShortcutsConfig(String::new()),
+            SvcAction::ShowWindow {
+                hwnd: 1,
+                command: 0,
+            },
+            SvcAction::ShowWindowAsync {
+                hwnd: 1,
+                command: 0,
+            },
+            SvcAction::SetWindowPosition {
+                hwnd: 1,
+                rect: rect.clone(),
+                flags: 0,
+            },
+            SvcAction::DeferWindowPositions {
+                list: HashMap::new(),
+                animated: false,
+                animation_duration: 0,
+                easing: String::new(),
+            },
+            SvcAction::SetForeground(1),
+            SvcAction::StartShortcutRegistration,
+            SvcAction::StopShortcutRegistration,
+            SvcAction::Batch {
+                actions: vec![],
+                stop_on_error: false,
+            },
+            SvcAction::GetProcessPath { hwnd: 1 },
+            SvcAction::GetStatus,
+            SvcAction::RegisterSnapZones {
+                zones: HashMap::new(),
+                animation_duration: 0,
+                easing: String::new(),
+            },
+            SvcAction::SetWindowSnapZone {
+                hwnd: 1,
+                zone_id: 0,
+            },
+            SvcAction::RegisterSnapZone {
+                zone_id: 0,
+                rect: rect.clone(),
+                monitor: 1,
+            },
+            SvcAction::Subscribe,
+            SvcAction::SetWindowPositionOnMonitor {
+                hwnd: 1,
+                rect,
+                flags: 0,
+                monitor: 1,
+            },
+        ];
+        assert_eq!(actions.len(), SvcAction::NAMES.len());
+        for (tag, action) in actions.iter().enumerate() {
+            assert_eq!(encode(action)[0] as usize, tag, "{}", action.name());
+            assert_eq!(SvcAction::NAMES[tag], action.name());
+        }
+    }
+
+    #[test]
+    fn responses_are_turned_into_results() {
+        assert_eq!(IpcResponse::Success.data().unwrap(), "");
+        assert_eq!(IpcResponse::Data("path".to_owned()).data().unwrap(), "path");
+        assert!(matches!(
+            IpcResponse::Err("failed".to_owned()).data(),
+            Err(Error::IpcResponseError(err)) if err == "failed"
+        ));
+        assert!(matches!(
+            IpcResponse::UnsupportedVersion(4).data(),
+            Err(Error::UnsupportedVersion { supported: 4, .. })
+        ));
+        assert!(IpcResponse::Challenge(vec![1]).ok().is_err());
+    }
+
+    #[test]
+    fn batches_report_each_action() {
+        let batch = || {
+            IpcResponse::Batch(vec![
+                IpcResponse::Success,
+                IpcResponse::Err("no window".to_owned()),
+                IpcResponse::Data("path".to_owned()),
+            ])
+        };
+        let err = batch().data().unwrap_err();
+        assert!(err.to_string().contains("Action 1 of the batch failed"));
+
+        let results = batch().batch_results();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].as_ref().is_ok_and(|data| data.is_empty()));
+        assert!(results[1].is_err());
+        assert!(results[2].as_ref().is_ok_and(|data| data == "path"));
+
+        // other responses are a single result
+        assert_eq!(IpcResponse::Success.batch_results().len(), 1);
+    }
+}
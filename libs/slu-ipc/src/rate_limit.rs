@@ -0,0 +1,59 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Max sustained commands per second allowed for a single client process.
+pub const RATE_LIMIT_PER_SECOND: f64 = 100.0;
+/// Max commands that can be sent at once after the client was idle.
+pub const RATE_LIMIT_BURST: f64 = 20.0;
+
+/// Buckets idle for longer than this are dropped to avoid growing forever.
+const IDLE_BUCKET_LIFETIME: Duration = Duration::from_secs(60);
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new() -> Self {
+        Self {
+            tokens: RATE_LIMIT_BURST,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * RATE_LIMIT_PER_SECOND).min(RATE_LIMIT_BURST);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Token-bucket rate limiter keyed by the client process id.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<u32, TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// returns false if the client exceeded the limits
+    pub fn check(&self, client_id: u32) -> bool {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.retain(|_, bucket| bucket.last_refill.elapsed() < IDLE_BUCKET_LIFETIME);
+        buckets
+            .entry(client_id)
+            .or_insert_with(TokenBucket::new)
+            .try_acquire()
+    }
+}
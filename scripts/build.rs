@@ -1,3 +1,13 @@
 fn main() {
+    // reported by the service status, to tell apart builds of the same version
+    let git_hash = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success());
+    if let Some(output) = git_hash {
+        let hash = String::from_utf8_lossy(&output.stdout);
+        println!("cargo:rustc-env=SLU_GIT_HASH={}", hash.trim());
+    }
     tauri_build::build();
 }
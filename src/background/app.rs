@@ -1,4 +1,7 @@
-use std::sync::{atomic::AtomicBool, Arc, LazyLock};
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc, LazyLock},
+};
 
 use getset::{Getters, MutGetters};
 use parking_lot::Mutex;
@@ -15,13 +18,18 @@ use crate::{
     log_error,
     modules::{
         monitors::{MonitorManager, MonitorManagerEvent},
+        start::application::StartMenuManager,
         system_settings::application::{SystemSettings, SystemSettingsEvent},
     },
     restoration_and_migrations::RestorationAndMigration,
-    state::application::{FullState, FULL_STATE},
+    state::application::{is_garbage_collection_due, FullState, IconPacksManager, FULL_STATE},
     system::{declare_system_events_handlers, release_system_events_handlers},
     trace_lock,
-    utils::discord::start_discord_rpc,
+    utils::{
+        constants::SEELEN_COMMON,
+        discord::start_discord_rpc,
+        icon_extractor::{resume_icons_refresh, warm_running_apps_icons, warm_uwp_icons_cache},
+    },
     virtual_desktops::get_vd_manager,
     widgets::{
         launcher::SeelenRofi,
@@ -150,12 +158,48 @@ impl Seelen {
         }
     }
 
+    /// exists while the app is running, so finding it on startup means the last session crashed
+    fn running_marker_path() -> PathBuf {
+        SEELEN_COMMON.app_cache_dir().join("running.marker")
+    }
+
+    fn repair_after_unclean_shutdown(state: &FullState) {
+        if !Self::running_marker_path().exists() {
+            return;
+        }
+        log::warn!("Previous session was not closed properly, checking system icon pack");
+        match trace_lock!(state.icon_packs()).quick_repair_icon_pack() {
+            Ok(report) if !report.issues.is_empty() => log::info!(
+                "System icon pack repaired, {} broken entries removed",
+                report.removed_entries
+            ),
+            Ok(_) => {}
+            Err(err) => log::error!("Failed to repair system icon pack: {err:?}"),
+        }
+    }
+
+    /// Runs on a background thread, it reads the whole system icons folder
+    fn collect_icons_garbage_if_due(state: &FullState) {
+        if !is_garbage_collection_due(state.icon_extraction.garbage_collection_interval_days) {
+            return;
+        }
+        let mutex = state.icon_packs().clone();
+        std::thread::spawn(move || match IconPacksManager::collect_garbage(&mutex) {
+            Ok(deleted) => log::info!("Icons garbage collected, {deleted} files deleted"),
+            Err(err) => log::error!("Failed to collect icons garbage: {err:?}"),
+        });
+    }
+
     pub fn start(&mut self) -> Result<()> {
         RestorationAndMigration::run_full()?;
 
         let state = FULL_STATE.load();
         rust_i18n::set_locale(state.locale());
 
+        Self::repair_after_unclean_shutdown(&state);
+        Self::collect_icons_garbage_if_due(&state);
+        log_error!(std::fs::write(Self::running_marker_path(), []));
+
         // order is important
         create_background_window()?;
         declare_system_events_handlers()?;
@@ -193,6 +237,10 @@ impl Seelen {
 
         register_win_hook()?;
         start_discord_rpc()?;
+        log_error!(StartMenuManager::start_listeners());
+        warm_uwp_icons_cache();
+        warm_running_apps_icons();
+        log_error!(resume_icons_refresh());
 
         if state.are_shortcuts_enabled() {
             ServicePipe::request(SvcAction::SetShortcutsConfig(serde_json::to_string(
@@ -217,6 +265,8 @@ impl Seelen {
     pub fn stop(&self) {
         SEELEN_IS_RUNNING.store(false, std::sync::atomic::Ordering::SeqCst);
         release_system_events_handlers();
+        log_error!(trace_lock!(FULL_STATE.load().icon_packs()).flush_system_icon_pack());
+        log_error!(std::fs::remove_file(Self::running_marker_path()));
     }
 
     fn add_monitor(&mut self, view: MonitorView) -> Result<()> {
@@ -6,4 +6,4 @@ pub mod application;
 
 pub use infrastructure::*;
 pub use self_pipe::SelfPipe;
-pub use svc_pipe::ServicePipe;
+pub use svc_pipe::{ServiceEvents, ServicePipe};
@@ -1,6 +1,9 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::LazyLock, time::Duration};
 
-use slu_ipc::{messages::SvcAction, ServiceIpc, IPC};
+use slu_ipc::{
+    messages::{ServiceStatus, SvcAction, SvcEvent},
+    ServiceIpc, SvcChannel, SvcEventStream, IPC,
+};
 use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 use windows::Win32::{
     System::TaskScheduler::{IExecAction2, ITaskService, TaskScheduler},
@@ -11,24 +14,139 @@ use windows_core::Interface;
 use crate::{
     app::get_app_handle,
     error::Result,
-    get_tokio_handle,
+    event_manager, get_tokio_handle,
     utils::{pwsh::PwshScript, was_installed_using_msix},
     windows_api::{Com, WindowsApi},
 };
 
+/// connection to the service shared by all the requests of the app
+static SERVICE_CHANNEL: LazyLock<SvcChannel> = LazyLock::new(SvcChannel::new);
+
+/// the service could be restarting or of a version without events
+const EVENTS_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(10);
+
 pub struct ServicePipe;
 
+/// Events pushed by the service
+pub struct ServiceEvents;
+
+event_manager!(ServiceEvents, SvcEvent);
+
+impl ServiceEvents {
+    /// Subscribes to the service events forwarding them to the subscribers of `ServiceEvents`,
+    /// the subscription is renewed each time the service closes it.
+    pub fn start_listener() {
+        get_tokio_handle().spawn(async {
+            loop {
+                match SvcEventStream::open().await {
+                    Ok(mut stream) => {
+                        while let Ok(event) = stream.next().await {
+                            log::debug!("Service event received: {event:?}");
+                            Self::send(event);
+                        }
+                    }
+                    Err(err) => log::debug!("Can not subscribe to the service events: {err}"),
+                }
+                tokio::time::sleep(EVENTS_RESUBSCRIBE_DELAY).await;
+            }
+        });
+    }
+}
+
 impl ServicePipe {
     /// will ignore any response
     pub fn request(message: SvcAction) -> Result<()> {
         get_tokio_handle().spawn(async move {
-            if let Err(err) = ServiceIpc::send(message.clone()).await {
-                log::error!("Error sending message to service {err}. Message: {message:?}");
+            match SERVICE_CHANNEL.request(message.clone()).await {
+                Ok(_) => {}
+                // the service was not updated yet, e.g. an app update pending of a restart
+                Err(err @ slu_ipc::error::Error::UnsupportedVersion { .. }) => {
+                    log::warn!("Service can't handle {}: {err}", message.name());
+                }
+                Err(err) => {
+                    log::error!("Error sending message to service {err}. Message: {message:?}");
+                }
             }
         });
         Ok(())
     }
 
+    /// Sends the action waiting for the service to handle it
+    pub async fn send(message: SvcAction) -> Result<()> {
+        SERVICE_CHANNEL.request(message).await?;
+        Ok(())
+    }
+
+    pub async fn query_status() -> slu_ipc::error::Result<ServiceStatus> {
+        let json = SERVICE_CHANNEL.request(SvcAction::GetStatus).await?;
+        ServiceStatus::from_json(&json)
+    }
+
+    /// Services of another build are left running by in-place updates, in that case
+    /// the user is asked to restart it.
+    pub async fn check_service_build() -> Result<()> {
+        let app_version = env!("CARGO_PKG_VERSION");
+        let app_hash = option_env!("SLU_GIT_HASH");
+        let service_version = match Self::query_status().await {
+            Ok(status) => {
+                if status.is_same_build(app_version, app_hash) {
+                    log::debug!(
+                        "Seelen UI Service {} running for {}s",
+                        status.version,
+                        status.uptime_secs
+                    );
+                    return Ok(());
+                }
+                match status.git_hash {
+                    Some(hash) => format!("{} ({hash})", status.version),
+                    None => status.version,
+                }
+            }
+            // services older than the status action
+            Err(slu_ipc::error::Error::UnsupportedVersion { .. }) => "unknown".to_owned(),
+            Err(err) => return Err(err.into()),
+        };
+        let app_version = match app_hash {
+            Some(hash) => format!("{app_version} ({hash})"),
+            None => app_version.to_owned(),
+        };
+
+        log::warn!(
+            "Seelen UI Service build mismatch, service: {service_version}, app: {app_version}"
+        );
+        let restart = get_app_handle()
+            .dialog()
+            .message(t!(
+                "service.outdated_description",
+                service_version = service_version,
+                app_version = app_version
+            ))
+            .title(t!("service.outdated"))
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                t!("service.outdated_ok").to_string(),
+                t!("cancel").to_string(),
+            ))
+            .blocking_show();
+        if restart {
+            Self::restart_service().await?;
+        }
+        Ok(())
+    }
+
+    /// Stops the service and starts it again through the task scheduler
+    async fn restart_service() -> Result<()> {
+        Self::send(SvcAction::Stop).await?;
+        // the pipe is closed once the service finished its shutdown
+        for _ in 0..20 {
+            if !Self::is_running() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        }
+        Self::start_service().await
+    }
+
     pub fn is_running() -> bool {
         ServiceIpc::can_stablish_connection()
     }
@@ -62,6 +62,9 @@ define_app_errors!(
     SluIpc(slu_ipc::error::Error);
     Tokio(tokio::task::JoinError);
     Positioning(positioning::error::Error);
+    Zip(zip::result::ZipError);
+    Svg(resvg::usvg::Error);
+    IconExtraction(crate::utils::icon_extractor::IconExtractionError);
 );
 
 impl std::fmt::Debug for AppError {
@@ -15,9 +15,17 @@ use crate::app::{get_app_handle, Seelen};
 use crate::error::Result;
 use crate::hook::HookManager;
 use crate::modules::input::Keyboard;
+use crate::state::application::{IconPackValidationReport, FULL_STATE};
+use crate::trace_lock;
 
 use crate::utils::constants::SEELEN_COMMON;
-use crate::utils::icon_extractor::{extract_and_save_icon_from_file, extract_and_save_icon_umid};
+use crate::utils::icon_extractor::{
+    export_icon_as_ico, extract_and_save_icon_from_file, extract_and_save_icon_umid,
+    get_extraction_failures, get_extraction_metrics, get_icon_colors as stored_icon_colors,
+    get_refresh_progress, refresh_all_icons, retry_icon_extraction, IcoExportVariant, IconColors,
+    IconExtractionFailure, IconExtractionKey, IconExtractionMetrics, IconPreviewError,
+    IconRefreshProgress,
+};
 use crate::utils::is_running_as_appx;
 use crate::utils::pwsh::PwshScript;
 use crate::virtual_desktops::get_vd_manager;
@@ -163,6 +171,122 @@ fn get_icon(path: Option<PathBuf>, umid: Option<String>) -> Result<()> {
     Ok(())
 }
 
+#[tauri::command(async)]
+fn get_icon_extraction_failures() -> Vec<IconExtractionFailure> {
+    get_extraction_failures()
+}
+
+#[tauri::command(async)]
+fn get_icon_extraction_metrics() -> IconExtractionMetrics {
+    get_extraction_metrics()
+}
+
+#[tauri::command(async)]
+fn retry_failed_icon_extraction(key: IconExtractionKey) {
+    retry_icon_extraction(key);
+}
+
+#[tauri::command(async)]
+fn set_custom_app_icon(key: IconExtractionKey, image_path: PathBuf) -> Result<()> {
+    let state = FULL_STATE.load();
+    trace_lock!(state.icon_packs()).set_custom_app_icon(&key, &image_path)?;
+    state.emit_icon_packs()
+}
+
+/// Extracts again all the system icons, the progress is given by `get_icon_refresh_progress`
+#[tauri::command(async)]
+fn refresh_all_system_icons() -> Result<()> {
+    refresh_all_icons()
+}
+
+#[tauri::command(async)]
+fn get_icon_refresh_progress() -> IconRefreshProgress {
+    get_refresh_progress()
+}
+
+/// Colors of the stored icons, by file relative to the icons folder like `system/abc.png`
+#[tauri::command(async)]
+fn get_icon_colors() -> HashMap<String, IconColors> {
+    stored_icon_colors()
+}
+
+#[tauri::command(async)]
+fn export_app_icon_as_ico(
+    key: IconExtractionKey,
+    destination: PathBuf,
+    variant: Option<IcoExportVariant>,
+) -> Result<()> {
+    export_icon_as_ico(&key, &destination, variant.unwrap_or_default())
+}
+
+#[tauri::command(async)]
+fn clear_custom_app_icon(key: IconExtractionKey) -> Result<()> {
+    let state = FULL_STATE.load();
+    trace_lock!(state.icon_packs()).clear_custom_app_icon(&key)?;
+    state.emit_icon_packs()
+}
+
+#[tauri::command(async)]
+fn validate_icon_pack(repair: bool) -> Result<IconPackValidationReport> {
+    let state = FULL_STATE.load();
+    let report = trace_lock!(state.icon_packs()).validate_icon_pack(repair)?;
+    if repair && !report.issues.is_empty() {
+        state.emit_icon_packs()?;
+    }
+    Ok(report)
+}
+
+#[tauri::command(async)]
+fn export_icon_pack(name: String, destination: PathBuf) -> Result<PathBuf> {
+    let mutex = FULL_STATE.load().icon_packs().clone();
+    let icon_manager = trace_lock!(mutex);
+    icon_manager.export_icon_pack(&name, &destination)
+}
+
+#[tauri::command(async)]
+fn import_icon_pack(archive: PathBuf) -> Result<String> {
+    let state = FULL_STATE.load();
+    let name = trace_lock!(state.icon_packs()).import_pack(&archive)?;
+    state.emit_icon_packs()?;
+    Ok(name)
+}
+
+#[tauri::command]
+async fn preview_icon_for_path(path: String) -> std::result::Result<Vec<u8>, IconPreviewError> {
+    // shell extraction is blocking and can hang on slow drives
+    tauri::async_runtime::spawn_blocking(move || {
+        crate::utils::icon_extractor::preview_icon_for_path(&PathBuf::from(path))
+    })
+    .await
+    .map_err(|err| IconPreviewError::ExtractionFailed {
+        message: err.to_string(),
+    })?
+}
+
+#[tauri::command]
+async fn preview_all_icons_of_file(
+    path: String,
+) -> std::result::Result<Vec<(i32, Vec<u8>)>, IconPreviewError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        crate::utils::icon_extractor::preview_all_icons_of_file(&PathBuf::from(path))
+    })
+    .await
+    .map_err(|err| IconPreviewError::ExtractionFailed {
+        message: err.to_string(),
+    })?
+}
+
+#[tauri::command]
+async fn preview_icon_for_window(hwnd: isize) -> std::result::Result<Vec<u8>, IconPreviewError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        crate::utils::icon_extractor::preview_icon_for_window(hwnd)
+    })
+    .await
+    .map_err(|err| IconPreviewError::ExtractionFailed {
+        message: err.to_string(),
+    })?
+}
+
 #[tauri::command(async)]
 fn simulate_fullscreen(webview: WebviewWindow<tauri::Wry>, value: bool) -> Result<()> {
     let window = Window::from(webview.hwnd()?.0 as isize);
@@ -238,6 +362,17 @@ async fn translate_text(
     Ok(translated)
 }
 
+/// Names and handler of the commands declared on this crate, from a single list so the
+/// dispatch can't get out of sync with the handler.
+macro_rules! local_command_handlers {
+    ($($command:ident),* $(,)?) => {
+        (
+            [$(stringify!($command)),*],
+            tauri::generate_handler![$($command),*],
+        )
+    };
+}
+
 pub fn register_invoke_handler(app_builder: Builder<Wry>) -> Builder<Wry> {
     use crate::cli::*;
     use crate::state::infrastructure::*;
@@ -259,5 +394,31 @@ pub fn register_invoke_handler(app_builder: Builder<Wry>) -> Builder<Wry> {
     use crate::modules::user::infrastructure::*;
     use crate::resources::commands::*;
 
-    app_builder.invoke_handler(command_handler_list!())
+    // commands that are not yet part of the shared list on seelen_core
+    let (local_commands, local_handler) = local_command_handlers![
+        get_icon_extraction_failures,
+        get_icon_extraction_metrics,
+        retry_failed_icon_extraction,
+        preview_icon_for_path,
+        preview_all_icons_of_file,
+        preview_icon_for_window,
+        export_icon_pack,
+        import_icon_pack,
+        set_custom_app_icon,
+        clear_custom_app_icon,
+        export_app_icon_as_ico,
+        get_icon_colors,
+        refresh_all_system_icons,
+        get_icon_refresh_progress,
+        validate_icon_pack,
+    ];
+    let shared_handler = command_handler_list!();
+
+    app_builder.invoke_handler(move |invoke| {
+        if local_commands.contains(&invoke.message.command()) {
+            local_handler(invoke)
+        } else {
+            shared_handler(invoke)
+        }
+    })
 }
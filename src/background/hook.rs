@@ -28,7 +28,7 @@ use crate::{
     modules::input::{domain::Point, Mouse},
     state::application::FULL_STATE,
     trace_lock,
-    utils::spawn_named_thread,
+    utils::{icon_extractor::retry_pending_umid_of_window, spawn_named_thread},
     virtual_desktops::{events::VirtualDesktopEvent, get_vd_manager, SluWorkspacesManager},
     widgets::{weg::SeelenWeg, window_manager::instance::WindowManagerV2},
     windows_api::{
@@ -180,6 +180,10 @@ impl HookManager {
             log_error!(get_vd_manager().on_win_event(event, &origin), event);
         }
 
+        if matches!(event, WinEvent::ObjectCreate | WinEvent::ObjectShow) {
+            retry_pending_umid_of_window(&origin);
+        }
+
         let app_state = FULL_STATE.load();
         if app_state.is_weg_enabled() {
             std::thread::spawn(move || {
@@ -32,7 +32,7 @@ use std::sync::{atomic::AtomicBool, OnceLock};
 
 use app::{Seelen, SEELEN};
 use app_tray::try_register_tray_icon;
-use cli::{application::handle_console_client, SelfPipe, ServicePipe};
+use cli::{application::handle_console_client, SelfPipe, ServiceEvents, ServicePipe};
 use error::Result;
 use exposed::register_invoke_handler;
 use itertools::Itertools;
@@ -73,6 +73,11 @@ async fn setup(app_handle: &tauri::AppHandle<tauri::Wry>) -> Result<()> {
     if !ServicePipe::is_running() {
         ServicePipe::start_service().await?;
     }
+    // the dialog would block the setup
+    get_tokio_handle().spawn(async {
+        log_error!(ServicePipe::check_service_build().await);
+    });
+    ServiceEvents::start_listener();
 
     check_for_webview_optimal_state(app_handle)?;
 
@@ -1,12 +1,36 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use arc_swap::ArcSwap;
 use lazy_static::lazy_static;
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{
+        event::{ModifyKind, RenameMode},
+        EventKind, ReadDirectoryChangesWatcher, RecursiveMode, Watcher,
+    },
+    DebounceEventResult, DebouncedEvent, Debouncer, FileIdMap,
+};
+use parking_lot::Mutex;
 use seelen_core::system_state::StartMenuItem;
 use windows::Win32::UI::Shell::{FOLDERID_CommonPrograms, FOLDERID_Programs};
 
-use crate::{error::Result, log_error, utils::constants::SEELEN_COMMON, windows_api::WindowsApi};
+use crate::{
+    error::Result,
+    log_error,
+    state::application::{IconPacksManager, FULL_STATE},
+    trace_lock,
+    utils::{
+        constants::SEELEN_COMMON,
+        icon_extractor::{
+            extract_and_save_icons_from_files, is_excluded_from_extraction,
+            retry_pending_umids_with_shortcut, spawn_icon_extractor_pool,
+        },
+    },
+    windows_api::WindowsApi,
+};
 
 lazy_static! {
     pub static ref START_MENU_MANAGER: ArcSwap<StartMenuManager> = ArcSwap::from_pointee({
@@ -14,10 +38,29 @@ lazy_static! {
         manager.init().unwrap();
         manager
     });
+    static ref START_MENU_WATCHER: Mutex<Option<StartMenuWatcher>> = Mutex::new(None);
+}
+
+type StartMenuWatcher = Debouncer<ReadDirectoryChangesWatcher, FileIdMap>;
+
+/// installers usually write several shortcuts in a row
+const WATCHER_DEBOUNCE: Duration = Duration::from_millis(1500);
+
+/// shell icon extraction is mostly io bound, but too many workers contend on the icon pack
+const MAX_ICON_WORKERS: usize = 4;
+
+/// Changes on the start menu shortcuts, joined from the debounced events
+#[derive(Debug, Default)]
+struct StartMenuChanges {
+    changed: HashSet<PathBuf>,
+    removed: HashSet<PathBuf>,
+    renamed: Vec<(PathBuf, PathBuf)>,
 }
 
 pub struct StartMenuManager {
     pub list: Vec<StartMenuItem>,
+    /// umid -> position on `list` of the first shortcut with it, rebuilt each time the list is read
+    umid_index: HashMap<String, usize>,
     cache_path: PathBuf,
 }
 
@@ -36,6 +79,7 @@ impl StartMenuManager {
     pub fn new() -> StartMenuManager {
         StartMenuManager {
             list: Vec::new(),
+            umid_index: HashMap::new(),
             cache_path: SEELEN_COMMON.app_cache_dir().join("start_menu_v2.json"),
         }
     }
@@ -49,6 +93,7 @@ impl StartMenuManager {
                         let mut menu = StartMenuManager::new();
                         log_error!(menu.read_start_menu_folders());
                         log_error!(menu.store_cache());
+                        log_error!(menu.extract_icons());
                         START_MENU_MANAGER.swap(Arc::new(menu));
                     });
                     return Ok(());
@@ -72,18 +117,24 @@ impl StartMenuManager {
 
     /// https://learn.microsoft.com/en-us/windows/win32/properties/props-system-appusermodel-relaunchiconresource
     pub fn get_by_file_umid(&self, umid: &str) -> Option<&StartMenuItem> {
+        if let Some(index) = self.umid_index.get(umid) {
+            return self.list.get(*index);
+        }
+        // some apps registered as media player as example use the process name as umid
         self.list.iter().find(|item| {
-            if let Some(item_umid) = &item.umid {
-                return item_umid == umid;
-            }
-            if let Some(target) = &item.target {
-                // some apps registered as media player as example use the process name as umid
-                return target.ends_with(umid);
-            }
-            false
+            item.umid.is_none() && item.target.as_ref().is_some_and(|t| t.ends_with(umid))
         })
     }
 
+    fn rebuild_umid_index(&mut self) {
+        self.umid_index.clear();
+        for (index, item) in self.list.iter().enumerate() {
+            if let Some(umid) = &item.umid {
+                self.umid_index.entry(umid.clone()).or_insert(index);
+            }
+        }
+    }
+
     pub fn store_cache(&self) -> Result<()> {
         let file = std::fs::File::create(&self.cache_path)?;
         let writer = std::io::BufWriter::new(file);
@@ -95,6 +146,7 @@ impl StartMenuManager {
         let file = std::fs::File::open(&self.cache_path)?;
         let reader = std::io::BufReader::new(file);
         self.list = serde_json::from_reader(reader)?;
+        self.rebuild_umid_index();
         Ok(())
     }
 
@@ -120,11 +172,137 @@ impl StartMenuManager {
         Ok(items)
     }
 
+    /// Extracts the icons of all the items in parallel, already extracted ones are skipped
+    fn extract_icons(&self) -> Result<()> {
+        let num_threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(MAX_ICON_WORKERS);
+
+        // the system pack is written once, when the scan finishes
+        let _batch = IconPacksManager::batch_write_guard();
+
+        let pool = spawn_icon_extractor_pool(num_threads);
+        for item in &self.list {
+            pool.submit(item.path.clone());
+        }
+        let failed = pool.join().into_iter().filter(|r| r.is_err()).count();
+        if failed > 0 {
+            log::warn!("Failed to extract {failed} start menu icons");
+        }
+        Ok(())
+    }
+
+    fn is_shortcut(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| {
+            let ext = ext.to_string_lossy().to_lowercase();
+            ext == "lnk" || ext == "url"
+        })
+    }
+
+    fn join_debounced_changes(events: Vec<DebouncedEvent>) -> StartMenuChanges {
+        let mut changes = StartMenuChanges::default();
+        for event in events {
+            match event.kind {
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                    let (from, to) = (&event.paths[0], &event.paths[1]);
+                    if Self::is_shortcut(from) && Self::is_shortcut(to) {
+                        changes.renamed.push((from.clone(), to.clone()));
+                    }
+                }
+                EventKind::Create(_) | EventKind::Modify(_) => {
+                    for path in event.paths.iter().filter(|p| Self::is_shortcut(p)) {
+                        changes.removed.remove(path);
+                        changes.changed.insert(path.clone());
+                    }
+                }
+                EventKind::Remove(_) => {
+                    for path in event.paths.iter().filter(|p| Self::is_shortcut(p)) {
+                        changes.changed.remove(path);
+                        changes.removed.insert(path.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        changes
+    }
+
+    /// Reads the start menu folders again, replacing the current list
+    pub fn reindex() -> Result<()> {
+        let mut menu = StartMenuManager::new();
+        menu.read_start_menu_folders()?;
+        menu.store_cache()?;
+        START_MENU_MANAGER.swap(Arc::new(menu));
+        Ok(())
+    }
+
+    fn on_files_changed(changes: StartMenuChanges) -> Result<()> {
+        if changes.changed.is_empty() && changes.removed.is_empty() && changes.renamed.is_empty() {
+            return Ok(());
+        }
+
+        Self::reindex()?;
+        retry_pending_umids_with_shortcut();
+
+        {
+            let mutex = FULL_STATE.load().icon_packs().clone();
+            let mut icon_manager = trace_lock!(mutex);
+            // reinstalled apps get their shortcuts back
+            for path in &changes.changed {
+                icon_manager.unmark_system_app_icon_stale(path);
+            }
+            // the entry and its files are kept, the user could have customized them
+            for path in &changes.removed {
+                icon_manager.mark_system_app_icon_stale(path);
+            }
+            if !changes.renamed.is_empty() {
+                for (from, to) in &changes.renamed {
+                    icon_manager.rename_system_app_icon(from, to);
+                }
+                icon_manager.write_system_icon_pack()?;
+            }
+        }
+
+        let to_extract: Vec<PathBuf> = changes
+            .changed
+            .into_iter()
+            .filter(|path| !is_excluded_from_extraction(path))
+            .collect();
+        if !to_extract.is_empty() {
+            extract_and_save_icons_from_files(to_extract);
+        }
+        Ok(())
+    }
+
+    /// Watches the start menu folders to keep the list and the icons of new apps up to date
+    pub fn start_listeners() -> Result<()> {
+        let mut debouncer = new_debouncer(
+            WATCHER_DEBOUNCE,
+            None,
+            |result: DebounceEventResult| match result {
+                Ok(events) => {
+                    log_error!(Self::on_files_changed(Self::join_debounced_changes(events)));
+                }
+                Err(errors) => {
+                    log::error!("Start Menu Watcher Error: {errors:?}")
+                }
+            },
+        )?;
+
+        let watcher = debouncer.watcher();
+        watcher.watch(&Self::common_items_path(), RecursiveMode::Recursive)?;
+        watcher.watch(&Self::user_items_path(), RecursiveMode::Recursive)?;
+        *START_MENU_WATCHER.lock() = Some(debouncer);
+        Ok(())
+    }
+
     pub fn read_start_menu_folders(&mut self) -> Result<()> {
         let mut items = vec![];
         items.extend(Self::_get_items(&Self::common_items_path())?);
         items.extend(Self::_get_items(&Self::user_items_path())?);
         self.list = items;
+        self.rebuild_umid_index();
         Ok(())
     }
 }
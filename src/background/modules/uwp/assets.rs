@@ -0,0 +1,415 @@
+use std::path::{Path, PathBuf};
+
+/// Theme variant declared by the `altform-*` qualifier of an asset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetPlate {
+    /// default asset, with the accent colored plate baked in
+    Plated,
+    /// `altform-unplated`, intended for dark backgrounds
+    Unplated,
+    /// `altform-lightunplated`, intended for light backgrounds
+    LightUnplated,
+}
+
+/// A file on disk that is a variant of a logo declared on the package manifest
+#[derive(Debug, Clone)]
+pub struct AssetCandidate {
+    pub path: PathBuf,
+    pub plate: AssetPlate,
+    pub target_size: Option<u32>,
+    pub scale: Option<u32>,
+    /// estimated size in pixels of the longest side
+    pub pixel_size: u32,
+}
+
+/// Manifest logo from where the asset was taken, ordered by preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UwpAssetClass {
+    Square44x44Logo,
+    Square150x150Logo,
+    SplashScreen,
+    StoreLogo,
+}
+
+impl UwpAssetClass {
+    /// wide assets need to be cropped to a square around its content
+    pub fn is_wide(&self) -> bool {
+        matches!(self, UwpAssetClass::SplashScreen)
+    }
+}
+
+/// Selected asset to be used as icon
+#[derive(Debug, Clone)]
+pub struct UwpIconAsset {
+    pub light: PathBuf,
+    pub dark: PathBuf,
+    /// size in pixels of the chosen asset, `None` if it couldn't be determined
+    pub pixel_size: Option<u32>,
+    /// the asset is meant to be drawn over the app background color
+    pub plated: bool,
+}
+
+/// `Square44x44Logo` -> 44, used to compute the pixel size of scale qualified assets
+fn base_size_from_name(name: &str) -> Option<u32> {
+    let digits: String = name
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+/// Parses the qualifiers of an asset filename like `Logo.targetsize-256_altform-unplated.png`
+pub fn parse_candidate(logo_stem: &str, extension: &str, path: &Path) -> Option<AssetCandidate> {
+    let file_name = path.file_name()?.to_str()?;
+    let lower = file_name.to_lowercase();
+    let stem = lower.strip_suffix(&format!(".{}", extension.to_lowercase()))?;
+    let qualifiers = stem
+        .strip_prefix(&logo_stem.to_lowercase())?
+        .strip_prefix('.')?;
+
+    let mut candidate = AssetCandidate {
+        path: path.to_path_buf(),
+        plate: AssetPlate::Plated,
+        target_size: None,
+        scale: None,
+        pixel_size: 0,
+    };
+
+    for qualifier in qualifiers.split(['_', '.']) {
+        if let Some(size) = qualifier.strip_prefix("targetsize-") {
+            candidate.target_size = size.parse().ok();
+        } else if let Some(scale) = qualifier.strip_prefix("scale-") {
+            candidate.scale = scale.parse().ok();
+        } else if qualifier == "altform-unplated" {
+            candidate.plate = AssetPlate::Unplated;
+        } else if qualifier == "altform-lightunplated" {
+            candidate.plate = AssetPlate::LightUnplated;
+        } else if qualifier.starts_with("contrast-") {
+            // high contrast assets are not suitable as icons
+            return None;
+        }
+    }
+
+    candidate.pixel_size = match (candidate.target_size, candidate.scale) {
+        (Some(size), _) => size,
+        (None, Some(scale)) => base_size_from_name(logo_stem).unwrap_or(100) * scale / 100,
+        (None, None) => image::image_dimensions(path)
+            .map(|(w, h)| w.max(h))
+            .unwrap_or(0),
+    };
+    Some(candidate)
+}
+
+/// Enumerates all the qualified variants of the logo on its directory
+pub fn enumerate_asset_candidates(logo_path: &Path) -> Vec<AssetCandidate> {
+    let (Some(stem), Some(extension), Some(dir)) = (
+        logo_path.file_stem().and_then(|s| s.to_str()),
+        logo_path.extension().and_then(|s| s.to_str()),
+        logo_path.parent(),
+    ) else {
+        return Vec::new();
+    };
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| parse_candidate(stem, extension, &entry.path()))
+        .collect()
+}
+
+/// Chooses the highest-resolution asset, unplated assets are always preferred over plated ones.
+///
+/// With `max_scale` the assets qualified with a bigger `scale-*` are ignored, unless there is
+/// nothing else to choose. Assets qualified by `targetsize-*` don't depend on the scale.
+///
+/// Packages using resources.pri indirection can have the asset without qualifiers on disk,
+/// in that case the literal manifest path is used.
+pub fn select_best_asset(logo_path: &Path, max_scale: Option<u32>) -> Option<UwpIconAsset> {
+    let mut candidates = enumerate_asset_candidates(logo_path);
+    if let Some(max_scale) = max_scale {
+        let fits = |c: &AssetCandidate| c.scale.is_none_or(|scale| scale <= max_scale);
+        if candidates.iter().any(fits) {
+            candidates.retain(fits);
+        } else if logo_path.exists() {
+            // only bigger scales are available, so the unscaled asset is used instead
+            candidates.clear();
+        }
+    }
+
+    let best_of = |plate: AssetPlate| {
+        candidates
+            .iter()
+            .filter(|c| c.plate == plate)
+            .max_by_key(|c| (c.pixel_size, c.target_size.is_some()))
+    };
+
+    let light_unplated = best_of(AssetPlate::LightUnplated);
+    let unplated = best_of(AssetPlate::Unplated);
+
+    let asset = match (light_unplated, unplated) {
+        (Some(light), Some(dark)) => Some(UwpIconAsset {
+            light: light.path.clone(),
+            dark: dark.path.clone(),
+            pixel_size: Some(light.pixel_size.max(dark.pixel_size)),
+            plated: false,
+        }),
+        (Some(single), None) | (None, Some(single)) => Some(UwpIconAsset {
+            light: single.path.clone(),
+            dark: single.path.clone(),
+            pixel_size: Some(single.pixel_size),
+            plated: false,
+        }),
+        (None, None) => best_of(AssetPlate::Plated).map(|plated| UwpIconAsset {
+            light: plated.path.clone(),
+            dark: plated.path.clone(),
+            pixel_size: Some(plated.pixel_size),
+            plated: true,
+        }),
+    };
+
+    asset.or_else(|| {
+        logo_path.exists().then(|| UwpIconAsset {
+            light: logo_path.to_path_buf(),
+            dark: logo_path.to_path_buf(),
+            pixel_size: image::image_dimensions(logo_path)
+                .ok()
+                .map(|(w, h)| w.max(h)),
+            plated: true,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// folder with empty files as assets, removed on drop
+    struct AssetsDir(PathBuf);
+
+    impl AssetsDir {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("slu-uwp-assets-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            for file in files {
+                std::fs::write(dir.join(file), []).unwrap();
+            }
+            Self(dir)
+        }
+
+        fn logo(&self) -> PathBuf {
+            self.0.join("Square44x44Logo.png")
+        }
+
+        fn file_name(path: &Path) -> &str {
+            path.file_name().unwrap().to_str().unwrap()
+        }
+    }
+
+    impl Drop for AssetsDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn parse_qualified_names() {
+        let cases = [
+            (
+                "Square44x44Logo.scale-200.png",
+                AssetPlate::Plated,
+                None,
+                Some(200),
+                88,
+            ),
+            (
+                "SQUARE44X44LOGO.SCALE-150.PNG",
+                AssetPlate::Plated,
+                None,
+                Some(150),
+                66,
+            ),
+            (
+                "Square44x44Logo.targetsize-256_altform-unplated.png",
+                AssetPlate::Unplated,
+                Some(256),
+                None,
+                256,
+            ),
+            (
+                "Square44x44Logo.targetsize-48_altform-lightunplated.png",
+                AssetPlate::LightUnplated,
+                Some(48),
+                None,
+                48,
+            ),
+            (
+                "Square44x44Logo.altform-unplated_targetsize-32.png",
+                AssetPlate::Unplated,
+                Some(32),
+                None,
+                32,
+            ),
+        ];
+
+        for (name, plate, target_size, scale, pixel_size) in cases {
+            let candidate = parse_candidate("Square44x44Logo", "png", Path::new(name))
+                .unwrap_or_else(|| panic!("{name} should be a candidate"));
+            assert_eq!(candidate.plate, plate, "{name}");
+            assert_eq!(candidate.target_size, target_size, "{name}");
+            assert_eq!(candidate.scale, scale, "{name}");
+            assert_eq!(candidate.pixel_size, pixel_size, "{name}");
+        }
+    }
+
+    #[test]
+    fn scale_uses_the_size_of_the_logo_name() {
+        let candidate = parse_candidate(
+            "Square150x150Logo",
+            "png",
+            Path::new("Square150x150Logo.scale-125.png"),
+        )
+        .unwrap();
+        assert_eq!(candidate.pixel_size, 187);
+    }
+
+    #[test]
+    fn skip_non_candidates() {
+        let cases = [
+            "Square44x44Logo.scale-100_contrast-black.png",
+            "Square44x44Logo.contrast-white_scale-200.png",
+            "Square44x44Logo.png",
+            "Square44x44Logo.scale-100.jpg",
+            "StoreLogo.scale-100.png",
+        ];
+        for name in cases {
+            assert!(
+                parse_candidate("Square44x44Logo", "png", Path::new(name)).is_none(),
+                "{name} should not be a candidate"
+            );
+        }
+    }
+
+    #[test]
+    fn select_the_biggest_scale() {
+        let dir = AssetsDir::new(
+            "biggest",
+            &[
+                "Square44x44Logo.scale-100.png",
+                "Square44x44Logo.scale-200.png",
+                "Square44x44Logo.scale-400.png",
+            ],
+        );
+        let asset = select_best_asset(&dir.logo(), None).unwrap();
+        assert_eq!(
+            AssetsDir::file_name(&asset.light),
+            "Square44x44Logo.scale-400.png"
+        );
+        assert_eq!(asset.pixel_size, Some(176));
+        assert!(asset.plated);
+    }
+
+    #[test]
+    fn max_scale_cuts_off_bigger_scales() {
+        let dir = AssetsDir::new(
+            "max-scale",
+            &[
+                "Square44x44Logo.scale-100.png",
+                "Square44x44Logo.scale-200.png",
+                "Square44x44Logo.scale-400.png",
+            ],
+        );
+        let asset = select_best_asset(&dir.logo(), Some(200)).unwrap();
+        assert_eq!(
+            AssetsDir::file_name(&asset.light),
+            "Square44x44Logo.scale-200.png"
+        );
+
+        // nothing fits and there is no unqualified asset, so the cut-off is ignored
+        let asset = select_best_asset(&dir.logo(), Some(50)).unwrap();
+        assert_eq!(
+            AssetsDir::file_name(&asset.light),
+            "Square44x44Logo.scale-400.png"
+        );
+    }
+
+    #[test]
+    fn max_scale_falls_back_to_the_unqualified_asset() {
+        let dir = AssetsDir::new(
+            "unqualified",
+            &["Square44x44Logo.png", "Square44x44Logo.scale-400.png"],
+        );
+        let asset = select_best_asset(&dir.logo(), Some(200)).unwrap();
+        assert_eq!(asset.light, dir.logo());
+        assert!(asset.plated);
+    }
+
+    #[test]
+    fn max_scale_keeps_target_sizes() {
+        let dir = AssetsDir::new(
+            "target-size",
+            &[
+                "Square44x44Logo.scale-400.png",
+                "Square44x44Logo.targetsize-256.png",
+            ],
+        );
+        let asset = select_best_asset(&dir.logo(), Some(100)).unwrap();
+        assert_eq!(
+            AssetsDir::file_name(&asset.light),
+            "Square44x44Logo.targetsize-256.png"
+        );
+        assert_eq!(asset.pixel_size, Some(256));
+    }
+
+    #[test]
+    fn unplated_is_preferred_over_bigger_plated() {
+        let dir = AssetsDir::new(
+            "unplated",
+            &[
+                "Square44x44Logo.scale-400.png",
+                "Square44x44Logo.targetsize-48_altform-unplated.png",
+            ],
+        );
+        let asset = select_best_asset(&dir.logo(), None).unwrap();
+        assert_eq!(
+            AssetsDir::file_name(&asset.light),
+            "Square44x44Logo.targetsize-48_altform-unplated.png"
+        );
+        assert_eq!(asset.light, asset.dark);
+        assert!(!asset.plated);
+    }
+
+    #[test]
+    fn light_and_dark_unplated_variants() {
+        let dir = AssetsDir::new(
+            "variants",
+            &[
+                "Square44x44Logo.targetsize-256_altform-unplated.png",
+                "Square44x44Logo.targetsize-256_altform-lightunplated.png",
+                "Square44x44Logo.targetsize-32_altform-lightunplated.png",
+            ],
+        );
+        let asset = select_best_asset(&dir.logo(), None).unwrap();
+        assert_eq!(
+            AssetsDir::file_name(&asset.light),
+            "Square44x44Logo.targetsize-256_altform-lightunplated.png"
+        );
+        assert_eq!(
+            AssetsDir::file_name(&asset.dark),
+            "Square44x44Logo.targetsize-256_altform-unplated.png"
+        );
+        assert_eq!(asset.pixel_size, Some(256));
+        assert!(!asset.plated);
+    }
+
+    #[test]
+    fn no_asset_without_files() {
+        let dir = AssetsDir::new("empty", &[]);
+        assert!(select_best_asset(&dir.logo(), None).is_none());
+    }
+}
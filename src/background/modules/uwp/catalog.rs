@@ -0,0 +1,125 @@
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+use windows::{
+    ApplicationModel::{
+        Package, PackageCatalog, PackageInstallingEventArgs, PackageUninstallingEventArgs,
+        PackageUpdatingEventArgs,
+    },
+    Foundation::TypedEventHandler,
+};
+
+use crate::{error::Result, event_manager, log_error, state::application::FULL_STATE, trace_lock};
+
+use super::UwpManager;
+
+static PACKAGE_CATALOG: LazyLock<Mutex<Option<PackageCatalogSubscription>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+/// Package family name of the package that changed
+#[derive(Debug, Clone)]
+pub enum UwpPackageEvent {
+    Installed(String),
+    Updated(String),
+    Uninstalled(String),
+}
+
+event_manager!(UwpManager, UwpPackageEvent);
+
+struct PackageCatalogSubscription {
+    catalog: PackageCatalog,
+    installing_token: i64,
+    updating_token: i64,
+    uninstalling_token: i64,
+}
+
+unsafe impl Send for PackageCatalogSubscription {}
+
+fn family_name(package: windows_core::Result<Package>) -> windows_core::Result<String> {
+    Ok(package?.Id()?.FamilyName()?.to_string_lossy())
+}
+
+fn on_package_installing(
+    _sender: &Option<PackageCatalog>,
+    args: &Option<PackageInstallingEventArgs>,
+) -> windows_core::Result<()> {
+    if let Some(args) = args {
+        if args.IsComplete()? {
+            UwpManager::send(UwpPackageEvent::Installed(family_name(args.Package())?));
+        }
+    }
+    Ok(())
+}
+
+fn on_package_updating(
+    _sender: &Option<PackageCatalog>,
+    args: &Option<PackageUpdatingEventArgs>,
+) -> windows_core::Result<()> {
+    if let Some(args) = args {
+        if args.IsComplete()? {
+            UwpManager::send(UwpPackageEvent::Updated(family_name(args.TargetPackage())?));
+        }
+    }
+    Ok(())
+}
+
+fn on_package_uninstalling(
+    _sender: &Option<PackageCatalog>,
+    args: &Option<PackageUninstallingEventArgs>,
+) -> windows_core::Result<()> {
+    if let Some(args) = args {
+        if args.IsComplete()? {
+            UwpManager::send(UwpPackageEvent::Uninstalled(family_name(args.Package())?));
+        }
+    }
+    Ok(())
+}
+
+impl UwpManager {
+    /// Listens to the packages installed, updated or removed for the current user,
+    /// dropping the icons extracted from them so they are extracted again on next access.
+    pub fn init() -> Result<()> {
+        let catalog = PackageCatalog::OpenForCurrentUser()?;
+        let subscription = PackageCatalogSubscription {
+            installing_token: catalog
+                .PackageInstalling(&TypedEventHandler::new(on_package_installing))?,
+            updating_token: catalog
+                .PackageUpdating(&TypedEventHandler::new(on_package_updating))?,
+            uninstalling_token: catalog
+                .PackageUninstalling(&TypedEventHandler::new(on_package_uninstalling))?,
+            catalog,
+        };
+        *trace_lock!(PACKAGE_CATALOG) = Some(subscription);
+
+        Self::subscribe(|event| log_error!(Self::on_package_event(event)));
+        Ok(())
+    }
+
+    pub fn release() {
+        if let Some(subscription) = trace_lock!(PACKAGE_CATALOG).take() {
+            let catalog = &subscription.catalog;
+            log_error!(catalog.RemovePackageInstalling(subscription.installing_token));
+            log_error!(catalog.RemovePackageUpdating(subscription.updating_token));
+            log_error!(catalog.RemovePackageUninstalling(subscription.uninstalling_token));
+        }
+    }
+
+    fn on_package_event(event: UwpPackageEvent) -> Result<()> {
+        log::trace!("Package changed: {event:?}");
+        let family_name = match &event {
+            UwpPackageEvent::Installed(name)
+            | UwpPackageEvent::Updated(name)
+            | UwpPackageEvent::Uninstalled(name) => name,
+        };
+
+        let state = FULL_STATE.load();
+        {
+            let mut icon_manager = trace_lock!(state.icon_packs());
+            if !icon_manager.remove_package_app_icons(family_name) {
+                return Ok(());
+            }
+            icon_manager.write_system_icon_pack()?;
+        }
+        state.emit_icon_packs()
+    }
+}
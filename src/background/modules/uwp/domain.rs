@@ -59,4 +59,13 @@ pub struct ManifestApplicationVisualElements {
     pub logo_150: String,
     #[serde(rename = "@Square44x44Logo")]
     pub logo_44: String,
+    pub splash_screen: Option<ManifestSplashScreen>,
+}
+
+/// This struct makes reference to:
+/// https://learn.microsoft.com/en-us/uwp/schemas/appxpackage/uapmanifestschema/element-uap-splashscreen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestSplashScreen {
+    #[serde(rename = "@Image")]
+    pub image: String,
 }
@@ -1,74 +1,22 @@
+pub mod assets;
+mod catalog;
 // unused/deprecated code but could be useful for understanding how uwp packing works
 pub mod domain;
 
+use assets::{select_best_asset, UwpAssetClass, UwpIconAsset};
 use domain::{ManifestApplication, PackageManifest};
-use lazy_static::lazy_static;
 use std::path::{Path, PathBuf};
-use windows::ApplicationModel::{AppInfo, Package};
-
-use crate::error::Result;
-
-pub static UWP_LIGHTUNPLATED_POSTFIX: &str = "_altform-lightunplated";
-pub static UWP_UNPLATED_POSTFIX: &str = "_altform-unplated";
-
-lazy_static! {
-    pub static ref UWP_TARGET_SIZE_POSTFIXES: Vec<&'static str> = vec![
-        ".targetsize-256",
-        ".targetsize-96",
-        ".targetsize-64",
-        ".targetsize-48",
-        ".targetsize-32",
-    ];
-    pub static ref UWP_SCALE_POSTFIXES: Vec<&'static str> = vec![
-        ".scale-400",
-        ".scale-200",
-        ".scale-150",
-        ".scale-125",
-        ".scale-100",
-    ];
-}
+use windows::{
+    core::HSTRING,
+    ApplicationModel::{AppInfo, Package},
+    Management::Deployment::PackageManager,
+};
+
+use crate::{error::Result, windows_api::WindowsApi};
 
 // returns light and dark icons
 pub fn get_hightest_quality_posible(icon_path: &Path) -> Option<(PathBuf, PathBuf)> {
-    let filename = icon_path.file_stem()?.to_str()?;
-    let extension = icon_path.extension()?.to_str()?;
-
-    let size_postfixes = (*UWP_TARGET_SIZE_POSTFIXES)
-        .iter()
-        .chain((*UWP_SCALE_POSTFIXES).iter());
-
-    for size_postfix in size_postfixes {
-        let light_icon = icon_path.with_file_name(format!(
-            "{filename}{size_postfix}{UWP_LIGHTUNPLATED_POSTFIX}.{extension}"
-        ));
-
-        let dark_icon = icon_path.with_file_name(format!(
-            "{filename}{size_postfix}{UWP_UNPLATED_POSTFIX}.{extension}"
-        ));
-
-        let unthemed_icon =
-            icon_path.with_file_name(format!("{filename}{size_postfix}.{extension}"));
-
-        match (
-            light_icon.exists(),
-            dark_icon.exists(),
-            unthemed_icon.exists(),
-        ) {
-            (true, true, _) => return Some((light_icon, dark_icon)),
-            (true, false, _) => return Some((light_icon.clone(), light_icon)),
-            (false, true, true) => return Some((unthemed_icon, dark_icon)),
-            (false, false, true) => return Some((unthemed_icon.clone(), unthemed_icon)),
-            _ => {}
-        }
-    }
-
-    // Some apps only adds one icon and without any postfix
-    // but we prefer the light/dark specific icon
-    if icon_path.exists() {
-        return Some((icon_path.to_path_buf(), icon_path.to_path_buf()));
-    }
-
-    None
+    select_best_asset(icon_path, None).map(|asset| (asset.light, asset.dark))
 }
 
 impl PackageManifest {
@@ -80,11 +28,43 @@ impl PackageManifest {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct UwpPackageInfo {
+    pub package_family_name: String,
+    pub display_name: String,
+    pub version: String,
+    pub install_path: PathBuf,
+}
+
+impl UwpPackageInfo {
+    fn from_package(package: &Package) -> Result<Self> {
+        let id = package.Id()?;
+        let version = id.Version()?;
+        Ok(Self {
+            package_family_name: id.FamilyName()?.to_string_lossy(),
+            // some system packages fail to resolve its display name
+            display_name: package
+                .DisplayName()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default(),
+            version: format!(
+                "{}.{}.{}.{}",
+                version.Major, version.Minor, version.Build, version.Revision
+            ),
+            install_path: PathBuf::from(package.InstalledPath()?.to_os_string()),
+        })
+    }
+}
+
 pub struct UwpManager;
 
 impl UwpManager {
     pub fn manifest_from_package(package: &Package) -> Result<PackageManifest> {
         let package_path = PathBuf::from(package.InstalledPath()?.to_os_string());
+        Self::manifest_from_install_path(&package_path)
+    }
+
+    pub fn manifest_from_install_path(package_path: &Path) -> Result<PackageManifest> {
         let manifest_path = package_path.join("AppxManifest.xml");
 
         let file = std::fs::File::open(&manifest_path)?;
@@ -93,6 +73,23 @@ impl UwpManager {
         Ok(quick_xml::de::from_reader(&mut reader)?)
     }
 
+    /// Lists all the packages installed for the current user, packages that can't be
+    /// read (eg: staged or being updated) are skipped.
+    pub fn enumerate_installed_packages() -> Result<Vec<UwpPackageInfo>> {
+        let manager = PackageManager::new()?;
+        // empty sid means the current user
+        let packages = manager.FindPackagesForUser(&HSTRING::new())?;
+
+        let mut result = Vec::new();
+        for package in packages {
+            match UwpPackageInfo::from_package(&package) {
+                Ok(info) => result.push(info),
+                Err(err) => log::trace!("Skipping package: {err:?}"),
+            }
+        }
+        Ok(result)
+    }
+
     /// Some apps like PWA on edge can be stored as UWP apps and don't have an executable path,
     /// so in that cases the function will return None
     pub fn get_app_path(app_umid: &str) -> Result<Option<PathBuf>> {
@@ -118,30 +115,85 @@ impl UwpManager {
         Ok(None)
     }
 
-    // returns light and dark icons
-    pub fn get_high_quality_icon_path(app_umid: &str) -> Result<(PathBuf, PathBuf)> {
+    /// Id of the app with the family name and app id as registered on the system, lookups are
+    /// case-insensitive. Fails if the package is not installed for the current user.
+    pub fn canonical_app_umid(app_umid: &str) -> Result<String> {
+        let (family_name, _) = app_umid
+            .split_once('!')
+            .ok_or_else(|| format!("Invalid package app user model id: {app_umid}"))?;
+        let manager = PackageManager::new()?;
+        let mut packages = manager
+            .FindPackagesForUserWithPackageFamilyName(&HSTRING::new(), &family_name.into())?
+            .into_iter();
+        if packages.next().is_none() {
+            return Err(format!("Package {family_name} is not installed").into());
+        }
+
+        let app_info = AppInfo::GetFromAppUserModelId(&app_umid.into())?;
+        Ok(format!(
+            "{}!{}",
+            app_info.PackageFamilyName()?.to_string_lossy(),
+            app_info.Id()?.to_string_lossy()
+        ))
+    }
+
+    /// `BackgroundColor` declared by the app on the package manifest
+    pub fn get_app_background_color(app_umid: &str) -> Result<Option<String>> {
+        let app_info = AppInfo::GetFromAppUserModelId(&app_umid.into())?;
+        let manifest = Self::manifest_from_package(&app_info.Package()?)?;
+        Ok(manifest
+            .get_app(&app_info.Id()?.to_string_lossy())
+            .map(|app| app.visual_elements.background_color.clone()))
+    }
+
+    /// scale of the primary monitor as percent (100, 125, 150, ...), used to choose the assets
+    pub fn primary_monitor_scale_percent() -> u32 {
+        WindowsApi::get_monitor_scale_factor(WindowsApi::primary_monitor())
+            .map(|factor| (factor * 100.0).round() as u32)
+            .unwrap_or(100)
+    }
+
+    /// returns the light and dark icons with the highest resolution available for `scale_percent`,
+    /// following the fallback chain: Square44x44Logo -> Square150x150Logo -> SplashScreen -> StoreLogo
+    pub fn get_high_quality_icon_path(
+        app_umid: &str,
+        scale_percent: u32,
+    ) -> Result<(UwpAssetClass, UwpIconAsset)> {
         let app_info = AppInfo::GetFromAppUserModelId(&app_umid.into())?;
         let package = app_info.Package()?;
         let manifest = Self::manifest_from_package(&package)?;
 
         let package_path = PathBuf::from(package.InstalledPath()?.to_os_string());
-        let store_logo = package_path.join(&manifest.properties.logo);
+        let mut chain = Vec::new();
 
         // if package does't have the app but it is still part of the package then use the package logo
-        let app_manifest = match manifest.get_app(&app_info.Id()?.to_string_lossy()) {
-            Some(app) => app,
-            None => {
-                return get_hightest_quality_posible(&store_logo)
-                    .ok_or("Could not find package logo path".into())
+        if let Some(app_manifest) = manifest.get_app(&app_info.Id()?.to_string_lossy()) {
+            let visual_elements = &app_manifest.visual_elements;
+            chain.push((
+                UwpAssetClass::Square44x44Logo,
+                package_path.join(&visual_elements.logo_44),
+            ));
+            chain.push((
+                UwpAssetClass::Square150x150Logo,
+                package_path.join(&visual_elements.logo_150),
+            ));
+            if let Some(splash) = &visual_elements.splash_screen {
+                chain.push((
+                    UwpAssetClass::SplashScreen,
+                    package_path.join(&splash.image),
+                ));
             }
-        };
-
-        let app_logo_44 = package_path.join(&app_manifest.visual_elements.logo_44);
-        let app_logo_150 = package_path.join(&app_manifest.visual_elements.logo_150);
+        }
+        chain.push((
+            UwpAssetClass::StoreLogo,
+            package_path.join(&manifest.properties.logo),
+        ));
 
-        get_hightest_quality_posible(&app_logo_44)
-            .or_else(|| get_hightest_quality_posible(&app_logo_150))
-            .or_else(|| get_hightest_quality_posible(&store_logo))
+        chain
+            .into_iter()
+            .find_map(|(class, path)| {
+                select_best_asset(&path, Some(scale_percent)).map(|asset| (class, asset))
+            })
             .ok_or_else(|| format!("App icon not found for {app_umid}").into())
     }
 }
@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use seelen_core::state::IconPack;
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::utils::{
+    constants::SEELEN_COMMON,
+    icon_extractor::{system_pack_file, JsonSidecar, FALLBACK_ICON_PREFIX},
+};
+
+use super::{
+    icon_gc::delete_unreferenced_files,
+    icon_pack_archive::{entry_icon_mut, icon_files_mut},
+};
+
+static DEDUP_INDEX: JsonSidecar<DedupIndex> =
+    JsonSidecar::new(dedup_index_path, DedupIndex::retain_existing_files);
+
+/// Content hash -> first file of the system pack stored with that content
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+struct DedupIndex {
+    files: HashMap<u64, PathBuf>,
+    /// reverse of `files`, so already indexed files are not hashed again
+    #[serde(skip)]
+    hashes: HashMap<PathBuf, u64>,
+}
+
+fn dedup_index_path() -> PathBuf {
+    system_pack_file(".dedup_index.json")
+}
+
+impl DedupIndex {
+    fn retain_existing_files(&mut self) {
+        let folder = SEELEN_COMMON.user_icons_path().join("system");
+        self.files.retain(|_, file| folder.join(file).exists());
+        self.hashes = self
+            .files
+            .iter()
+            .map(|(hash, file)| (file.clone(), *hash))
+            .collect();
+    }
+}
+
+/// Points the entries using a file identical to an already stored one to the stored file,
+/// deleting the duplicate. Fallback icons are only merged between them, as they are
+/// recognized by their file name.
+pub fn deduplicate_icon_files(pack: &mut IconPack) {
+    let folder = pack.metadata.internal.path.clone();
+    let mut index = DEDUP_INDEX.lock();
+    // duplicate -> file kept
+    let mut replaced: HashMap<String, String> = HashMap::new();
+    let mut indexed = false;
+    let mut saved_bytes = 0;
+
+    for entry in &mut pack.entries {
+        let Some(icon) = entry_icon_mut(entry) else {
+            continue;
+        };
+        for file in icon_files_mut(icon) {
+            if let Some(kept) = replaced.get(file.as_str()) {
+                *file = kept.clone();
+                continue;
+            }
+            if index.hashes.contains_key(Path::new(file.as_str())) {
+                continue;
+            }
+            let Ok(content) = std::fs::read(folder.join(file.as_str())) else {
+                continue;
+            };
+
+            let hash = xxh3_64(&content);
+            let path = PathBuf::from(file.as_str());
+            let kept = index
+                .files
+                .get(&hash)
+                .map(|kept| kept.to_string_lossy().to_string())
+                .filter(|kept| folder.join(kept).exists());
+            match kept {
+                Some(kept)
+                    if kept.starts_with(FALLBACK_ICON_PREFIX)
+                        == file.starts_with(FALLBACK_ICON_PREFIX) =>
+                {
+                    saved_bytes += content.len();
+                    replaced.insert(file.clone(), kept.clone());
+                    *file = kept;
+                }
+                // same content as a file of the other kind, kept apart
+                Some(_) => {
+                    index.hashes.insert(path, hash);
+                }
+                None => {
+                    if let Some(previous) = index.files.insert(hash, path.clone()) {
+                        index.hashes.remove(&previous);
+                    }
+                    index.hashes.insert(path, hash);
+                    indexed = true;
+                }
+            }
+        }
+    }
+
+    delete_unreferenced_files(pack, replaced.keys().cloned());
+    if !replaced.is_empty() {
+        log::trace!(
+            "Deduplicated {} icon files, {} KiB saved",
+            replaced.len(),
+            saved_bytes / 1024
+        );
+    }
+    if indexed || !replaced.is_empty() {
+        DEDUP_INDEX.save();
+    }
+}
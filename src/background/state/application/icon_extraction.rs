@@ -0,0 +1,30 @@
+use crate::{error::Result, utils::constants::SEELEN_COMMON};
+
+use super::FullState;
+
+impl FullState {
+    fn _read_icon_extraction_settings(&mut self) -> Result<()> {
+        let path = SEELEN_COMMON.icon_extraction_settings_path();
+        if path.exists() {
+            self.icon_extraction = serde_yaml::from_str(&std::fs::read_to_string(path)?)?;
+        } else {
+            self.write_icon_extraction_settings()?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn read_icon_extraction_settings(&mut self) {
+        if let Err(err) = self._read_icon_extraction_settings() {
+            log::error!("Failed to read icon extraction settings: {err}");
+            Self::show_corrupted_state_to_user(SEELEN_COMMON.icon_extraction_settings_path());
+        }
+    }
+
+    pub fn write_icon_extraction_settings(&self) -> Result<()> {
+        std::fs::write(
+            SEELEN_COMMON.icon_extraction_settings_path(),
+            serde_yaml::to_string(&self.icon_extraction)?,
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use seelen_core::state::IconPack;
+
+use crate::{error::Result, trace_lock, utils::constants::SEELEN_COMMON};
+
+use super::{
+    icon_pack_archive::{entry_icon, icon_files},
+    IconPacksManager,
+};
+
+/// files written recently can belong to an extraction that is not yet registered on the pack
+const GC_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Unix timestamp of the last garbage collection
+fn last_collection_path() -> PathBuf {
+    SEELEN_COMMON.app_cache_dir().join("icons_gc.timestamp")
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// `interval_days` 0 disables the collection
+pub fn is_garbage_collection_due(interval_days: u32) -> bool {
+    if interval_days == 0 {
+        return false;
+    }
+    let last = std::fs::read_to_string(last_collection_path())
+        .ok()
+        .and_then(|content| content.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    now_secs().saturating_sub(last) >= interval_days as u64 * 24 * 60 * 60
+}
+
+/// Files referenced by the entries of the pack and its missing icon
+fn referenced_files(pack: &IconPack) -> HashSet<String> {
+    pack.entries
+        .iter()
+        .filter_map(entry_icon)
+        .chain(pack.missing.as_ref())
+        .flat_map(icon_files)
+        .cloned()
+        .collect()
+}
+
+/// Deletes the files of the pack folder that are no longer referenced by the pack,
+/// returning the deleted ones. Entries can share a file after deduplication, so files
+/// are never deleted along with an entry without going through here.
+pub fn delete_unreferenced_files(
+    pack: &IconPack,
+    files: impl IntoIterator<Item = String>,
+) -> Vec<String> {
+    let folder = &pack.metadata.internal.path;
+    let referenced = referenced_files(pack);
+    let mut deleted = Vec::new();
+    for file in files.into_iter().collect::<HashSet<_>>() {
+        if !referenced.contains(&file) && std::fs::remove_file(folder.join(&file)).is_ok() {
+            deleted.push(file);
+        }
+    }
+    deleted
+}
+
+impl IconPacksManager {
+    /// Folder of the system pack and the files referenced by its entries
+    fn referenced_system_files(&self) -> (PathBuf, HashSet<String>) {
+        let pack = self.get_system();
+        (pack.metadata.internal.path.clone(), referenced_files(pack))
+    }
+
+    /// Deletes the files on the system icon pack folder that are not referenced by any entry,
+    /// like the icons of uninstalled apps. Returns the amount of deleted files.
+    ///
+    /// The manager is only locked to read the referenced files, not while the folder is scanned.
+    pub fn collect_garbage(mutex: &Mutex<Self>) -> Result<u32> {
+        let (folder, referenced) = trace_lock!(mutex).referenced_system_files();
+        let candidates = unreferenced_files(&folder, &referenced, GC_GRACE_PERIOD)?;
+
+        // entries added during the scan could already be using some of the candidates
+        let (_, referenced) = trace_lock!(mutex).referenced_system_files();
+        let mut deleted = 0;
+        for name in candidates.iter().filter(|name| !referenced.contains(*name)) {
+            if std::fs::remove_file(folder.join(name)).is_ok() {
+                deleted += 1;
+            }
+        }
+
+        std::fs::write(last_collection_path(), now_secs().to_string())?;
+        Ok(deleted)
+    }
+}
+
+/// Files of the folder not referenced and not written during the `grace` period
+fn unreferenced_files(
+    folder: &Path,
+    referenced: &HashSet<String>,
+    grace: Duration,
+) -> Result<Vec<String>> {
+    let mut unreferenced = Vec::new();
+    for entry in std::fs::read_dir(folder)?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // pack metadata, its backups and markers
+        if name.starts_with("metadata.yml") || name.starts_with('.') {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let is_recent = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_none_or(|elapsed| elapsed < grace);
+        if !metadata.is_file() || is_recent || referenced.contains(&name) {
+            continue;
+        }
+        unreferenced.push(name);
+    }
+    Ok(unreferenced)
+}
+
+#[cfg(test)]
+mod tests {
+    use seelen_core::state::{Icon, IconPackEntry, UniqueIconPackEntry};
+
+    use super::*;
+    use crate::state::application::testing::TempFolder;
+
+    fn write_at(path: &Path, age: Duration) {
+        let file = std::fs::File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn only_old_unreferenced_files_are_collected() {
+        let folder = TempFolder::new("icon-gc-unreferenced");
+        let old = Duration::from_secs(2 * 60 * 60);
+        for name in [
+            "referenced.png",
+            "orphan.png",
+            "metadata.yml",
+            "metadata.yml.bak",
+            ".normalized_keys",
+        ] {
+            write_at(&folder.join(name), old);
+        }
+        write_at(&folder.join("just_extracted.png"), Duration::ZERO);
+        std::fs::create_dir(folder.join("rasterized")).unwrap();
+
+        let referenced = HashSet::from(["referenced.png".to_owned()]);
+        let candidates = unreferenced_files(folder.path(), &referenced, GC_GRACE_PERIOD).unwrap();
+        assert_eq!(candidates, ["orphan.png"]);
+    }
+
+    #[test]
+    fn files_shared_with_other_entries_are_not_deleted() {
+        let folder = TempFolder::new("icon-gc-shared");
+        for name in ["shared.png", "own.png", "missing.png"] {
+            write_at(&folder.join(name), Duration::ZERO);
+        }
+
+        let mut pack = IconPack::default();
+        pack.metadata.internal.path = folder.path().to_path_buf();
+        // the entry of the removed app used shared.png and own.png
+        pack.entries = vec![IconPackEntry::Unique(UniqueIconPackEntry {
+            umid: Some("Other.App".to_owned()),
+            path: None,
+            redirect: None,
+            icon: Some(Icon {
+                base: Some("shared.png".to_owned()),
+                ..Default::default()
+            }),
+        })];
+        pack.missing = Some(Icon {
+            base: Some("missing.png".to_owned()),
+            ..Default::default()
+        });
+
+        let removed = ["shared.png", "own.png", "own.png", "missing.png"].map(String::from);
+        let deleted = delete_unreferenced_files(&pack, removed);
+        assert_eq!(deleted, ["own.png"]);
+        assert!(folder.join("shared.png").exists());
+        assert!(folder.join("missing.png").exists());
+        assert!(!folder.join("own.png").exists());
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use seelen_core::state::{IconPack, IconPackEntry};
+
+use super::{
+    icon_gc::delete_unreferenced_files,
+    icon_pack_archive::{entry_icon, icon_files},
+};
+
+/// Key used to store and look up the icon of a file, so the same file always maps to the
+/// same entry no matter the casing, separators, 8.3 short names or subst drives used to reach it.
+pub fn normalize_icon_path(path: &Path) -> PathBuf {
+    // on windows canonicalize uses GetFinalPathNameByHandleW, it only works for existing files
+    let resolved = match std::fs::canonicalize(path) {
+        Ok(canonical) => strip_verbatim_prefix(&canonical),
+        Err(_) => lexical_normalize(path),
+    };
+    PathBuf::from(resolved.to_string_lossy().to_lowercase())
+}
+
+/// `\\?\C:\dir` -> `C:\dir` and `\\?\UNC\server\share` -> `\\server\share`
+fn strip_verbatim_prefix(path: &Path) -> PathBuf {
+    let path_str = path.to_string_lossy();
+    if let Some(rest) = path_str.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{rest}"));
+    }
+    match path_str.strip_prefix(r"\\?\") {
+        Some(rest) => PathBuf::from(rest),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Removes `.` and `..` components and unifies the separators, without touching the disk
+fn lexical_normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) {
+                    normalized.pop();
+                }
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// last time any of the image files of the entry was written
+fn entry_written_at(folder: &Path, entry: &IconPackEntry) -> Option<SystemTime> {
+    icon_files(entry_icon(entry)?)
+        .filter_map(|file| std::fs::metadata(folder.join(file)).ok()?.modified().ok())
+        .max()
+}
+
+/// Normalizes the path of the app entries and merges the ones that were duplicated by
+/// differences of case or format, keeping the most recently written one.
+/// Returns the number of removed entries.
+pub fn merge_duplicated_app_entries(pack: &mut IconPack) -> usize {
+    let folder = pack.metadata.internal.path.clone();
+
+    for entry in &mut pack.entries {
+        if let IconPackEntry::Unique(entry) = entry {
+            entry.path = entry.path.as_deref().map(normalize_icon_path);
+        }
+    }
+
+    // (umid, path) -> index of the entry kept
+    let mut kept: HashMap<(Option<String>, Option<PathBuf>), usize> = HashMap::new();
+    let mut removed = HashSet::new();
+    for (idx, entry) in pack.entries.iter().enumerate() {
+        let IconPackEntry::Unique(unique) = entry else {
+            continue;
+        };
+        let key = (unique.umid.clone(), unique.path.clone());
+        match kept.get(&key) {
+            Some(&previous) => {
+                let previous_written = entry_written_at(&folder, &pack.entries[previous]);
+                // on equal dates the later entry wins, as it was added after
+                if entry_written_at(&folder, entry) >= previous_written {
+                    removed.insert(previous);
+                    kept.insert(key, idx);
+                } else {
+                    removed.insert(idx);
+                }
+            }
+            None => {
+                kept.insert(key, idx);
+            }
+        }
+    }
+
+    if removed.is_empty() {
+        return 0;
+    }
+
+    let mut idx = 0;
+    let mut dropped = Vec::new();
+    pack.entries.retain(|entry| {
+        let keep = !removed.contains(&idx);
+        if !keep {
+            dropped.push(entry.clone());
+        }
+        idx += 1;
+        keep
+    });
+
+    // the images of the dropped entries are deleted if nothing else uses them
+    let files = dropped.iter().filter_map(entry_icon).flat_map(icon_files);
+    delete_unreferenced_files(pack, files.cloned());
+
+    dropped.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use seelen_core::state::{Icon, UniqueIconPackEntry};
+    use windows::Win32::Storage::FileSystem::GetShortPathNameW;
+
+    use super::*;
+    use crate::{
+        state::application::testing::TempFolder, windows_api::string_utils::WindowsString,
+    };
+
+    fn short_path(path: &Path) -> PathBuf {
+        let long = WindowsString::from_os_string(path.as_os_str());
+        let mut short = WindowsString::new_to_fill(1024);
+        let len = unsafe { GetShortPathNameW(long.as_pcwstr(), Some(short.as_mut_slice())) };
+        assert!(len > 0, "GetShortPathNameW failed for {path:?}");
+        PathBuf::from(short.to_os_string())
+    }
+
+    fn app_entry(path: &str, file: &str) -> IconPackEntry {
+        IconPackEntry::Unique(UniqueIconPackEntry {
+            umid: None,
+            path: Some(PathBuf::from(path)),
+            redirect: None,
+            icon: Some(Icon {
+                base: Some(file.to_owned()),
+                ..Default::default()
+            }),
+        })
+    }
+
+    fn write_at(path: &Path, age: Duration) {
+        let file = std::fs::File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn missing_files_are_normalized_lexically() {
+        let expected = PathBuf::from(r"c:\program files\slu-missing\app.exe");
+        for path in [
+            r"C:\Program Files\slu-missing\App.EXE",
+            r"c:/program files/slu-missing/app.exe",
+            r"C:\Program Files\.\slu-missing\sub\..\app.exe",
+        ] {
+            assert_eq!(normalize_icon_path(Path::new(path)), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn verbatim_prefixes_are_stripped() {
+        for (path, expected) in [
+            (r"\\?\C:\dir\app.exe", r"C:\dir\app.exe"),
+            (r"\\?\UNC\server\share\app.exe", r"\\server\share\app.exe"),
+            (r"C:\dir\app.exe", r"C:\dir\app.exe"),
+        ] {
+            assert_eq!(
+                strip_verbatim_prefix(Path::new(path)),
+                PathBuf::from(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn existing_files_share_the_key_of_their_short_path() {
+        let folder = TempFolder::new("icon-keys-Long Folder Name");
+        let file = folder.join("Long Application Name.exe");
+        std::fs::write(&file, b"").unwrap();
+
+        let key = normalize_icon_path(&file);
+        assert_eq!(key, PathBuf::from(key.to_string_lossy().to_lowercase()));
+        let upper = PathBuf::from(file.to_string_lossy().to_uppercase());
+        assert_eq!(normalize_icon_path(&upper), key);
+
+        // 8.3 names can be disabled on the volume, then the short path is the same
+        let short = short_path(&file);
+        assert_eq!(normalize_icon_path(&short), key, "{short:?}");
+    }
+
+    #[test]
+    fn duplicated_entries_keep_the_most_recently_written() {
+        let folder = TempFolder::new("icon-keys-merge");
+        write_at(&folder.join("old.png"), Duration::from_secs(3600));
+        write_at(&folder.join("new.png"), Duration::ZERO);
+        write_at(&folder.join("shared.png"), Duration::from_secs(3600));
+
+        let mut pack = IconPack::default();
+        pack.metadata.internal.path = folder.path().to_path_buf();
+        pack.entries = vec![
+            app_entry(r"C:\slu-missing\App.exe", "old.png"),
+            app_entry(r"c:/slu-missing/app.exe", "new.png"),
+            app_entry(r"C:\slu-missing\Other.exe", "shared.png"),
+            app_entry(r"c:\slu-missing\OTHER.exe", "shared.png"),
+        ];
+
+        assert_eq!(merge_duplicated_app_entries(&mut pack), 2);
+        let kept: Vec<&str> = pack
+            .entries
+            .iter()
+            .filter_map(entry_icon)
+            .flat_map(icon_files)
+            .map(String::as_str)
+            .collect();
+        assert_eq!(kept, ["new.png", "shared.png"]);
+        assert!(!folder.join("old.png").exists());
+        // still used by the kept entry
+        assert!(folder.join("shared.png").exists());
+    }
+}
@@ -0,0 +1,583 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use seelen_core::state::{Icon, IconPack, IconPackEntry};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{error::Result, utils::constants::SEELEN_COMMON};
+
+use super::{icon_svg::is_rasterized_file, icons::load_pack_or_backup, IconPacksManager};
+
+const ARCHIVE_METADATA: &str = "metadata.yml";
+
+/// limit of the archive and of its extracted content, so a zip bomb can't fill the disk
+const MAX_ARCHIVE_SIZE: u64 = 500 * 1024 * 1024;
+
+/// Entry of the imported pack that could not be imported as is.
+#[derive(Debug, Clone)]
+struct IconPackImportConflict {
+    /// umid, path, extension or key of the entry
+    entry: String,
+    file: String,
+    resolution: IconPackConflictResolution,
+}
+
+#[derive(Debug, Clone)]
+enum IconPackConflictResolution {
+    /// a different file with the same name already existed, so it was stored with this name
+    Renamed(String),
+    /// the file is not part of the archive, the entry was skipped
+    Skipped,
+}
+
+pub(super) fn icon_files(icon: &Icon) -> impl Iterator<Item = &String> {
+    [&icon.base, &icon.light, &icon.dark, &icon.mask]
+        .into_iter()
+        .flatten()
+}
+
+pub(super) fn icon_files_mut(icon: &mut Icon) -> impl Iterator<Item = &mut String> {
+    [
+        &mut icon.base,
+        &mut icon.light,
+        &mut icon.dark,
+        &mut icon.mask,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+pub(super) fn entry_icon(entry: &IconPackEntry) -> Option<&Icon> {
+    match entry {
+        IconPackEntry::Unique(entry) => entry.icon.as_ref(),
+        IconPackEntry::Shared(entry) => Some(&entry.icon),
+        IconPackEntry::Custom(entry) => Some(&entry.icon),
+    }
+}
+
+pub(super) fn entry_icon_mut(entry: &mut IconPackEntry) -> Option<&mut Icon> {
+    match entry {
+        IconPackEntry::Unique(entry) => entry.icon.as_mut(),
+        IconPackEntry::Shared(entry) => Some(&mut entry.icon),
+        IconPackEntry::Custom(entry) => Some(&mut entry.icon),
+    }
+}
+
+pub(super) fn entry_name(entry: &IconPackEntry) -> String {
+    match entry {
+        IconPackEntry::Unique(entry) => entry
+            .umid
+            .clone()
+            .or_else(|| entry.path.as_ref().map(|p| p.to_string_lossy().to_string()))
+            .unwrap_or_default(),
+        IconPackEntry::Shared(entry) => entry.extension.clone(),
+        IconPackEntry::Custom(entry) => entry.key.clone(),
+    }
+}
+
+pub(super) fn is_remote_file(file: &str) -> bool {
+    file.starts_with("http://") || file.starts_with("https://")
+}
+
+/// Only plain file names are allowed, so nothing can be written outside of the pack folder.
+fn is_flat_file_name(name: &str) -> bool {
+    let path = Path::new(name);
+    let mut components = path.components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(_)), None)
+    ) && !name.contains(['/', '\\', ':'])
+}
+
+/// Name not used on the folder, keeping the original name as prefix
+fn free_file_name(folder: &Path, file: &str) -> String {
+    let path = Path::new(file);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| format!("{stem}_{n}{ext}"))
+        .find(|name| !folder.join(name).exists())
+        .unwrap()
+}
+
+impl IconPacksManager {
+    /// Writes the pack metadata and the image files referenced by it as a zip archive,
+    /// `name` can be the pack id or the name of its folder.
+    pub fn export_icon_pack(&self, name: &str, destination: &Path) -> Result<PathBuf> {
+        let pack = self
+            .list()
+            .into_iter()
+            .find(|pack| {
+                pack.id.to_string() == name
+                    || pack
+                        .metadata
+                        .internal
+                        .path
+                        .file_name()
+                        .is_some_and(|folder| folder == name)
+            })
+            .ok_or_else(|| format!("Icon pack {name} not found"))?;
+        let folder = &pack.metadata.internal.path;
+
+        let destination = if destination.is_dir() {
+            let folder_name = folder.file_name().ok_or("Invalid icon pack folder")?;
+            destination.join(format!("{}.zip", folder_name.to_string_lossy()))
+        } else {
+            destination.to_path_buf()
+        };
+
+        write_archive(&exportable_pack(pack)?, &destination)?;
+        Ok(destination)
+    }
+
+    /// Imports an archive created by `export_icon_pack`. If a pack with the same id is already
+    /// installed the icons are added to it, renaming the files that collide.
+    /// The pack is registered right away, without waiting for the file watcher.
+    /// Returns the id of the imported pack.
+    pub fn import_pack(&mut self, archive: &Path) -> Result<String> {
+        let id = read_archive_metadata(archive)?.id.to_string();
+        let system_folder = SEELEN_COMMON.user_icons_path().join("system");
+        let installed = self
+            .list()
+            .into_iter()
+            .find(|installed| installed.id.to_string() == id)
+            .map(|installed| installed.metadata.internal.path.clone());
+        if installed.as_ref() == Some(&system_folder) {
+            return Err("The system icon pack can not be replaced".into());
+        }
+
+        let (folder, installed) = match installed {
+            // loaded from disk, the svg icons of the pack on memory point to their renders
+            Some(folder) => {
+                let installed = load_pack_or_backup(&folder)?;
+                (folder, Some(installed))
+            }
+            None => (
+                SEELEN_COMMON
+                    .user_icons_path()
+                    .join(crate::utils::date_based_hex_id()),
+                None,
+            ),
+        };
+
+        let (pack, conflicts) = extract_archive(archive, &folder, installed)?;
+        for conflict in conflicts {
+            log::warn!(
+                "Icon pack {id}: {} of {} was {:?}",
+                conflict.file,
+                conflict.entry,
+                conflict.resolution
+            );
+        }
+        pack.save()?;
+        self.reload_icon_pack(&folder)?;
+        Ok(id)
+    }
+}
+
+/// The svg icons of the packs on memory point to their renders, in that case the metadata is
+/// read again from disk so the archive contains the svg files and can be imported.
+fn exportable_pack(pack: &IconPack) -> Result<IconPack> {
+    let rasterized = pack
+        .entries
+        .iter()
+        .filter_map(entry_icon)
+        .flat_map(icon_files)
+        .any(|file| is_rasterized_file(file));
+    if rasterized {
+        load_pack_or_backup(&pack.metadata.internal.path)
+    } else {
+        Ok(pack.clone())
+    }
+}
+
+/// Writes the pack metadata and the files referenced by it, read from the pack folder.
+fn write_archive(pack: &IconPack, destination: &Path) -> Result<()> {
+    let folder = &pack.metadata.internal.path;
+    let referenced: HashSet<&String> = pack
+        .entries
+        .iter()
+        .filter_map(entry_icon)
+        .chain(pack.missing.as_ref())
+        .flat_map(icon_files)
+        .filter(|file| !is_remote_file(file) && folder.join(file).is_file())
+        .collect();
+
+    // images are already compressed, so they are only stored
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(File::create(destination)?);
+    zip.start_file(ARCHIVE_METADATA, options)?;
+    zip.write_all(serde_yaml::to_string(pack)?.as_bytes())?;
+
+    for file in referenced {
+        zip.start_file(file.as_str(), options)?;
+        std::io::copy(&mut File::open(folder.join(file))?, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Reads the bytes of an archive entry. Fails once they go over the `budget` left for the
+/// archive, as the sizes declared by the archive could be forged.
+fn read_capped(entry: impl Read, budget: &mut u64) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    entry.take(*budget + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > *budget {
+        return Err("Extracted icon pack would be bigger than 500 MB".into());
+    }
+    *budget -= bytes.len() as u64;
+    Ok(bytes)
+}
+
+fn open_archive(archive: &Path) -> Result<ZipArchive<File>> {
+    let file = File::open(archive)?;
+    if file.metadata()?.len() > MAX_ARCHIVE_SIZE {
+        return Err("Icon pack archive is bigger than 500 MB".into());
+    }
+    Ok(ZipArchive::new(file)?)
+}
+
+fn read_archive_metadata(archive: &Path) -> Result<IconPack> {
+    let mut zip = open_archive(archive)?;
+    let mut budget = MAX_ARCHIVE_SIZE;
+    let metadata = read_capped(zip.by_name(ARCHIVE_METADATA)?, &mut budget)?;
+    Ok(serde_yaml::from_slice(&metadata)?)
+}
+
+/// Extracts the files of the archive into `folder`, returning the pack to be saved on it.
+/// The entries are added to `installed` if the pack was already on the folder.
+fn extract_archive(
+    archive: &Path,
+    folder: &Path,
+    installed: Option<IconPack>,
+) -> Result<(IconPack, Vec<IconPackImportConflict>)> {
+    let mut zip = open_archive(archive)?;
+
+    let mut archive_files = HashSet::new();
+    for name in zip.file_names() {
+        if !is_flat_file_name(name) {
+            return Err(format!("Archive entry {name} points outside of the icon pack").into());
+        }
+        archive_files.insert(name.to_owned());
+    }
+
+    // declared sizes are checked first to fail before writing anything on common cases
+    let mut extracted_size = 0u64;
+    for idx in 0..zip.len() {
+        extracted_size = extracted_size.saturating_add(zip.by_index(idx)?.size());
+    }
+    if extracted_size > MAX_ARCHIVE_SIZE {
+        return Err("Extracted icon pack would be bigger than 500 MB".into());
+    }
+
+    let mut budget = MAX_ARCHIVE_SIZE;
+    let metadata = read_capped(zip.by_name(ARCHIVE_METADATA)?, &mut budget)?;
+    let mut pack: IconPack = serde_yaml::from_slice(&metadata)?;
+    std::fs::create_dir_all(folder)?;
+
+    let mut conflicts = Vec::new();
+    // archive file -> file stored on the pack folder
+    let mut stored: HashMap<String, String> = HashMap::new();
+    let mut import_file = |entry: &str, file: &str| -> Result<Option<String>> {
+        if let Some(name) = stored.get(file) {
+            return Ok(Some(name.clone()));
+        }
+        if file == ARCHIVE_METADATA || !archive_files.contains(file) {
+            conflicts.push(IconPackImportConflict {
+                entry: entry.to_owned(),
+                file: file.to_owned(),
+                resolution: IconPackConflictResolution::Skipped,
+            });
+            return Ok(None);
+        }
+
+        let bytes = read_capped(zip.by_name(file)?, &mut budget)?;
+        let target = folder.join(file);
+        let name = if target.exists() && std::fs::read(&target)? != bytes {
+            let name = free_file_name(folder, file);
+            conflicts.push(IconPackImportConflict {
+                entry: entry.to_owned(),
+                file: file.to_owned(),
+                resolution: IconPackConflictResolution::Renamed(name.clone()),
+            });
+            name
+        } else {
+            file.to_owned()
+        };
+
+        std::fs::write(folder.join(&name), &bytes)?;
+        stored.insert(file.to_owned(), name.clone());
+        Ok(Some(name))
+    };
+
+    let mut entries = Vec::new();
+    for mut entry in std::mem::take(&mut pack.entries) {
+        let name = entry_name(&entry);
+        let mut valid = true;
+        if let Some(icon) = entry_icon_mut(&mut entry) {
+            for file in icon_files_mut(icon).filter(|file| !is_remote_file(file)) {
+                match import_file(&name, file)? {
+                    Some(stored_name) => *file = stored_name,
+                    None => valid = false,
+                }
+            }
+        }
+        if valid {
+            entries.push(entry);
+        }
+    }
+
+    if let Some(mut missing) = pack.missing.take() {
+        let mut valid = true;
+        for file in icon_files_mut(&mut missing).filter(|file| !is_remote_file(file)) {
+            match import_file("missing", file)? {
+                Some(stored_name) => *file = stored_name,
+                None => valid = false,
+            }
+        }
+        pack.missing = valid.then_some(missing);
+    }
+
+    let pack = match installed {
+        Some(mut installed) => {
+            for entry in entries {
+                installed.add_entry(entry);
+            }
+            if installed.missing.is_none() {
+                installed.missing = pack.missing;
+            }
+            installed
+        }
+        None => {
+            pack.entries = entries;
+            pack.metadata.internal.path = folder.to_path_buf();
+            pack
+        }
+    };
+    Ok((pack, conflicts))
+}
+
+#[cfg(test)]
+mod tests {
+    use seelen_core::state::{SharedIconPackEntry, UniqueIconPackEntry};
+
+    use super::*;
+    use crate::state::application::{icon_svg::rasterize_pack_svgs, testing::TempFolder};
+
+    fn icon(base: &str) -> Icon {
+        Icon {
+            base: Some(base.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn app_entry(umid: &str, icon: Icon) -> IconPackEntry {
+        IconPackEntry::Unique(UniqueIconPackEntry {
+            umid: Some(umid.to_owned()),
+            path: None,
+            redirect: None,
+            icon: Some(icon),
+        })
+    }
+
+    fn pack_on(folder: &Path, entries: Vec<IconPackEntry>) -> IconPack {
+        let mut pack = IconPack {
+            id: "@test/icon-pack".into(),
+            ..Default::default()
+        };
+        pack.metadata.internal.path = folder.to_path_buf();
+        for entry in entries {
+            pack.add_entry(entry);
+        }
+        pack
+    }
+
+    fn find_icon<'a>(pack: &'a IconPack, name: &str) -> &'a Icon {
+        pack.entries
+            .iter()
+            .find(|entry| entry_name(entry) == name)
+            .and_then(entry_icon)
+            .unwrap_or_else(|| panic!("entry {name} not found"))
+    }
+
+    fn files(icon: &Icon) -> Vec<&str> {
+        icon_files(icon).map(String::as_str).collect()
+    }
+
+    #[test]
+    fn export_then_import_keeps_entries_and_files() {
+        let source = TempFolder::new("icon-pack-archive-roundtrip-source");
+        let target = TempFolder::new("icon-pack-archive-roundtrip-target");
+        std::fs::write(source.join("app.png"), b"app").unwrap();
+        std::fs::write(source.join("txt_light.png"), b"light").unwrap();
+        std::fs::write(source.join("txt_dark.png"), b"dark").unwrap();
+        std::fs::write(source.join("unused.png"), b"unused").unwrap();
+
+        let mut pack = pack_on(
+            source.path(),
+            vec![
+                app_entry("App.Id", icon("app.png")),
+                IconPackEntry::Shared(SharedIconPackEntry {
+                    extension: "txt".into(),
+                    icon: Icon {
+                        light: Some("txt_light.png".into()),
+                        dark: Some("txt_dark.png".into()),
+                        ..Default::default()
+                    },
+                }),
+            ],
+        );
+        pack.missing = Some(icon("app.png"));
+
+        let archive = target.join("pack.zip");
+        write_archive(&pack, &archive).unwrap();
+        let folder = target.join("imported");
+        let (imported, conflicts) = extract_archive(&archive, &folder, None).unwrap();
+
+        assert!(conflicts.is_empty(), "{conflicts:?}");
+        assert_eq!(imported.id.to_string(), pack.id.to_string());
+        assert_eq!(imported.metadata.internal.path, folder);
+        assert_eq!(imported.entries.len(), 2);
+        assert_eq!(files(find_icon(&imported, "App.Id")), ["app.png"]);
+        assert_eq!(
+            files(find_icon(&imported, "txt")),
+            ["txt_light.png", "txt_dark.png"]
+        );
+        assert_eq!(files(imported.missing.as_ref().unwrap()), ["app.png"]);
+        for (file, bytes) in [
+            ("app.png", &b"app"[..]),
+            ("txt_light.png", b"light"),
+            ("txt_dark.png", b"dark"),
+        ] {
+            assert_eq!(std::fs::read(folder.join(file)).unwrap(), bytes);
+        }
+        // only the referenced files are exported
+        assert!(!folder.join("unused.png").exists());
+    }
+
+    #[test]
+    fn import_into_installed_pack_keeps_its_icons() {
+        let source = TempFolder::new("icon-pack-archive-merge-source");
+        let installed_folder = TempFolder::new("icon-pack-archive-merge-installed");
+        std::fs::write(source.join("new.png"), b"new").unwrap();
+        std::fs::write(source.join("shared.png"), b"from archive").unwrap();
+        std::fs::write(installed_folder.join("old.png"), b"old").unwrap();
+        std::fs::write(installed_folder.join("shared.png"), b"installed").unwrap();
+
+        let pack = pack_on(
+            source.path(),
+            vec![
+                app_entry("New.App", icon("new.png")),
+                app_entry("Shared.App", icon("shared.png")),
+            ],
+        );
+        let installed = pack_on(
+            installed_folder.path(),
+            vec![
+                app_entry("Old.App", icon("old.png")),
+                app_entry("Installed.App", icon("shared.png")),
+            ],
+        );
+
+        let archive = source.join("pack.zip");
+        write_archive(&pack, &archive).unwrap();
+        let (merged, conflicts) =
+            extract_archive(&archive, installed_folder.path(), Some(installed)).unwrap();
+
+        assert_eq!(merged.entries.len(), 4);
+        assert_eq!(files(find_icon(&merged, "Old.App")), ["old.png"]);
+        assert_eq!(files(find_icon(&merged, "Installed.App")), ["shared.png"]);
+        assert_eq!(files(find_icon(&merged, "New.App")), ["new.png"]);
+        assert_eq!(files(find_icon(&merged, "Shared.App")), ["shared_1.png"]);
+
+        assert_eq!(conflicts.len(), 1);
+        assert!(matches!(
+            &conflicts[0].resolution,
+            IconPackConflictResolution::Renamed(name) if name == "shared_1.png"
+        ));
+        let read = |file: &str| std::fs::read(installed_folder.join(file)).unwrap();
+        assert_eq!(read("shared.png"), b"installed");
+        assert_eq!(read("shared_1.png"), b"from archive");
+    }
+
+    #[test]
+    fn entries_with_files_missing_from_the_archive_are_skipped() {
+        let source = TempFolder::new("icon-pack-archive-skipped-source");
+        std::fs::write(source.join("app.png"), b"app").unwrap();
+        let pack = pack_on(
+            source.path(),
+            vec![
+                app_entry("App.Id", icon("app.png")),
+                app_entry("Broken.App", icon("deleted.png")),
+            ],
+        );
+
+        let archive = source.join("pack.zip");
+        write_archive(&pack, &archive).unwrap();
+        let folder = source.join("imported");
+        let (imported, conflicts) = extract_archive(&archive, &folder, None).unwrap();
+
+        assert_eq!(imported.entries.len(), 1);
+        assert_eq!(entry_name(&imported.entries[0]), "App.Id");
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].entry, "Broken.App");
+        assert!(matches!(
+            conflicts[0].resolution,
+            IconPackConflictResolution::Skipped
+        ));
+    }
+
+    #[test]
+    fn exported_svg_icons_are_imported_as_svg() {
+        let source = TempFolder::new("icon-pack-archive-svg-source");
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16"><rect width="16" height="16" fill="red"/></svg>"#;
+        std::fs::write(source.join("logo.svg"), svg).unwrap();
+        let pack = pack_on(source.path(), vec![app_entry("App.Id", icon("logo.svg"))]);
+        pack.save().unwrap();
+
+        // same as the pack kept on memory by the manager
+        let mut loaded = load_pack_or_backup(source.path()).unwrap();
+        rasterize_pack_svgs(&mut loaded);
+        let rendered = files(find_icon(&loaded, "App.Id"))[0].to_owned();
+        assert!(is_rasterized_file(&rendered), "{rendered}");
+
+        let archive = source.join("pack.zip");
+        write_archive(&exportable_pack(&loaded).unwrap(), &archive).unwrap();
+        let folder = source.join("imported");
+        let (imported, conflicts) = extract_archive(&archive, &folder, None).unwrap();
+
+        assert!(conflicts.is_empty(), "{conflicts:?}");
+        assert_eq!(files(find_icon(&imported, "App.Id")), ["logo.svg"]);
+        assert_eq!(std::fs::read(folder.join("logo.svg")).unwrap(), svg);
+    }
+
+    #[test]
+    fn reads_are_capped_by_the_budget() {
+        let mut budget = 4;
+        assert_eq!(read_capped(&b"abc"[..], &mut budget).unwrap(), b"abc");
+        assert_eq!(budget, 1);
+        assert!(read_capped(&b"ab"[..], &mut budget).is_err());
+    }
+
+    #[test]
+    fn only_flat_file_names_are_allowed() {
+        assert!(is_flat_file_name("icon.png"));
+        for name in [
+            "../icon.png",
+            "sub/icon.png",
+            "sub\\icon.png",
+            "C:icon.png",
+            "/icon.png",
+            "..",
+        ] {
+            assert!(!is_flat_file_name(name), "{name}");
+        }
+    }
+}
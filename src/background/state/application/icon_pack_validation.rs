@@ -0,0 +1,157 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Serialize;
+
+use crate::error::Result;
+
+use super::{
+    icon_gc::delete_unreferenced_files,
+    icon_pack_archive::{entry_icon, entry_name, icon_files, is_remote_file},
+    IconPacksManager,
+};
+
+/// bigger images are not expected on the system pack, extracted icons are at most 256px
+const MAX_ICON_SIDE: u32 = 4096;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum IconFileProblem {
+    Missing,
+    Empty,
+    Undecodable { error: String },
+    InvalidSize { width: u32, height: u32 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconFileIssue {
+    /// umid, path, extension or key of the entry
+    pub entry: String,
+    pub file: String,
+    pub problem: IconFileProblem,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconPackValidationReport {
+    pub checked_files: usize,
+    pub issues: Vec<IconFileIssue>,
+    /// entries dropped on repair, they will be extracted again when needed
+    pub removed_entries: usize,
+    /// undecodable files deleted on repair
+    pub deleted_files: Vec<String>,
+}
+
+/// `decode` reads the whole image, otherwise only the header is read.
+fn check_icon_file(path: &Path, decode: bool) -> Option<IconFileProblem> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Some(IconFileProblem::Missing),
+    };
+    if metadata.len() == 0 {
+        return Some(IconFileProblem::Empty);
+    }
+
+    // svg can't be read by the image crate
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"))
+    {
+        return None;
+    }
+
+    let dimensions = match decode {
+        true => image::open(path).map(|image| (image.width(), image.height())),
+        false => image::image_dimensions(path),
+    };
+    match dimensions {
+        Ok((width, height)) => {
+            if width == 0 || height == 0 || width > MAX_ICON_SIDE || height > MAX_ICON_SIDE {
+                return Some(IconFileProblem::InvalidSize { width, height });
+            }
+            None
+        }
+        Err(err) => Some(IconFileProblem::Undecodable {
+            error: err.to_string(),
+        }),
+    }
+}
+
+impl IconPacksManager {
+    /// Checks that every file referenced by the system icon pack exists, is decodable and
+    /// has sane dimensions. On `repair` the broken entries are dropped and the undecodable
+    /// files are deleted.
+    pub fn validate_icon_pack(&mut self, repair: bool) -> Result<IconPackValidationReport> {
+        self._validate_icon_pack(repair, true)
+    }
+
+    /// Repair pass reading only the headers of the images, intended to run on startup
+    pub fn quick_repair_icon_pack(&mut self) -> Result<IconPackValidationReport> {
+        self._validate_icon_pack(true, false)
+    }
+
+    fn _validate_icon_pack(
+        &mut self,
+        repair: bool,
+        decode: bool,
+    ) -> Result<IconPackValidationReport> {
+        let pack = self.get_system_mut();
+        let folder = pack.metadata.internal.path.clone();
+
+        let mut report = IconPackValidationReport::default();
+        // files are shared between entries, so each one is checked once
+        let mut checked: HashMap<String, Option<IconFileProblem>> = HashMap::new();
+
+        let mut broken_entries = Vec::new();
+        for (idx, entry) in pack.entries.iter().enumerate() {
+            let Some(icon) = entry_icon(entry) else {
+                continue;
+            };
+
+            let mut is_broken = false;
+            for file in icon_files(icon).filter(|file| !is_remote_file(file)) {
+                let problem = checked
+                    .entry(file.clone())
+                    .or_insert_with(|| check_icon_file(&folder.join(file), decode));
+                if let Some(problem) = problem {
+                    is_broken = true;
+                    report.issues.push(IconFileIssue {
+                        entry: entry_name(entry),
+                        file: file.clone(),
+                        problem: problem.clone(),
+                    });
+                }
+            }
+
+            if is_broken {
+                broken_entries.push(idx);
+            }
+        }
+        report.checked_files = checked.len();
+
+        if !repair || report.issues.is_empty() {
+            return Ok(report);
+        }
+
+        let mut idx = 0;
+        pack.entries.retain(|_| {
+            let keep = !broken_entries.contains(&idx);
+            idx += 1;
+            keep
+        });
+        report.removed_entries = broken_entries.len();
+
+        let undecodable = checked.into_iter().filter_map(|(file, problem)| {
+            matches!(
+                problem,
+                Some(IconFileProblem::Empty | IconFileProblem::Undecodable { .. })
+            )
+            .then_some(file)
+        });
+        report.deleted_files = delete_unreferenced_files(pack, undecodable);
+
+        self.write_system_icon_pack()?;
+        self.flush_system_icon_pack()?;
+        Ok(report)
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::HashSet,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+};
+
+use image::RgbaImage;
+use resvg::{tiny_skia, usvg};
+use seelen_core::state::IconPack;
+
+use crate::{error::Result, log_error, utils::icon_extractor::generate_fallback_icon};
+
+use super::icon_pack_archive::{entry_icon_mut, icon_files_mut};
+
+/// folder of the pack where the png renders of its svg icons are cached
+static RASTERIZED_FOLDER: &str = "rasterized";
+
+/// size of the renders, the same used for the extracted icons
+const SVG_RASTER_SIZE: u32 = 256;
+
+fn is_svg_file(file: &str) -> bool {
+    Path::new(file)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"))
+}
+
+/// Whether the file is a render of a svg icon, instead of a file of the pack
+pub fn is_rasterized_file(file: &str) -> bool {
+    file.strip_prefix(RASTERIZED_FOLDER)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Renders the svg centered on a transparent square keeping its aspect ratio
+fn rasterize_svg(content: &[u8], size: u32) -> Result<RgbaImage> {
+    let tree = usvg::Tree::from_data(content, &usvg::Options::default())?;
+    let svg_size = tree.size();
+    let scale = size as f32 / svg_size.width().max(svg_size.height());
+    let offset_x = (size as f32 - svg_size.width() * scale) / 2.0;
+    let offset_y = (size as f32 - svg_size.height() * scale) / 2.0;
+
+    let mut pixmap = tiny_skia::Pixmap::new(size, size).ok_or("Invalid svg render size")?;
+    let transform =
+        tiny_skia::Transform::from_scale(scale, scale).post_translate(offset_x, offset_y);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia works with premultiplied alpha, images are stored with straight alpha
+    let mut image = RgbaImage::new(size, size);
+    for (pixel, rendered) in image.pixels_mut().zip(pixmap.pixels()) {
+        let color = rendered.demultiply();
+        pixel.0 = [color.red(), color.green(), color.blue(), color.alpha()];
+    }
+    Ok(image)
+}
+
+/// Returns the render of the svg relative to the pack folder, rendering it if the svg changed
+/// since the last time. Malformed svgs are replaced by the letter-avatar fallback icon.
+fn resolve_svg_file(folder: &Path, file: &str) -> Result<String> {
+    let content = std::fs::read(folder.join(file))?;
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    let name = format!("{:016x}_{SVG_RASTER_SIZE}.png", hasher.finish());
+    let relative = format!("{RASTERIZED_FOLDER}/{name}");
+
+    let rasterized = folder.join(RASTERIZED_FOLDER).join(&name);
+    if rasterized.exists() {
+        return Ok(relative);
+    }
+
+    let image = match rasterize_svg(&content, SVG_RASTER_SIZE) {
+        Ok(image) => image,
+        Err(err) => {
+            log::warn!("Invalid svg icon {:?}: {err:?}", folder.join(file));
+            let stem = Path::new(file).file_stem().unwrap_or_default();
+            generate_fallback_icon(&stem.to_string_lossy())
+        }
+    };
+    std::fs::create_dir_all(folder.join(RASTERIZED_FOLDER))?;
+    image.save(&rasterized)?;
+    Ok(relative)
+}
+
+/// Points the svg icons of the pack to their png renders, only on memory so the metadata
+/// keeps referencing the svg files. Renders no longer used by the pack are deleted.
+pub fn rasterize_pack_svgs(pack: &mut IconPack) {
+    let folder = pack.metadata.internal.path.clone();
+    let mut used = HashSet::new();
+
+    for entry in &mut pack.entries {
+        let Some(icon) = entry_icon_mut(entry) else {
+            continue;
+        };
+        for file in icon_files_mut(icon).filter(|file| is_svg_file(file)) {
+            match resolve_svg_file(&folder, file) {
+                Ok(rasterized) => {
+                    used.insert(rasterized.clone());
+                    *file = rasterized;
+                }
+                Err(err) => log::warn!("Failed to rasterize {:?}: {err:?}", folder.join(&*file)),
+            }
+        }
+    }
+
+    let Ok(renders) = std::fs::read_dir(folder.join(RASTERIZED_FOLDER)) else {
+        return;
+    };
+    for render in renders.flatten() {
+        let name = render.file_name().to_string_lossy().to_string();
+        if !used.contains(&format!("{RASTERIZED_FOLDER}/{name}")) {
+            log_error!(std::fs::remove_file(render.path()));
+        }
+    }
+}
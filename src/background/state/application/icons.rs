@@ -1,10 +1,14 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Write,
     path::{Path, PathBuf},
-    sync::LazyLock,
+    sync::{Arc, LazyLock},
+    time::Duration,
 };
 
 use itertools::Itertools;
+use parking_lot::Mutex;
 use seelen_core::{
     handlers::SeelenEvent,
     resource::{ResourceText, SluResource},
@@ -18,43 +22,212 @@ use tauri::Emitter;
 use crate::{
     app::get_app_handle,
     error::Result,
-    trace_lock,
-    utils::{constants::SEELEN_COMMON, date_based_hex_id},
+    event_manager, log_error, trace_lock,
+    utils::{
+        constants::SEELEN_COMMON,
+        date_based_hex_id,
+        icon_extractor::{
+            clear_all_icon_metadata, clear_all_without_icon, get_stored_icon_metadata,
+            is_aproximately_a_square, record_icon_colors, IconExtractionKey, IconMetadata,
+            MetricStage, StageTimer, FALLBACK_ICON_PREFIX,
+        },
+        Debouncer,
+    },
+    windows_api::WindowsApi,
 };
 
-use super::FullState;
+use super::{
+    icon_dedup::deduplicate_icon_files,
+    icon_gc::delete_unreferenced_files,
+    icon_keys::{merge_duplicated_app_entries, normalize_icon_path},
+    icon_pack_archive::icon_files,
+    icon_svg::rasterize_pack_svgs,
+    FullState, FULL_STATE,
+};
 
 static SYSTEM_ICONS: LazyLock<PathBuf> =
     LazyLock::new(|| SEELEN_COMMON.user_icons_path().join("system"));
 
+/// icons set by the user, kept apart so extractions never override them
+static USER_ICONS: LazyLock<PathBuf> =
+    LazyLock::new(|| SEELEN_COMMON.user_icons_path().join("user"));
+static USER_ICON_PACK_ID: &str = "@user/icon-pack";
+
+/// extractions come in bursts, so the system pack is written once they settle
+const SYSTEM_PACK_WRITE_DELAY: Duration = Duration::from_millis(500);
+static SYSTEM_PACK_WRITE_DEBOUNCER: LazyLock<Debouncer> =
+    LazyLock::new(|| Debouncer::new(SYSTEM_PACK_WRITE_DELAY));
+
+/// written once the app entries of the packs were migrated to normalized path keys
+static NORMALIZED_KEYS_MARKER: &str = ".normalized_keys";
+
+/// shortcuts can point to other shortcuts, the limit avoids looping on cycles
+const MAX_REDIRECT_DEPTH: usize = 4;
+
+static PACK_METADATA: &str = "metadata.yml";
+static PACK_METADATA_TMP: &str = "metadata.yml.tmp";
+static PACK_METADATA_BACKUP: &str = "metadata.yml.bak";
+
+/// Writes the pack metadata to a temporal file and then replaces the current one with it,
+/// so a crash while writing never leaves a truncated pack, the previous version is kept as backup.
+/// Returns the hash of the written metadata.
+fn write_pack_atomically(pack: &IconPack) -> Result<u64> {
+    let folder = &pack.metadata.internal.path;
+    let target = folder.join(PACK_METADATA);
+    let temp = folder.join(PACK_METADATA_TMP);
+
+    let content = serde_yaml::to_string(pack)?;
+    let mut file = std::fs::File::create(&temp)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    if target.exists() {
+        WindowsApi::replace_file(&target, &temp, &folder.join(PACK_METADATA_BACKUP))?;
+    } else {
+        std::fs::rename(&temp, &target)?;
+    }
+    Ok(hash_metadata(content.as_bytes()))
+}
+
+fn hash_metadata(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Folder of the icon pack whose metadata changed, the path can also be the folder itself
+/// when the whole pack was added or removed.
+pub(super) fn changed_icon_pack_folder(path: &Path) -> Option<PathBuf> {
+    let icons_path = SEELEN_COMMON.user_icons_path();
+    let relative = path.strip_prefix(icons_path).ok()?;
+    let mut components = relative.components();
+    let folder = icons_path.join(components.next()?);
+    match components.next() {
+        None => Some(folder),
+        Some(file) if file.as_os_str() == PACK_METADATA && components.next().is_none() => {
+            Some(folder)
+        }
+        _ => None,
+    }
+}
+
+/// Loads the pack, restoring the backup of the metadata if the current one is corrupted
+pub(super) fn load_pack_or_backup(path: &Path) -> Result<IconPack> {
+    let err = match IconPack::load(path) {
+        Ok(icon_pack) => return Ok(icon_pack),
+        Err(err) => err,
+    };
+
+    let backup = path.join(PACK_METADATA_BACKUP);
+    if !backup.exists() {
+        return Err(err.into());
+    }
+    log::warn!("Icon pack ({path:?}) is corrupted, restoring backup: {err:?}");
+    std::fs::copy(&backup, path.join(PACK_METADATA))?;
+    Ok(IconPack::load(path)?)
+}
+
+/// The system and user packs are written by us and never reference svg files,
+/// the rest get their svg icons rasterized for the frontend.
+fn prepare_loaded_pack(icon_pack: &mut IconPack) {
+    let path = icon_pack.metadata.internal.path.as_path();
+    if path != SYSTEM_ICONS.as_path() && path != USER_ICONS.as_path() {
+        rasterize_pack_svgs(icon_pack);
+    }
+}
+
+/// Replaces the app entries of `reloaded` changed since the last write with the ones of `current`,
+/// the removed ones are removed too. Other entries are kept as they are on `reloaded`.
+fn merge_pending_app_entries(
+    reloaded: &mut IconPack,
+    current: &IconPack,
+    changed: &HashSet<IconExtractionKey>,
+) {
+    let mut umids = HashSet::new();
+    let mut paths = HashSet::new();
+    for key in changed {
+        match key {
+            IconExtractionKey::Umid(umid) => umids.insert(umid.as_str()),
+            IconExtractionKey::Path(path) => paths.insert(normalize_icon_path(path)),
+        };
+    }
+    let is_changed = |entry: &IconPackEntry| match entry {
+        IconPackEntry::Unique(entry) => {
+            entry
+                .umid
+                .as_deref()
+                .is_some_and(|umid| umids.contains(umid))
+                || entry.path.as_ref().is_some_and(|path| paths.contains(path))
+        }
+        _ => false,
+    };
+
+    reloaded.entries.retain(|entry| !is_changed(entry));
+    reloaded.entries.extend(
+        current
+            .entries
+            .iter()
+            .filter(|entry| is_changed(entry))
+            .cloned(),
+    );
+}
+
 #[derive(Debug, Clone, Default)]
-pub struct IconPacksManager(HashMap<PathBuf, IconPack>);
+pub struct IconPacksManager {
+    packs: HashMap<PathBuf, IconPack>,
+    pending_writes: PendingWrites,
+    /// normalized paths of deleted shortcuts, their entries and files are kept until they come back
+    /// or the user clears the system icons, as the user could have customized them
+    stale_paths: HashSet<PathBuf>,
+}
+
+/// Writes of the system icon pack are debounced, and deferred while a batch is running
+#[derive(Debug, Clone, Default)]
+struct PendingWrites {
+    batches: usize,
+    dirty: bool,
+    /// hash of the last metadata written by us, to ignore our own changes on the file watcher
+    written_hash: Option<u64>,
+    /// apps whose entries changed since the last write
+    changed_keys: HashSet<IconExtractionKey>,
+}
+
+/// Sent after each write of the system icon pack
+#[derive(Debug, Clone)]
+pub enum IconPacksEvent {
+    /// apps whose icon changed, empty if only file type icons changed
+    SystemPackWritten(Vec<IconExtractionKey>),
+}
+
+event_manager!(IconPacksManager, IconPacksEvent);
 
 impl IconPacksManager {
     pub fn list(&self) -> Vec<&IconPack> {
-        self.0.values().collect_vec()
+        self.packs.values().collect_vec()
     }
 
     pub fn owned_list(&self) -> Vec<IconPack> {
-        self.0.values().cloned().collect_vec()
+        self.packs.values().cloned().collect_vec()
     }
 
     pub fn get_system(&self) -> &IconPack {
-        self.0.get(SYSTEM_ICONS.as_path()).unwrap()
+        self.packs.get(SYSTEM_ICONS.as_path()).unwrap()
     }
 
     pub fn get_system_mut(&mut self) -> &mut IconPack {
-        self.0.get_mut(SYSTEM_ICONS.as_path()).unwrap()
+        self.packs.get_mut(SYSTEM_ICONS.as_path()).unwrap()
     }
 
     pub fn add_system_app_icon(&mut self, umid: Option<&str>, path: Option<&Path>, icon: Icon) {
         if umid.is_none() && path.is_none() {
             return;
         }
+        self.mark_changed(umid, path);
         let system_pack = self.get_system_mut();
         system_pack.add_entry(IconPackEntry::Unique(UniqueIconPackEntry {
             umid: umid.map(|s| s.to_string()),
-            path: path.map(|p| p.to_path_buf()),
+            path: path.map(normalize_icon_path),
             redirect: None,
             icon: Some(icon),
         }));
@@ -66,15 +239,139 @@ impl IconPacksManager {
         origin: &Path,
         redirect: &Path,
     ) {
+        self.mark_changed(umid.as_deref(), Some(origin));
         let system_pack = self.get_system_mut();
         system_pack.add_entry(IconPackEntry::Unique(UniqueIconPackEntry {
             umid,
-            path: Some(origin.to_path_buf()),
+            path: Some(normalize_icon_path(origin)),
             redirect: Some(redirect.to_path_buf()),
             icon: None,
         }));
     }
 
+    /// Removes the app entries registered for the path, used when the file no longer exists
+    #[allow(dead_code)]
+    pub fn remove_system_app_icon(&mut self, path: &Path) {
+        self.mark_changed(None, Some(path));
+        let key = normalize_icon_path(path);
+        self.get_system_mut().entries.retain(|entry| match entry {
+            IconPackEntry::Unique(entry) => entry.path.as_ref() != Some(&key),
+            _ => true,
+        });
+    }
+
+    /// Marks the entries of a deleted file as stale, they are kept as is so the stored icon
+    /// survives in case the file is restored, but are no longer refreshed.
+    pub fn mark_system_app_icon_stale(&mut self, path: &Path) {
+        self.stale_paths.insert(normalize_icon_path(path));
+    }
+
+    pub fn unmark_system_app_icon_stale(&mut self, path: &Path) {
+        self.stale_paths.remove(&normalize_icon_path(path));
+    }
+
+    fn is_stale_entry(&self, entry: &UniqueIconPackEntry) -> bool {
+        entry
+            .path
+            .as_ref()
+            .is_some_and(|path| self.stale_paths.contains(path))
+    }
+
+    /// Keys of the app entries of the system pack with their own icon, redirects are skipped
+    /// because they follow their target, and stale ones because their file no longer exists.
+    pub fn system_app_keys(&self) -> Vec<IconExtractionKey> {
+        self.get_system()
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                IconPackEntry::Unique(entry)
+                    if entry.redirect.is_none() && !self.is_stale_entry(entry) =>
+                {
+                    match (&entry.umid, &entry.path) {
+                        (Some(umid), _) => Some(IconExtractionKey::Umid(umid.clone())),
+                        (None, Some(path)) => Some(IconExtractionKey::Path(path.clone())),
+                        (None, None) => None,
+                    }
+                }
+                _ => None,
+            })
+            .unique()
+            .collect()
+    }
+
+    /// Removes the entries of the app so it can be extracted again, without writing the pack.
+    /// They are given back to `restore_system_app_entries` once the extraction finished.
+    pub fn take_system_app_entries(&mut self, key: &IconExtractionKey) -> Vec<IconPackEntry> {
+        self.pending_writes.changed_keys.insert(key.clone());
+        let (umid, path) = match key {
+            IconExtractionKey::Umid(umid) => (Some(umid.as_str()), None),
+            IconExtractionKey::Path(path) => (None, Some(normalize_icon_path(path))),
+        };
+        let (taken, kept) = std::mem::take(&mut self.get_system_mut().entries)
+            .into_iter()
+            .partition(|entry| match entry {
+                IconPackEntry::Unique(entry) => match umid {
+                    Some(umid) => entry.umid.as_deref() == Some(umid),
+                    None => entry.path.is_some() && entry.path == path,
+                },
+                _ => false,
+            });
+        self.get_system_mut().entries = kept;
+        taken
+    }
+
+    /// Puts back the entries taken by `take_system_app_entries` if the extraction didn't
+    /// store a new icon, so a failed re-extraction keeps the previous icon instead of a fallback.
+    pub fn restore_system_app_entries(
+        &mut self,
+        key: &IconExtractionKey,
+        entries: Vec<IconPackEntry>,
+    ) -> Result<()> {
+        let (umid, path) = match key {
+            IconExtractionKey::Umid(umid) => (Some(umid.as_str()), None),
+            IconExtractionKey::Path(path) => (None, Some(path.as_path())),
+        };
+        if entries.is_empty() || self.has_app_icon(umid, path) {
+            return Ok(());
+        }
+        self.take_system_app_entries(key);
+        self.get_system_mut().entries.extend(entries);
+        self.write_system_icon_pack()
+    }
+
+    /// Removes the entries of the apps of the package, returns true if any was removed
+    pub fn remove_package_app_icons(&mut self, package_family_name: &str) -> bool {
+        let prefix = format!("{package_family_name}!");
+        let mut removed = Vec::new();
+        self.get_system_mut().entries.retain(|entry| match entry {
+            IconPackEntry::Unique(entry) => match &entry.umid {
+                Some(umid) if umid.starts_with(&prefix) => {
+                    removed.push(IconExtractionKey::Umid(umid.clone()));
+                    false
+                }
+                _ => true,
+            },
+            _ => true,
+        });
+        let any_removed = !removed.is_empty();
+        self.pending_writes.changed_keys.extend(removed);
+        any_removed
+    }
+
+    /// Moves the app entries registered for `from` to `to`, avoiding extracting the icon again
+    pub fn rename_system_app_icon(&mut self, from: &Path, to: &Path) {
+        self.mark_changed(None, Some(from));
+        self.mark_changed(None, Some(to));
+        let (from, to) = (normalize_icon_path(from), normalize_icon_path(to));
+        for entry in &mut self.get_system_mut().entries {
+            if let IconPackEntry::Unique(entry) = entry {
+                if entry.path.as_ref() == Some(&from) {
+                    entry.path = Some(to.clone());
+                }
+            }
+        }
+    }
+
     pub fn add_system_file_icon(&mut self, origin_extension: &str, icon: Icon) {
         let system_pack = self.get_system_mut();
         system_pack.add_entry(IconPackEntry::Shared(SharedIconPackEntry {
@@ -83,6 +380,19 @@ impl IconPacksManager {
         }));
     }
 
+    fn mark_changed(&mut self, umid: Option<&str>, path: Option<&Path>) {
+        if let Some(umid) = umid {
+            self.pending_writes
+                .changed_keys
+                .insert(IconExtractionKey::Umid(umid.to_string()));
+        }
+        if let Some(path) = path {
+            self.pending_writes
+                .changed_keys
+                .insert(IconExtractionKey::Path(path.to_path_buf()));
+        }
+    }
+
     fn icon_exists(&self, icon: &Icon) -> bool {
         icon.base
             .as_ref()
@@ -97,47 +407,117 @@ impl IconPacksManager {
                     .is_some_and(|p| SYSTEM_ICONS.join(p).exists()))
     }
 
-    /// Get icon pack by app user model id, filename or path
-    pub fn has_app_icon(&self, umid: Option<&str>, path: Option<&Path>) -> bool {
-        let icon_pack = self.get_system();
-        let lower_path = path.map(|p| p.to_string_lossy().to_lowercase());
-
-        for entry in &icon_pack.entries {
+    /// Entries of the system icon pack matching the app user model id or the path
+    fn find_app_entries<'a>(
+        &'a self,
+        umid: Option<&'a str>,
+        path: Option<&Path>,
+    ) -> impl Iterator<Item = &'a UniqueIconPackEntry> + 'a {
+        let key = path.map(normalize_icon_path);
+        self.get_system().entries.iter().filter_map(move |entry| {
             let IconPackEntry::Unique(entry) = entry else {
-                continue;
+                return None;
             };
 
-            let mut found = None;
             if let (Some(entry_umid), Some(umid)) = (&entry.umid, umid) {
                 if entry_umid == umid {
-                    found = Some(entry);
+                    return Some(entry);
                 }
             }
 
-            if found.is_none()
-                && entry
-                    .path
-                    .as_ref()
-                    .map(|p| p.to_string_lossy().to_lowercase())
-                    == lower_path
-            {
-                found = Some(entry);
+            if entry.path == key {
+                return Some(entry);
             }
+            None
+        })
+    }
+
+    /// Generated letter-avatar icons are marked by their file name
+    pub fn is_fallback_icon(icon: &Icon) -> bool {
+        icon.base
+            .as_ref()
+            .is_some_and(|base| base.starts_with(FALLBACK_ICON_PREFIX))
+    }
+
+    /// Get icon pack by app user model id, filename or path
+    ///
+    /// Fallback icons are ignored, so a later extraction can replace them.
+    pub fn has_app_icon(&self, umid: Option<&str>, path: Option<&Path>) -> bool {
+        self._has_app_icon(umid, path, 0)
+    }
 
-            if let Some(entry) = found {
-                if entry.redirect.is_some() {
-                    return true;
+    /// Redirects are aliases of the entry of the target, so they are only valid while the
+    /// target has an icon, this way a removed or re-extracted target is never left dangling.
+    fn _has_app_icon(&self, umid: Option<&str>, path: Option<&Path>, depth: usize) -> bool {
+        self.find_app_entries(umid, path)
+            .any(|entry| match &entry.redirect {
+                Some(target) => {
+                    depth < MAX_REDIRECT_DEPTH && self._has_app_icon(None, Some(target), depth + 1)
                 }
+                None => entry
+                    .icon
+                    .as_ref()
+                    .is_some_and(|icon| !Self::is_fallback_icon(icon) && self.icon_exists(icon)),
+            })
+    }
 
-                if let Some(icon) = &entry.icon {
-                    if self.icon_exists(icon) {
-                        return true;
-                    }
+    /// Image and mask files of the icon stored on the system pack for the app, following
+    /// redirects. Icons without variants use their base file whatever `dark` is.
+    pub fn get_stored_app_icon_files(
+        &self,
+        key: &IconExtractionKey,
+        dark: bool,
+    ) -> Option<(PathBuf, Option<PathBuf>)> {
+        let (umid, path) = match key {
+            IconExtractionKey::Umid(umid) => (Some(umid.as_str()), None),
+            IconExtractionKey::Path(path) => (None, Some(path.as_path())),
+        };
+        self._get_stored_app_icon_files(umid, path, dark, 0)
+    }
+
+    fn _get_stored_app_icon_files(
+        &self,
+        umid: Option<&str>,
+        path: Option<&Path>,
+        dark: bool,
+        depth: usize,
+    ) -> Option<(PathBuf, Option<PathBuf>)> {
+        self.find_app_entries(umid, path)
+            .find_map(|entry| match &entry.redirect {
+                Some(target) if depth < MAX_REDIRECT_DEPTH => {
+                    self._get_stored_app_icon_files(None, Some(target), dark, depth + 1)
                 }
-            };
-        }
+                Some(_) => None,
+                None => {
+                    let icon = entry.icon.as_ref()?;
+                    let variant = match dark {
+                        true => icon.dark.as_ref(),
+                        false => icon.light.as_ref(),
+                    };
+                    let file = SYSTEM_ICONS.join(variant.or(icon.base.as_ref())?);
+                    let mask = icon
+                        .mask
+                        .as_ref()
+                        .map(|mask| SYSTEM_ICONS.join(mask))
+                        .filter(|mask| mask.exists());
+                    file.exists().then_some((file, mask))
+                }
+            })
+    }
 
-        false
+    /// Source and extraction time of the icon stored for the app, following redirects
+    pub fn get_icon_metadata(&self, key: &IconExtractionKey) -> Option<IconMetadata> {
+        let (file, _) = self.get_stored_app_icon_files(key, false)?;
+        get_stored_icon_metadata(&file.file_name()?.to_string_lossy())
+    }
+
+    pub fn has_fallback_app_icon(&self, umid: Option<&str>, path: Option<&Path>) -> bool {
+        self.find_app_entries(umid, path).any(|entry| {
+            entry
+                .icon
+                .as_ref()
+                .is_some_and(|icon| Self::is_fallback_icon(icon) && self.icon_exists(icon))
+        })
     }
 
     pub fn get_file_icon(&self, path: &Path) -> Option<&Icon> {
@@ -157,8 +537,11 @@ impl IconPacksManager {
     }
 
     pub fn clear_system_icons(&mut self) -> Result<()> {
+        self.stale_paths.clear();
         let system_pack = self.get_system_mut();
         system_pack.entries.clear();
+        clear_all_without_icon();
+        clear_all_icon_metadata();
         let meta = std::ffi::OsStr::new("metadata.yml");
         for entry in std::fs::read_dir(SYSTEM_ICONS.as_path())?.flatten() {
             if entry.file_type()?.is_dir() {
@@ -172,7 +555,7 @@ impl IconPacksManager {
 
     pub fn sanitize_system_icon_pack(&mut self, is_first_load: bool) -> Result<()> {
         // add default icon pack if not exists
-        if !self.0.contains_key(SYSTEM_ICONS.as_path()) {
+        if !self.packs.contains_key(SYSTEM_ICONS.as_path()) {
             let mut icon_pack = IconPack {
                 id: "@system/icon-pack".into(),
                 ..Default::default()
@@ -182,9 +565,10 @@ impl IconPacksManager {
                 ResourceText::En("Icons from Windows and Program Files".to_string());
             icon_pack.metadata.internal.path = SYSTEM_ICONS.to_path_buf();
 
-            self.0
+            self.packs
                 .insert(icon_pack.metadata.internal.path.clone(), icon_pack);
-            self.write_system_icon_pack()?;
+            self.pending_writes.dirty = true;
+            self.flush_system_icon_pack()?;
         }
 
         let system_pack = self.get_system_mut();
@@ -258,10 +642,216 @@ impl IconPacksManager {
         Ok(())
     }
 
-    pub fn write_system_icon_pack(&self) -> Result<()> {
-        self.get_system().save()?;
+    fn get_user_mut(&mut self) -> Result<&mut IconPack> {
+        if !self.packs.contains_key(USER_ICONS.as_path()) {
+            std::fs::create_dir_all(USER_ICONS.as_path())?;
+            let mut icon_pack = IconPack {
+                id: USER_ICON_PACK_ID.into(),
+                ..Default::default()
+            };
+            icon_pack.metadata.display_name = ResourceText::En("Custom".to_string());
+            icon_pack.metadata.description =
+                ResourceText::En("Icons set by the user for specific apps".to_string());
+            icon_pack.metadata.internal.path = USER_ICONS.to_path_buf();
+            write_pack_atomically(&icon_pack)?;
+            self.packs.insert(USER_ICONS.to_path_buf(), icon_pack);
+        }
+        Ok(self.packs.get_mut(USER_ICONS.as_path()).unwrap())
+    }
+
+    /// Removes the user entries of the app and its image files
+    fn remove_user_app_entries(pack: &mut IconPack, key: &IconExtractionKey) {
+        let path_key = match key {
+            IconExtractionKey::Path(path) => Some(normalize_icon_path(path)),
+            IconExtractionKey::Umid(_) => None,
+        };
+        let mut removed = Vec::new();
+        pack.entries.retain(|entry| {
+            let IconPackEntry::Unique(entry) = entry else {
+                return true;
+            };
+            let matches = match key {
+                IconExtractionKey::Umid(umid) => entry.umid.as_ref() == Some(umid),
+                IconExtractionKey::Path(_) => entry.path.is_some() && entry.path == path_key,
+            };
+            if matches {
+                removed.extend(entry.icon.iter().flat_map(icon_files).cloned());
+            }
+            !matches
+        });
+        delete_unreferenced_files(pack, removed);
+    }
+
+    /// Copies the image into the user icon pack as the icon of the app,
+    /// replacing any previous custom icon of it.
+    pub fn set_custom_app_icon(
+        &mut self,
+        key: &IconExtractionKey,
+        image_path: &Path,
+    ) -> Result<()> {
+        let image = image::open(image_path)?.to_rgba8();
+        let extension = image_path
+            .extension()
+            .ok_or("Image without extension")?
+            .to_string_lossy()
+            .to_lowercase();
+
+        let pack = self.get_user_mut()?;
+        Self::remove_user_app_entries(pack, key);
+
+        let filename = format!("{}.{extension}", date_based_hex_id());
+        std::fs::copy(image_path, USER_ICONS.join(&filename))?;
+        record_icon_colors(&USER_ICONS.join(&filename), &image);
+
+        let (umid, path) = match key {
+            IconExtractionKey::Umid(umid) => (Some(umid.clone()), None),
+            IconExtractionKey::Path(path) => (None, Some(normalize_icon_path(path))),
+        };
+        pack.add_entry(IconPackEntry::Unique(UniqueIconPackEntry {
+            umid,
+            path,
+            redirect: None,
+            icon: Some(Icon {
+                base: Some(filename),
+                is_aproximately_square: is_aproximately_a_square(&image),
+                ..Default::default()
+            }),
+        }));
+        write_pack_atomically(pack)?;
+        activate_user_icon_pack()
+    }
+
+    /// Removes the custom icon of the app, so the system icon is used again
+    pub fn clear_custom_app_icon(&mut self, key: &IconExtractionKey) -> Result<()> {
+        if let Some(pack) = self.packs.get_mut(USER_ICONS.as_path()) {
+            Self::remove_user_app_entries(pack, key);
+            write_pack_atomically(pack)?;
+        }
+        Ok(())
+    }
+
+    /// Marks the system pack as modified, it will be written 500ms after the last change
+    /// or on `end_batch`, use `flush_system_icon_pack` when it needs to be written right away.
+    pub fn write_system_icon_pack(&mut self) -> Result<()> {
+        self.write_system_icon_pack_debounced(SYSTEM_PACK_WRITE_DELAY)
+    }
+
+    /// Same as `write_system_icon_pack` but written `delay` after the last change,
+    /// each call restarts the timer of the previous ones.
+    pub fn write_system_icon_pack_debounced(&mut self, delay: Duration) -> Result<()> {
+        self.pending_writes.dirty = true;
+        if self.pending_writes.batches > 0 {
+            return Ok(());
+        }
+        SYSTEM_PACK_WRITE_DEBOUNCER.call_after(delay, async move || {
+            // the lock can be held for a while by extractions, so it is not waited on the runtime
+            let flush = tauri::async_runtime::spawn_blocking(|| {
+                let mutex = FULL_STATE.load().icon_packs().clone();
+                log_error!(trace_lock!(mutex).flush_system_icon_pack());
+            });
+            log_error!(flush.await);
+        });
+        Ok(())
+    }
+
+    /// Writes the system pack now if it has pending changes
+    pub fn flush_system_icon_pack(&mut self) -> Result<()> {
+        if !self.pending_writes.dirty {
+            return Ok(());
+        }
+        self.pending_writes.dirty = false;
+        let _timer = StageTimer::start(MetricStage::PackWrite);
+        deduplicate_icon_files(self.get_system_mut());
+        self.pending_writes.written_hash = Some(write_pack_atomically(self.get_system())?);
+        let changed = self.pending_writes.changed_keys.drain().collect();
+        Self::send(IconPacksEvent::SystemPackWritten(changed));
+        Ok(())
+    }
+
+    /// Defers the writes of the system icon pack until `end_batch` is called,
+    /// so bursts of extractions result in a single write.
+    pub fn begin_batch(&mut self) {
+        self.pending_writes.batches += 1;
+    }
+
+    pub fn end_batch(&mut self) -> Result<()> {
+        self.pending_writes.batches = self.pending_writes.batches.saturating_sub(1);
+        if self.pending_writes.batches == 0 && self.pending_writes.dirty {
+            self.flush_system_icon_pack()?;
+        }
         Ok(())
     }
+
+    /// Begins a batch on the shared manager that ends when the guard is dropped, also on early
+    /// returns and panics. The manager must not be locked by the caller.
+    pub fn batch_write_guard() -> SystemPackWriteBatch {
+        let mutex = FULL_STATE.load().icon_packs().clone();
+        trace_lock!(mutex).begin_batch();
+        SystemPackWriteBatch(mutex)
+    }
+
+    /// Reloads the pack of the folder after its metadata was edited outside of the app, removing it
+    /// if the folder no longer contains a pack. Returns false if there was nothing to reload.
+    pub fn reload_icon_pack(&mut self, folder: &Path) -> Result<bool> {
+        let is_system = folder == SYSTEM_ICONS.as_path();
+        let metadata = match std::fs::read(folder.join(PACK_METADATA)) {
+            Ok(metadata) => metadata,
+            // the system pack is recreated from memory on the next write
+            Err(_) if is_system => return Ok(false),
+            Err(_) => return Ok(self.packs.remove(folder).is_some()),
+        };
+
+        if is_system && self.pending_writes.written_hash == Some(hash_metadata(&metadata)) {
+            return Ok(false);
+        }
+
+        let mut icon_pack = load_pack_or_backup(folder)?;
+        icon_pack.metadata.internal.bundled = is_system;
+        prepare_loaded_pack(&mut icon_pack);
+        if is_system && self.pending_writes.dirty {
+            // the manual edit wins, except for the apps extracted since the last write,
+            // the pack stays dirty so they are written on top of the edit
+            merge_pending_app_entries(
+                &mut icon_pack,
+                self.get_system(),
+                &self.pending_writes.changed_keys,
+            );
+        }
+        self.packs
+            .insert(icon_pack.metadata.internal.path.clone(), icon_pack);
+        Ok(true)
+    }
+
+    /// Entries created before the paths were normalized could be duplicated
+    /// by case or format, they are merged only once.
+    fn migrate_to_normalized_keys(&mut self) -> Result<()> {
+        let marker = SYSTEM_ICONS.join(NORMALIZED_KEYS_MARKER);
+        if marker.exists() {
+            return Ok(());
+        }
+
+        let merged = merge_duplicated_app_entries(self.get_system_mut());
+        self.pending_writes.dirty = true;
+        self.flush_system_icon_pack()?;
+
+        if let Some(user_pack) = self.packs.get_mut(USER_ICONS.as_path()) {
+            merge_duplicated_app_entries(user_pack);
+            write_pack_atomically(user_pack)?;
+        }
+
+        log::info!("Icon keys normalized, {merged} duplicated entries merged");
+        std::fs::write(marker, [])?;
+        Ok(())
+    }
+}
+
+/// See `IconPacksManager::batch_write_guard`
+pub struct SystemPackWriteBatch(Arc<Mutex<IconPacksManager>>);
+
+impl Drop for SystemPackWriteBatch {
+    fn drop(&mut self) {
+        log_error!(trace_lock!(self.0).end_batch());
+    }
 }
 
 impl FullState {
@@ -276,11 +866,11 @@ impl FullState {
     pub(super) fn load_icons_packs(&mut self, is_first_load: bool) -> Result<()> {
         let entries = std::fs::read_dir(SEELEN_COMMON.user_icons_path())?;
         let mut icon_packs_manager = trace_lock!(self.icon_packs);
-        icon_packs_manager.0.clear();
+        icon_packs_manager.packs.clear();
 
         for entry in entries.flatten() {
             let path = entry.path();
-            let mut icon_pack = match IconPack::load(&path) {
+            let mut icon_pack = match load_pack_or_backup(&path) {
                 Ok(icon_pack) => icon_pack,
                 Err(err) => {
                     log::error!("Failed to load icon pack ({path:?}): {err:?}");
@@ -289,16 +879,51 @@ impl FullState {
             };
 
             icon_pack.metadata.internal.bundled = entry.file_name() == "system";
+            prepare_loaded_pack(&mut icon_pack);
             icon_packs_manager
                 .0
                 .insert(icon_pack.metadata.internal.path.clone(), icon_pack);
         }
 
         icon_packs_manager.sanitize_system_icon_pack(is_first_load)?;
+        icon_packs_manager.migrate_to_normalized_keys()?;
         Ok(())
     }
 }
 
+/// The frontend gets the packs again after each write of the system pack
+pub fn register_icon_packs_events() {
+    IconPacksManager::subscribe(|event| match event {
+        IconPacksEvent::SystemPackWritten(changed) => {
+            log::trace!("System icon pack written, {} apps changed", changed.len());
+            log_error!(FULL_STATE.load().emit_icon_packs());
+        }
+    });
+}
+
+/// The user icon pack goes first on the active packs, so its icons are preferred over any other
+fn activate_user_icon_pack() -> Result<()> {
+    let is_active = FULL_STATE
+        .load()
+        .settings
+        .active_icon_packs
+        .iter()
+        .any(|id| id.to_string() == USER_ICON_PACK_ID);
+    if is_active {
+        return Ok(());
+    }
+
+    FULL_STATE.rcu(|state| {
+        let mut state = state.cloned();
+        state
+            .settings
+            .active_icon_packs
+            .insert(0, USER_ICON_PACK_ID.to_string().into());
+        state
+    });
+    FULL_STATE.load().write_settings()
+}
+
 pub async fn download_remote_icons(pack: &mut IconPack) -> Result<()> {
     if pack.remote_entries.is_empty() || pack.downloaded {
         return Ok(());
@@ -389,3 +1014,131 @@ async fn download_remote_icon_and_validate_it(
     icon.save(&icon_path)?;
     Ok(icon_path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::application::testing::TempFolder;
+
+    fn pack_with_apps(folder: &Path, umids: &[&str]) -> IconPack {
+        let mut pack = IconPack {
+            id: "@test/icon-pack".into(),
+            ..Default::default()
+        };
+        pack.metadata.internal.path = folder.to_path_buf();
+        for umid in umids {
+            pack.add_entry(IconPackEntry::Unique(UniqueIconPackEntry {
+                umid: Some(umid.to_string()),
+                path: None,
+                redirect: None,
+                icon: Some(Icon {
+                    base: Some(format!("{umid}.png")),
+                    ..Default::default()
+                }),
+            }));
+        }
+        pack
+    }
+
+    fn app_files(pack: &IconPack) -> Vec<String> {
+        pack.entries
+            .iter()
+            .filter_map(|entry| match entry {
+                IconPackEntry::Unique(entry) => entry.icon.as_ref()?.base.clone(),
+                _ => None,
+            })
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn reload_keeps_the_extractions_not_yet_written() {
+        let folder = Path::new(r"C:\slu-missing\system");
+        // edited by hand, "Edited" was customized and "Removed" deleted
+        let mut reloaded = pack_with_apps(folder, &["Edited", "Extracted"]);
+        let mut current = pack_with_apps(folder, &["Removed", "Extracted", "New"]);
+        for entry in &mut current.entries {
+            if let IconPackEntry::Unique(entry) = entry {
+                if entry.umid.as_deref() == Some("Extracted") {
+                    entry.icon.as_mut().unwrap().base = Some("Extracted_v2.png".into());
+                }
+            }
+        }
+        let changed = HashSet::from([
+            IconExtractionKey::Umid("Extracted".into()),
+            IconExtractionKey::Umid("New".into()),
+            IconExtractionKey::Umid("Gone".into()),
+        ]);
+
+        merge_pending_app_entries(&mut reloaded, &current, &changed);
+        assert_eq!(
+            app_files(&reloaded),
+            ["Edited.png", "Extracted_v2.png", "New.png"]
+        );
+    }
+
+    #[test]
+    fn reload_applies_the_removals_not_yet_written() {
+        let folder = Path::new(r"C:\slu-missing\system");
+        let mut reloaded = pack_with_apps(folder, &["Edited", "Uninstalled"]);
+        let current = pack_with_apps(folder, &["Edited"]);
+        let changed = HashSet::from([IconExtractionKey::Umid("Uninstalled".into())]);
+
+        merge_pending_app_entries(&mut reloaded, &current, &changed);
+        assert_eq!(app_files(&reloaded), ["Edited.png"]);
+    }
+
+    #[test]
+    fn first_write_creates_the_metadata() {
+        let folder = TempFolder::new("icons-atomic-first-write");
+        let hash = write_pack_atomically(&pack_with_apps(folder.path(), &["App"])).unwrap();
+
+        let written = std::fs::read(folder.join(PACK_METADATA)).unwrap();
+        assert_eq!(hash, hash_metadata(&written));
+        assert!(!folder.join(PACK_METADATA_TMP).exists());
+        assert!(!folder.join(PACK_METADATA_BACKUP).exists());
+        assert_eq!(load_pack_or_backup(folder.path()).unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn replace_keeps_the_previous_metadata_as_backup() {
+        let folder = TempFolder::new("icons-atomic-replace");
+        write_pack_atomically(&pack_with_apps(folder.path(), &["App"])).unwrap();
+        let previous = std::fs::read(folder.join(PACK_METADATA)).unwrap();
+        write_pack_atomically(&pack_with_apps(folder.path(), &["App", "Other"])).unwrap();
+
+        assert!(!folder.join(PACK_METADATA_TMP).exists());
+        assert_eq!(
+            std::fs::read(folder.join(PACK_METADATA_BACKUP)).unwrap(),
+            previous
+        );
+        assert_eq!(load_pack_or_backup(folder.path()).unwrap().entries.len(), 2);
+    }
+
+    #[test]
+    fn corrupted_metadata_is_restored_from_backup() {
+        let folder = TempFolder::new("icons-atomic-restore");
+        write_pack_atomically(&pack_with_apps(folder.path(), &["App"])).unwrap();
+        write_pack_atomically(&pack_with_apps(folder.path(), &["App", "Other"])).unwrap();
+        // truncated by a crash in the middle of a write
+        std::fs::write(
+            folder.join(PACK_METADATA),
+            "id: '@test/icon-pack'\nentries: [",
+        )
+        .unwrap();
+
+        let restored = load_pack_or_backup(folder.path()).unwrap();
+        assert_eq!(restored.entries.len(), 1);
+        assert_eq!(
+            std::fs::read(folder.join(PACK_METADATA)).unwrap(),
+            std::fs::read(folder.join(PACK_METADATA_BACKUP)).unwrap()
+        );
+    }
+
+    #[test]
+    fn corrupted_metadata_without_backup_fails() {
+        let folder = TempFolder::new("icons-atomic-no-backup");
+        std::fs::write(folder.join(PACK_METADATA), "entries: [").unwrap();
+        assert!(load_pack_or_backup(folder.path()).is_err());
+    }
+}
@@ -1,17 +1,31 @@
 mod apps_config;
 mod events;
+mod icon_dedup;
+mod icon_extraction;
+mod icon_gc;
+mod icon_keys;
+mod icon_pack_archive;
+mod icon_pack_validation;
+mod icon_svg;
 mod icons;
 pub mod performance;
 mod profiles;
 mod settings;
+#[cfg(test)]
+mod testing;
 mod toolbar_items;
 mod weg_items;
 
-pub use icons::download_remote_icons;
+pub use icon_gc::is_garbage_collection_due;
+pub use icon_pack_validation::{IconFileIssue, IconFileProblem, IconPackValidationReport};
+pub use icons::{
+    download_remote_icons, register_icon_packs_events, IconPacksManager, SystemPackWriteBatch,
+};
+
+use icons::changed_icon_pack_folder;
 
 use arc_swap::ArcSwap;
 use getset::Getters;
-use icons::IconPacksManager;
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use notify_debouncer_full::{
@@ -32,11 +46,11 @@ use std::{
 };
 
 use crate::{
-    error::Result, log_error, resources::RESOURCES, utils::constants::SEELEN_COMMON,
+    error::Result, log_error, resources::RESOURCES, trace_lock, utils::constants::SEELEN_COMMON,
     widgets::popups::POPUPS_MANAGER,
 };
 
-use super::domain::{AppConfig, Placeholder, Settings};
+use super::domain::{AppConfig, IconExtractionSettings, Placeholder, Settings};
 
 lazy_static! {
     pub static ref FULL_STATE: Arc<ArcSwap<FullState>> = Arc::new(ArcSwap::from_pointee({
@@ -56,6 +70,7 @@ pub struct FullState {
     pub weg_items: WegItems,
     pub toolbar_items: Placeholder,
     pub launcher_history: LauncherHistory,
+    pub icon_extraction: IconExtractionSettings,
     // ====== resources ========
     pub icon_packs: Arc<Mutex<IconPacksManager>>,
 }
@@ -73,6 +88,7 @@ impl FullState {
             weg_items: WegItems::default(),
             toolbar_items: Placeholder::default(),
             launcher_history: LauncherHistory::default(),
+            icon_extraction: IconExtractionSettings::default(),
             icon_packs: Arc::new(Mutex::new(IconPacksManager::default())),
         };
         manager.load_all()?; // ScaDaned log shows a deadlock here.
@@ -101,7 +117,7 @@ impl FullState {
 
     fn process_changes(&mut self, changed: &HashSet<PathBuf>) -> Result<()> {
         let mut icons_changed = false;
-        let mut system_icons_changed = false;
+        let mut changed_icon_packs = HashSet::new();
         let mut weg_items_changed = false;
         let mut toolbar_items_changed = false;
         let mut history_changed = false;
@@ -111,14 +127,13 @@ impl FullState {
         let mut widgets_changed = false;
         let mut settings_changed = false;
         let mut wallpapers_changed = false;
+        let mut icon_extraction_changed = false;
 
         // Single iteration over the changed paths
         for path in changed {
-            if !icons_changed && path.starts_with(SEELEN_COMMON.user_icons_path()) {
+            if path.starts_with(SEELEN_COMMON.user_icons_path()) {
                 icons_changed = true;
-                if !system_icons_changed {
-                    system_icons_changed = path.to_string_lossy().contains("system");
-                }
+                changed_icon_packs.extend(changed_icon_pack_folder(path));
             };
 
             if !weg_items_changed && path == SEELEN_COMMON.weg_items_path() {
@@ -168,14 +183,28 @@ impl FullState {
             if !wallpapers_changed && path.starts_with(SEELEN_COMMON.user_wallpapers_path()) {
                 wallpapers_changed = true;
             }
+
+            if !icon_extraction_changed && path == SEELEN_COMMON.icon_extraction_settings_path() {
+                icon_extraction_changed = true;
+            }
         }
 
         if icons_changed {
             log::info!("Icon Packs changed");
-            if !system_icons_changed {
-                self.load_icons_packs(false)?;
+            let mut reloaded = false;
+            {
+                let mut icon_manager = trace_lock!(self.icon_packs);
+                for folder in &changed_icon_packs {
+                    match icon_manager.reload_icon_pack(folder) {
+                        Ok(changed) => reloaded |= changed,
+                        Err(err) => log::error!("{err:?}"),
+                    }
+                }
+            }
+            // our own writes of the system pack are emitted by `register_icon_packs_events`
+            if reloaded {
+                self.emit_icon_packs()?;
             }
-            self.emit_icon_packs()?;
         }
 
         if weg_items_changed {
@@ -196,6 +225,11 @@ impl FullState {
             }
         }
 
+        if icon_extraction_changed {
+            log::info!("Icon Extraction Settings changed");
+            self.read_icon_extraction_settings();
+        }
+
         if history_changed {
             log::info!("History changed");
             self.load_history();
@@ -271,6 +305,7 @@ impl FullState {
             SEELEN_COMMON.toolbar_items_path(),
             SEELEN_COMMON.user_app_configs_path(),
             SEELEN_COMMON.history_path(),
+            SEELEN_COMMON.icon_extraction_settings_path(),
             SEELEN_COMMON.user_icons_path(),
             SEELEN_COMMON.user_themes_path(),
             SEELEN_COMMON.user_plugins_path(),
@@ -388,6 +423,9 @@ impl FullState {
         log::trace!("Initial load: wallpapers");
         RESOURCES.load_all_of_type(ResourceKind::Wallpaper)?;
 
+        log::trace!("Initial load: icon extraction settings");
+        self.read_icon_extraction_settings();
+
         log::trace!("Initial load: icons packs");
         self.load_icons_packs(true)?;
 
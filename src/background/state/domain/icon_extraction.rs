@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+/// Settings that control how icons are extracted and stored on the system icon pack.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct IconExtractionSettings {
+    /// generate a letter-avatar icon for apps where every extraction strategy failed
+    pub fallback_icons: bool,
+    /// synthesize light/dark variants for monochrome Win32 icons (terminals, CLI tools)
+    pub monochrome_theme_variants: bool,
+    /// draw plated UWP logos on a circle of the app background color, like the start menu does
+    pub compose_uwp_backgrounds: bool,
+    /// generate silhouette masks for icons with light/dark variants
+    pub generate_masks: bool,
+    /// gaussian blur sigma applied to the generated masks, 0 to disable
+    pub mask_feather: f32,
+    /// don't extract icons from network shares and removable drives at all
+    pub skip_remote_paths: bool,
+    /// max time to wait for a network or removable path before using the fallback icon
+    pub remote_paths_timeout_ms: u64,
+    /// max time to wait for the shell to give the icon of a file, hung shell extensions
+    /// are abandoned and the icon is read from the file resources instead
+    pub shell_timeout_ms: u64,
+    /// format used to store the extracted icons, already stored icons are kept as they are
+    pub storage_format: IconStorageFormat,
+    /// only applies when `storage_format` is png
+    pub png_compression: PngCompression,
+    /// glob patterns of files whose icons are never extracted, like `%PROGRAMDATA%\Tools\**`
+    /// or `*.url`. `**` matches any amount of folders and environment variables are expanded.
+    pub excluded_paths: Vec<String>,
+    /// register the letter-avatar icon for excluded files, so they are not requested again
+    pub fallback_for_excluded_paths: bool,
+    /// custom protocols of internet shortcuts resolved to the icon of the app handling them
+    pub url_protocols: Vec<String>,
+    /// days between deletions of the stored icons no longer used, 0 to disable it
+    pub garbage_collection_interval_days: u32,
+    /// amount of shell extracted icons kept in memory, 0 to disable the cache
+    pub memory_cache_capacity: usize,
+    /// how the alpha of the icons given by the shell is interpreted
+    pub alpha_mode: IconAlphaMode,
+    /// shape applied to app icons whose opaque pixels reach the four edges, like screenshots
+    /// or full-bleed tiles. Free-form icons with transparent surroundings are never changed.
+    pub icon_shape: IconShape,
+    /// corner radius of the shape, as a fraction of the icon side from 0 to 0.5
+    pub icon_shape_radius: f32,
+    /// time the extraction stages, exposed by `get_icon_extraction_metrics`
+    pub collect_metrics: bool,
+    /// extractions between dumps of the metrics to the debug log, 0 to never log them
+    pub metrics_log_interval: u32,
+}
+
+impl Default for IconExtractionSettings {
+    fn default() -> Self {
+        Self {
+            fallback_icons: true,
+            monochrome_theme_variants: false,
+            compose_uwp_backgrounds: false,
+            generate_masks: true,
+            mask_feather: 0.0,
+            skip_remote_paths: false,
+            remote_paths_timeout_ms: 3000,
+            shell_timeout_ms: 5000,
+            storage_format: IconStorageFormat::default(),
+            png_compression: PngCompression::default(),
+            excluded_paths: Vec::new(),
+            fallback_for_excluded_paths: true,
+            url_protocols: vec!["steam".to_owned(), "com.epicgames.launcher".to_owned()],
+            garbage_collection_interval_days: 7,
+            memory_cache_capacity: 256,
+            alpha_mode: IconAlphaMode::default(),
+            icon_shape: IconShape::default(),
+            icon_shape_radius: 0.225,
+            collect_metrics: false,
+            metrics_log_interval: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconAlphaMode {
+    /// detect premultiplied icons by their pixels
+    #[default]
+    Auto,
+    /// never unpremultiply, for icons where the detection guesses wrong
+    Straight,
+    /// always unpremultiply
+    Premultiplied,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconShape {
+    /// icons are stored as they are
+    #[default]
+    None,
+    RoundedRect,
+    /// continuous corners, like the macOS dock
+    Squircle,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconStorageFormat {
+    #[default]
+    Png,
+    /// lossless, usually a lot smaller than png for big UWP assets
+    Webp,
+}
+
+impl IconStorageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            IconStorageFormat::Png => "png",
+            IconStorageFormat::Webp => "webp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
@@ -1 +1,4 @@
+mod icon_extraction;
+
+pub use icon_extraction::*;
 pub use seelen_core::state::*;
@@ -149,6 +149,7 @@ pub fn state_delete_cached_icons() -> Result<()> {
     icon_manager.clear_system_icons()?;
     icon_manager.sanitize_system_icon_pack(false)?;
     icon_manager.write_system_icon_pack()?;
+    icon_manager.flush_system_icon_pack()?;
     Ok(())
 }
 
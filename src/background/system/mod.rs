@@ -18,7 +18,9 @@ use crate::{
         system_settings::infrastructure::{register_system_settings_events, release_colors_events},
         tray::infrastructure::register_tray_icons_events,
         user::infrastructure::register_user_events,
+        uwp::UwpManager,
     },
+    state::application::register_icon_packs_events,
     trace_lock,
 };
 
@@ -28,6 +30,7 @@ pub fn declare_system_events_handlers() -> Result<()> {
     // others like bluetooth or wi-fi, bandwidth, etc depends on this.
     std::thread::spawn(move || {
         log_error!(trace_lock!(RADIO_MANAGER).initialize());
+        log_error!(UwpManager::init());
     })
     .join()
     .expect("Failed to register system events");
@@ -47,6 +50,7 @@ pub fn declare_system_events_handlers() -> Result<()> {
     register_system_settings_events();
     register_power_events();
     register_language_events();
+    register_icon_packs_events();
     Ok(())
 }
 
@@ -56,4 +60,5 @@ pub fn release_system_events_handlers() {
     release_power_events();
     release_bluetooth_events();
     release_colors_events();
+    UwpManager::release();
 }
@@ -50,6 +50,7 @@ pub struct SeelenCommon {
     weg_items: PathBuf,
     toolbar_items: PathBuf,
     icons: PathBuf,
+    icon_extraction: PathBuf,
     user_themes: PathBuf,
     bundled_themes: PathBuf,
     user_plugins: PathBuf,
@@ -91,6 +92,7 @@ impl SeelenCommon {
             weg_items: data_dir.join("seelenweg_items_v2.yml"),
             toolbar_items: data_dir.join("toolbar_items.yml"),
             icons: data_dir.join("iconpacks"),
+            icon_extraction: data_dir.join("icon_extraction.yml"),
             sounds: data_dir.join("soundpacks"),
             user_themes: data_dir.join("themes"),
             bundled_themes: resource_dir.join("static/themes"),
@@ -154,6 +156,10 @@ impl SeelenCommon {
         &self.icons
     }
 
+    pub fn icon_extraction_settings_path(&self) -> &Path {
+        &self.icon_extraction
+    }
+
     pub fn user_sounds_path(&self) -> &Path {
         &self.sounds
     }
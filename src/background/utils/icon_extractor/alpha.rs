@@ -0,0 +1,149 @@
+use image::RgbaImage;
+
+use crate::state::{application::FULL_STATE, domain::IconAlphaMode};
+
+/// tolerance for rounding errors on premultiplied data
+const CHANNEL_OVER_ALPHA_TOLERANCE: u8 = 2;
+
+/// Premultiplied data can't have a color channel brighter than its alpha,
+/// so any of those pixels means straight alpha.
+/// Otherwise it is considered premultiplied if the semi-transparent pixels (edges) are
+/// darker than the opaque ones, closer to what premultiplication would produce.
+pub fn is_premultiplied(image: &RgbaImage) -> bool {
+    let mut edge_brightness = 0f32;
+    let mut edge_alpha = 0f32;
+    let mut edge_count = 0u32;
+    let mut opaque_brightness = 0f32;
+    let mut opaque_count = 0u32;
+
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        let max = r.max(g).max(b);
+        if max > a.saturating_add(CHANNEL_OVER_ALPHA_TOLERANCE) {
+            return false;
+        }
+        match a {
+            0 => {}
+            255 => {
+                opaque_brightness += max as f32 / 255.0;
+                opaque_count += 1;
+            }
+            _ => {
+                edge_brightness += max as f32 / 255.0;
+                edge_alpha += a as f32 / 255.0;
+                edge_count += 1;
+            }
+        }
+    }
+
+    if edge_count == 0 || opaque_count == 0 {
+        return false;
+    }
+
+    let edge_brightness = edge_brightness / edge_count as f32;
+    let edge_alpha = edge_alpha / edge_count as f32;
+    let opaque_brightness = opaque_brightness / opaque_count as f32;
+
+    // straight alpha expects edges as bright as the body, premultiplied expects `body * alpha`
+    let straight_expected = opaque_brightness;
+    let premultiplied_expected = opaque_brightness * edge_alpha;
+    edge_brightness < (straight_expected + premultiplied_expected) / 2.0
+}
+
+/// Converts premultiplied pixels to straight alpha
+pub fn unpremultiply_alpha(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let a = pixel[3] as u32;
+        if a == 0 || a == 255 {
+            continue;
+        }
+        for channel in 0..3 {
+            pixel[channel] = ((pixel[channel] as u32 * 255 + a / 2) / a).min(255) as u8;
+        }
+    }
+}
+
+/// Applies `unpremultiply_alpha` following the extraction settings
+pub fn normalize_alpha(image: &mut RgbaImage) {
+    let premultiplied = match FULL_STATE.load().icon_extraction.alpha_mode {
+        IconAlphaMode::Auto => is_premultiplied(image),
+        IconAlphaMode::Straight => false,
+        IconAlphaMode::Premultiplied => true,
+    };
+    if premultiplied {
+        unpremultiply_alpha(image);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// opaque body on the left half and semi-transparent edge pixels on the right half
+    fn icon(body: [u8; 4], edge: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_fn(4, 4, |x, _| match x < 2 {
+            true => Rgba(body),
+            false => Rgba(edge),
+        })
+    }
+
+    fn unpremultiplied(pixel: [u8; 4]) -> [u8; 4] {
+        let mut image = RgbaImage::from_pixel(1, 1, Rgba(pixel));
+        unpremultiply_alpha(&mut image);
+        image.get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn unpremultiply_skips_transparent_and_opaque_pixels() {
+        assert_eq!(unpremultiplied([0, 0, 0, 0]), [0, 0, 0, 0]);
+        assert_eq!(unpremultiplied([10, 20, 30, 0]), [10, 20, 30, 0]);
+        assert_eq!(unpremultiplied([100, 150, 200, 255]), [100, 150, 200, 255]);
+    }
+
+    #[test]
+    fn unpremultiply_rounds_to_nearest() {
+        assert_eq!(unpremultiplied([64, 32, 0, 128]), [128, 64, 0, 128]);
+        assert_eq!(unpremultiplied([1, 1, 1, 2]), [128, 128, 128, 2]);
+        assert_eq!(unpremultiplied([1, 2, 1, 3]), [85, 170, 85, 3]);
+        assert_eq!(unpremultiplied([100, 50, 25, 200]), [128, 64, 32, 200]);
+    }
+
+    #[test]
+    fn unpremultiply_clamps_invalid_pixels() {
+        assert_eq!(unpremultiplied([200, 0, 0, 100]), [255, 0, 0, 100]);
+    }
+
+    #[test]
+    fn detects_premultiplied_edges() {
+        let image = icon([200, 200, 200, 255], [100, 100, 100, 128]);
+        assert!(is_premultiplied(&image));
+    }
+
+    #[test]
+    fn detects_straight_edges() {
+        // channels brighter than alpha can't be premultiplied
+        let image = icon([200, 200, 200, 255], [200, 200, 200, 128]);
+        assert!(!is_premultiplied(&image));
+        // dark icons have no channel over alpha, but the edges are as bright as the body
+        let image = icon([50, 50, 50, 255], [50, 50, 50, 128]);
+        assert!(!is_premultiplied(&image));
+    }
+
+    #[test]
+    fn needs_edges_and_body_to_decide() {
+        let opaque = RgbaImage::from_pixel(4, 4, Rgba([20, 20, 20, 255]));
+        assert!(!is_premultiplied(&opaque));
+        let translucent = RgbaImage::from_pixel(4, 4, Rgba([20, 20, 20, 128]));
+        assert!(!is_premultiplied(&translucent));
+    }
+
+    #[test]
+    fn premultiplied_icon_is_restored() {
+        let mut image = icon([200, 100, 50, 255], [100, 50, 25, 128]);
+        assert!(is_premultiplied(&image));
+        unpremultiply_alpha(&mut image);
+        assert_eq!(image.get_pixel(3, 0).0, [199, 100, 50, 128]);
+        assert_eq!(image.get_pixel(0, 0).0, [200, 100, 50, 255]);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use image::RgbaImage;
+use zip::ZipArchive;
+
+use crate::{
+    error::Result,
+    modules::uwp::{
+        assets::{parse_candidate, AssetPlate},
+        domain::PackageManifest,
+    },
+};
+
+use super::{crop_transparent_borders, IconExtractionError, IconExtractionStage};
+
+/// Reads the icon of a sideloaded `.appx`/`.msix` package from the archive itself,
+/// these packages are not registered so the package manager can't resolve them.
+///
+/// Follows the same chain as installed packages: Square44x44Logo -> Square150x150Logo -> StoreLogo
+pub fn get_icon_from_appx_package(path: &Path) -> Result<RgbaImage> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let manifest: PackageManifest = {
+        let manifest_file = archive.by_name("AppxManifest.xml")?;
+        quick_xml::de::from_reader(BufReader::new(manifest_file))?
+    };
+
+    let mut chain = Vec::new();
+    if let Some(app) = manifest.applications.application.first() {
+        chain.push(app.visual_elements.logo_44.clone());
+        chain.push(app.visual_elements.logo_150.clone());
+    }
+    chain.push(manifest.properties.logo.clone());
+
+    for logo in chain {
+        if let Some(icon) = get_best_logo_variant(&mut archive, &logo)? {
+            return Ok(crop_transparent_borders(&icon));
+        }
+    }
+    Err(IconExtractionError::new(
+        IconExtractionStage::UwpAsset,
+        "no logo found on the package",
+    )
+    .with_key(path.display())
+    .into())
+}
+
+/// Highest resolution variant of the logo on the archive, unplated ones are preferred
+fn get_best_logo_variant(archive: &mut ZipArchive<File>, logo: &str) -> Result<Option<RgbaImage>> {
+    // manifests use windows separators but archive entries use forward slashes
+    let logo = logo.replace('\\', "/");
+    let logo_path = Path::new(&logo);
+    let (Some(stem), Some(extension), Some(dir)) = (
+        logo_path.file_stem().and_then(|s| s.to_str()),
+        logo_path.extension().and_then(|s| s.to_str()),
+        logo_path.parent(),
+    ) else {
+        return Ok(None);
+    };
+
+    let best = archive
+        .file_names()
+        .filter(|name| Path::new(name).parent() == Some(dir))
+        .filter_map(|name| parse_candidate(stem, extension, Path::new(name)))
+        .max_by_key(|c| (c.plate != AssetPlate::Plated, c.pixel_size));
+
+    let entry_name = match best {
+        Some(candidate) => candidate.path.to_string_lossy().to_string(),
+        // unqualified logo, only present on packages without resource indirection
+        None => match archive
+            .file_names()
+            .find(|name| name.eq_ignore_ascii_case(&logo))
+        {
+            Some(name) => name.to_owned(),
+            None => return Ok(None),
+        },
+    };
+
+    let mut bytes = Vec::new();
+    archive.by_name(&entry_name)?.read_to_end(&mut bytes)?;
+    Ok(Some(image::load_from_memory(&bytes)?.to_rgba8()))
+}
@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+
+use image::RgbaImage;
+use winreg::{enums::HKEY_CLASSES_ROOT, RegKey};
+
+use crate::{
+    error::Result,
+    windows_api::{string_utils::WindowsString, WindowsApi},
+};
+
+use super::{get_icon_from_resource, IconExtractionError, IconExtractionStage};
+
+/// `DefaultIcon` of the extension, or of the ProgID it is associated with
+fn get_default_icon_value(ext: &str) -> Result<String> {
+    let hkcr = RegKey::predef(HKEY_CLASSES_ROOT);
+    let ext_key = hkcr.open_subkey(format!(".{ext}"))?;
+    if let Ok(value) = ext_key
+        .open_subkey("DefaultIcon")
+        .and_then(|key| key.get_value::<String, _>(""))
+    {
+        return Ok(value);
+    }
+
+    let prog_id: String = ext_key.get_value("")?;
+    Ok(hkcr
+        .open_subkey(format!("{prog_id}\\DefaultIcon"))?
+        .get_value("")?)
+}
+
+/// `"%SystemRoot%\app.exe",-101` -> (`C:\Windows\app.exe`, -101), the index defaults to 0
+fn parse_icon_location(value: &str) -> Option<(PathBuf, i32)> {
+    let value = value.trim();
+    let (file, index) = match value.rsplit_once(',') {
+        Some((file, index)) => match index.trim().parse::<i32>() {
+            Ok(index) => (file, index),
+            Err(_) => (value, 0),
+        },
+        None => (value, 0),
+    };
+
+    let file = file.trim().trim_matches('"');
+    // `%1` means each file has its own icon, handled by the shell handler
+    if file.is_empty() || file.contains("%1") {
+        return None;
+    }
+    let file = WindowsApi::resolve_environment_variables(&WindowsString::from_str(file)).ok()?;
+    Some((PathBuf::from(file.to_os_string()), index))
+}
+
+/// Icon registered for the file type, like `.py` -> `python.exe,1`. Used for types launched
+/// by interpreters where the shell doesn't resolve the icon.
+pub fn get_icon_from_registry_association(ext: &str) -> Result<RgbaImage> {
+    let value = get_default_icon_value(ext)?;
+    let (file, index) = parse_icon_location(&value).ok_or_else(|| {
+        IconExtractionError::new(
+            IconExtractionStage::ShellLookup,
+            format!("unusable DefaultIcon {value:?}"),
+        )
+        .with_key(format!(".{ext}"))
+    })?;
+    get_icon_from_resource(&file, index)
+}
@@ -0,0 +1,45 @@
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use image::RgbaImage;
+use lru::LruCache;
+use parking_lot::Mutex;
+
+use crate::state::application::FULL_STATE;
+
+/// In memory cache of the icons returned by the shell, separated from the icon packs on disk.
+static SHELL_ICONS_CACHE: LazyLock<Mutex<LruCache<PathBuf, RgbaImage>>> =
+    LazyLock::new(|| Mutex::new(LruCache::new(NonZeroUsize::MIN)));
+
+/// returns the configured capacity, `None` if the cache is disabled
+fn capacity() -> Option<NonZeroUsize> {
+    NonZeroUsize::new(FULL_STATE.load().icon_extraction.memory_cache_capacity)
+}
+
+pub fn get_cached_icon(path: &Path) -> Option<RgbaImage> {
+    let capacity = capacity()?;
+    let mut cache = SHELL_ICONS_CACHE.lock();
+    if cache.cap() != capacity {
+        cache.resize(capacity);
+    }
+    cache.get(path).cloned()
+}
+
+pub fn cache_icon(path: &Path, icon: &RgbaImage) {
+    let Some(capacity) = capacity() else {
+        return;
+    };
+    let mut cache = SHELL_ICONS_CACHE.lock();
+    if cache.cap() != capacity {
+        cache.resize(capacity);
+    }
+    cache.put(path.to_path_buf(), icon.clone());
+}
+
+/// Drops the cached icon of a file changed since it was cached, like an executable updated in place
+pub fn invalidate_cached_icon(path: &Path) {
+    SHELL_ICONS_CACHE.lock().pop(path);
+}
@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use image::RgbaImage;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::constants::SEELEN_COMMON;
+
+use super::sidecar::JsonSidecar;
+
+/// pixels less opaque than this are not part of the visible icon
+const OPAQUE_ALPHA: u8 = 128;
+/// channels above/below these values are considered white/black, not a color of the app
+const NEAR_WHITE: u8 = 235;
+const NEAR_BLACK: u8 = 20;
+/// bits kept per channel on the histogram, 4 bits = 4096 buckets
+const QUANTIZATION_BITS: u8 = 4;
+/// icons have few colors, k-means converges in a few iterations
+const KMEANS_ITERATIONS: usize = 8;
+/// representative colors stored per icon, for theming like the window borders
+const PALETTE_SIZE: usize = 4;
+
+/// icon file relative to the icons folder, like `system/abc.png` -> colors
+static ICON_COLORS: JsonSidecar<HashMap<String, IconColors>> =
+    JsonSidecar::new(colors_path, retain_existing_icons);
+
+/// Colors derived from an icon, as `#RRGGBB`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconColors {
+    /// most common color, ignoring white and black
+    pub dominant: Option<String>,
+    pub average: Option<String>,
+    /// representative colors, from the most to the least frequent
+    #[serde(default)]
+    pub palette: Vec<String>,
+}
+
+fn colors_path() -> PathBuf {
+    SEELEN_COMMON.app_cache_dir().join("icon_colors.json")
+}
+
+fn retain_existing_icons(colors: &mut HashMap<String, IconColors>) {
+    let icons_path = SEELEN_COMMON.user_icons_path();
+    colors.retain(|file, _| icons_path.join(file).exists());
+}
+
+fn to_hex([r, g, b]: [u64; 3], count: u64) -> Option<String> {
+    if count == 0 {
+        return None;
+    }
+    Some(format!(
+        "#{:02X}{:02X}{:02X}",
+        r / count,
+        g / count,
+        b / count
+    ))
+}
+
+fn bucket_key(r: u8, g: u8, b: u8) -> u16 {
+    let shift = 8 - QUANTIZATION_BITS;
+    (((r >> shift) as u16) << (2 * QUANTIZATION_BITS))
+        | (((g >> shift) as u16) << QUANTIZATION_BITS)
+        | (b >> shift) as u16
+}
+
+fn squared_distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Up to `count` representative colors of the opaque pixels, sorted from the most to the least
+/// frequent. K-means seeded with the most populated buckets of the histogram, so the result
+/// is deterministic.
+pub fn extract_dominant_colors(image: &RgbaImage, count: usize) -> Vec<[u8; 3]> {
+    let pixels: Vec<[f64; 3]> = image
+        .pixels()
+        .filter(|pixel| pixel[3] >= OPAQUE_ALPHA)
+        .map(|pixel| [pixel[0] as f64, pixel[1] as f64, pixel[2] as f64])
+        .collect();
+    if pixels.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    let mut buckets: HashMap<u16, ([f64; 3], usize)> = HashMap::new();
+    for pixel in &pixels {
+        let key = bucket_key(pixel[0] as u8, pixel[1] as u8, pixel[2] as u8);
+        let bucket = buckets.entry(key).or_default();
+        bucket
+            .0
+            .iter_mut()
+            .zip(pixel)
+            .for_each(|(sum, c)| *sum += c);
+        bucket.1 += 1;
+    }
+    let mut centroids: Vec<[f64; 3]> = buckets
+        .into_values()
+        .sorted_by(|a, b| b.1.cmp(&a.1))
+        .take(count)
+        .map(|(sums, len)| sums.map(|sum| sum / len as f64))
+        .collect();
+
+    let mut sizes = vec![0usize; centroids.len()];
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut sums = vec![([0f64; 3], 0usize); centroids.len()];
+        for pixel in &pixels {
+            let nearest = centroids
+                .iter()
+                .map(|centroid| squared_distance(centroid, pixel))
+                .position_min_by(|a, b| a.total_cmp(b))
+                .unwrap_or_default();
+            sums[nearest]
+                .0
+                .iter_mut()
+                .zip(pixel)
+                .for_each(|(sum, c)| *sum += c);
+            sums[nearest].1 += 1;
+        }
+        for (centroid, (sum, len)) in centroids.iter_mut().zip(&sums) {
+            if *len > 0 {
+                *centroid = sum.map(|sum| sum / *len as f64);
+            }
+        }
+        sizes = sums.iter().map(|(_, len)| *len).collect();
+    }
+
+    centroids
+        .into_iter()
+        .zip(sizes)
+        .filter(|(_, size)| *size > 0)
+        .sorted_by(|a, b| b.1.cmp(&a.1))
+        .map(|(centroid, _)| centroid.map(|c| c.round().clamp(0.0, 255.0) as u8))
+        .collect()
+}
+
+/// Quantized histogram over the opaque pixels, the dominant color is the average of the
+/// pixels on the most populated bucket so it is not snapped to the bucket corner.
+pub fn compute_icon_colors(image: &RgbaImage) -> IconColors {
+    let mut total = ([0u64; 3], 0u64);
+    let mut buckets: HashMap<u16, ([u64; 3], u64)> = HashMap::new();
+
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a < OPAQUE_ALPHA {
+            continue;
+        }
+        let rgb = [r as u64, g as u64, b as u64];
+        total.0.iter_mut().zip(rgb).for_each(|(sum, c)| *sum += c);
+        total.1 += 1;
+
+        let is_white = r > NEAR_WHITE && g > NEAR_WHITE && b > NEAR_WHITE;
+        let is_black = r < NEAR_BLACK && g < NEAR_BLACK && b < NEAR_BLACK;
+        if is_white || is_black {
+            continue;
+        }
+
+        let bucket = buckets.entry(bucket_key(r, g, b)).or_default();
+        bucket.0.iter_mut().zip(rgb).for_each(|(sum, c)| *sum += c);
+        bucket.1 += 1;
+    }
+
+    let average = to_hex(total.0, total.1);
+    let dominant = buckets
+        .into_values()
+        .max_by_key(|(_, count)| *count)
+        .and_then(|(sums, count)| to_hex(sums, count))
+        // icons made only of white and black
+        .or_else(|| average.clone());
+
+    let palette = extract_dominant_colors(image, PALETTE_SIZE)
+        .into_iter()
+        .map(|[r, g, b]| format!("#{r:02X}{g:02X}{b:02X}"))
+        .collect();
+
+    IconColors {
+        dominant,
+        average,
+        palette,
+    }
+}
+
+/// Computes and stores the colors of the icon file just written, replacing the previous ones
+pub fn record_icon_colors(file: &Path, image: &RgbaImage) {
+    let Ok(relative) = file.strip_prefix(SEELEN_COMMON.user_icons_path()) else {
+        return;
+    };
+    let key = relative.to_string_lossy().replace('\\', "/");
+    ICON_COLORS.lock().insert(key, compute_icon_colors(image));
+    ICON_COLORS.save();
+}
+
+/// Colors of the stored icons, by file relative to the icons folder like `system/abc.png`
+pub fn get_icon_colors() -> HashMap<String, IconColors> {
+    ICON_COLORS.lock().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn solid_icon_colors_are_its_color() {
+        let image = RgbaImage::from_pixel(16, 16, Rgba([0x20, 0x80, 0xC0, 255]));
+        let colors = compute_icon_colors(&image);
+        assert_eq!(colors.dominant.as_deref(), Some("#2080C0"));
+        assert_eq!(colors.average.as_deref(), Some("#2080C0"));
+        assert_eq!(colors.palette, ["#2080C0"]);
+    }
+
+    #[test]
+    fn two_tone_icon_dominant_is_the_most_common_tone() {
+        // 3/4 red and 1/4 blue
+        let image = RgbaImage::from_fn(16, 16, |x, _| match x < 12 {
+            true => Rgba([200, 0, 0, 255]),
+            false => Rgba([0, 0, 200, 255]),
+        });
+        let colors = compute_icon_colors(&image);
+        assert_eq!(colors.dominant.as_deref(), Some("#C80000"));
+        assert_eq!(colors.average.as_deref(), Some("#960032"));
+        assert_eq!(colors.palette, ["#C80000", "#0000C8"]);
+    }
+
+    #[test]
+    fn white_and_black_are_not_dominant() {
+        let image = RgbaImage::from_fn(16, 16, |x, y| match (x < 8, y < 4) {
+            (true, _) => Rgba([255, 255, 255, 255]),
+            (false, true) => Rgba([0, 160, 0, 255]),
+            (false, false) => Rgba([0, 0, 0, 255]),
+        });
+        assert_eq!(
+            compute_icon_colors(&image).dominant.as_deref(),
+            Some("#00A000")
+        );
+    }
+
+    #[test]
+    fn transparent_pixels_are_ignored() {
+        let mut image = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 0]));
+        image.put_pixel(0, 0, Rgba([0, 0, 255, 255]));
+        let colors = compute_icon_colors(&image);
+        assert_eq!(colors.dominant.as_deref(), Some("#0000FF"));
+        assert_eq!(colors.average.as_deref(), Some("#0000FF"));
+    }
+
+    #[test]
+    fn dominant_colors_are_sorted_by_frequency() {
+        let image = RgbaImage::from_fn(10, 10, |x, y| match (x, y) {
+            (0..2, _) => Rgba([250, 250, 250, 255]),
+            (2..7, _) => Rgba([10, 120, 10, 255]),
+            (7..10, 0..5) => Rgba([12, 118, 10, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        assert_eq!(
+            extract_dominant_colors(&image, 3),
+            [[10, 120, 10], [250, 250, 250]]
+        );
+        assert_eq!(extract_dominant_colors(&image, 1).len(), 1);
+        assert!(extract_dominant_colors(&image, 0).is_empty());
+    }
+
+    #[test]
+    fn fully_transparent_icon_has_no_colors() {
+        let image = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 0]));
+        assert_eq!(compute_icon_colors(&image), IconColors::default());
+    }
+}
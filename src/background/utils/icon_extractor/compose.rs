@@ -0,0 +1,45 @@
+use image::{imageops, Rgba, RgbaImage};
+
+/// portion of the circle diameter used by the foreground, similar to the Windows 11 start menu
+const FOREGROUND_SCALE: f32 = 0.66;
+
+/// Parses the `BackgroundColor` of the manifest visual elements, `#RRGGBB` or `#AARRGGBB`.
+/// `transparent` and named colors are not composed.
+pub fn parse_background_color(value: &str) -> Option<Rgba<u8>> {
+    let hex = value.trim().strip_prefix('#')?;
+    let channel = |idx: usize| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok();
+    match hex.len() {
+        6 => Some(Rgba([channel(0)?, channel(2)?, channel(4)?, 255])),
+        8 => Some(Rgba([channel(2)?, channel(4)?, channel(6)?, channel(0)?])),
+        _ => None,
+    }
+    .filter(|color| color[3] > 0)
+}
+
+/// Anti-aliased coverage of a circle of `size` diameter at the center of the pixel.
+fn circle_coverage(x: u32, y: u32, size: u32) -> f32 {
+    let radius = size as f32 / 2.0;
+    let distance = (x as f32 + 0.5 - radius).hypot(y as f32 + 0.5 - radius) - radius;
+    (0.5 - distance).clamp(0.0, 1.0)
+}
+
+/// Draws the foreground layer of an UWP app centered on a circle filled with its background color.
+/// The canvas grows around the foreground, so it keeps its resolution.
+pub fn compose_on_background(foreground: &RgbaImage, background: Rgba<u8>) -> RgbaImage {
+    let (width, height) = foreground.dimensions();
+    let size = (width.max(height) as f32 / FOREGROUND_SCALE).round() as u32;
+    let mut composed = RgbaImage::from_fn(size, size, |x, y| {
+        let alpha = background[3] as f32 * circle_coverage(x, y, size);
+        Rgba([
+            background[0],
+            background[1],
+            background[2],
+            alpha.round() as u8,
+        ])
+    });
+
+    let x = (size - width) / 2;
+    let y = (size - height) / 2;
+    imageops::overlay(&mut composed, foreground, x as i64, y as i64);
+    composed
+}
@@ -0,0 +1,122 @@
+use std::fmt::{Debug, Display};
+
+use crate::error::{AppError, Result};
+
+/// Step of the extraction chain where the error happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconExtractionStage {
+    /// the path can't be used as key, like paths without file name
+    InvalidPath,
+    /// asking the shell, the window or the resources of a file for the icon
+    ShellLookup,
+    /// resolving the target of a shortcut or the shortcut of an umid
+    LnkResolution,
+    HiconConversion,
+    Dib,
+    /// locating or decoding the logo of an UWP package
+    UwpAsset,
+    Save,
+    PackWrite,
+}
+
+/// Error of the icon extraction, converted into `AppError` its message includes the stage
+/// and the path or umid being extracted.
+pub struct IconExtractionError {
+    stage: IconExtractionStage,
+    key: Option<String>,
+    source: String,
+}
+
+impl IconExtractionError {
+    pub fn new(stage: IconExtractionStage, source: impl Display) -> Self {
+        Self {
+            stage,
+            key: None,
+            source: source.to_string(),
+        }
+    }
+
+    pub fn with_key(mut self, key: impl Display) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+}
+
+impl Display for IconExtractionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "{:?} failed for {key}: {}", self.stage, self.source),
+            None => write!(f, "{:?} failed: {}", self.stage, self.source),
+        }
+    }
+}
+
+impl Debug for IconExtractionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+pub trait IconExtractionResultExt<T> {
+    /// Wraps the error with the stage and the key being extracted
+    fn at_stage(self, stage: IconExtractionStage, key: impl Display) -> Result<T>;
+}
+
+impl<T, E: Into<AppError>> IconExtractionResultExt<T> for core::result::Result<T, E> {
+    fn at_stage(self, stage: IconExtractionStage, key: impl Display) -> Result<T> {
+        self.map_err(|err| {
+            // only the message, the backtrace is already part of the new error
+            let source = format!("{:?}", err.into());
+            let source = source.lines().next().unwrap_or_default();
+            IconExtractionError::new(stage, source).with_key(key).into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn first_line(err: AppError) -> String {
+        format!("{err:?}")
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned()
+    }
+
+    #[test]
+    fn lnk_without_target_names_the_shortcut() {
+        let lnk = Path::new(r"C:\ProgramData\Microsoft\Windows\Start Menu\Programs\App.lnk");
+        let err = Err::<(), AppError>("the shortcut has no target".into())
+            .at_stage(IconExtractionStage::LnkResolution, lnk.display())
+            .unwrap_err();
+        assert_eq!(
+            first_line(err),
+            r#"IconExtraction(LnkResolution failed for C:\ProgramData\Microsoft\Windows\Start Menu\Programs\App.lnk: App("the shortcut has no target"))"#
+        );
+    }
+
+    #[test]
+    fn missing_uwp_asset_names_the_asset() {
+        let asset = std::env::temp_dir().join("slu-missing-asset/Square44x44Logo.scale-200.png");
+        let err = image::open(&asset)
+            .at_stage(IconExtractionStage::UwpAsset, asset.display())
+            .unwrap_err();
+        let message = first_line(err);
+        let expected = format!("IconExtraction(UwpAsset failed for {}: ", asset.display());
+        assert!(message.starts_with(&expected), "{message}");
+    }
+
+    #[test]
+    fn errors_without_key_only_name_the_stage() {
+        let err = IconExtractionError::new(IconExtractionStage::Dib, "8bpp bitmap, expected 32bpp");
+        assert_eq!(err.to_string(), "Dib failed: 8bpp bitmap, expected 32bpp");
+        assert_eq!(
+            first_line(err.into()),
+            "IconExtraction(Dib failed: 8bpp bitmap, expected 32bpp)"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::{path::Path, sync::LazyLock};
+
+use parking_lot::Mutex;
+use regex::Regex;
+
+use crate::{
+    state::application::FULL_STATE,
+    windows_api::{string_utils::WindowsString, WindowsApi},
+};
+
+struct ExclusionPattern {
+    /// patterns without folders, like `*.url`, are matched against the file name only
+    file_name_only: bool,
+    regex: Regex,
+}
+
+/// patterns compiled from the list on settings, compiled again when the list changes
+static EXCLUSIONS: LazyLock<Mutex<(Vec<String>, Vec<ExclusionPattern>)>> =
+    LazyLock::new(|| Mutex::new((Vec::new(), Vec::new())));
+
+/// `**` matches any amount of folders, `*` and `?` don't cross folders, case insensitive.
+fn glob_to_regex(pattern: &str) -> std::result::Result<Regex, regex::Error> {
+    let mut regex = String::from("(?i)^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**\` also matches no folder at all
+                if chars.peek() == Some(&'\\') {
+                    chars.next();
+                    regex.push_str(r"(?:.*\\)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str(r"[^\\]*"),
+            '?' => regex.push_str(r"[^\\]"),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex)
+}
+
+fn compile_pattern(pattern: &str) -> Option<ExclusionPattern> {
+    let expanded = WindowsApi::resolve_environment_variables(&WindowsString::from_str(pattern))
+        .map(|expanded| expanded.to_string())
+        .unwrap_or_else(|_| pattern.to_string())
+        .replace('/', "\\");
+
+    match glob_to_regex(&expanded) {
+        Ok(regex) => Some(ExclusionPattern {
+            file_name_only: !expanded.contains('\\'),
+            regex,
+        }),
+        Err(err) => {
+            log::warn!("Invalid icon extraction exclusion pattern {pattern}: {err}");
+            None
+        }
+    }
+}
+
+/// The user doesn't want the icon of this file to be extracted, see `excluded_paths` on settings.
+pub fn is_excluded_from_extraction(path: &Path) -> bool {
+    let state = FULL_STATE.load();
+    let patterns = &state.icon_extraction.excluded_paths;
+    if patterns.is_empty() {
+        return false;
+    }
+
+    let mut exclusions = EXCLUSIONS.lock();
+    if &exclusions.0 != patterns {
+        exclusions.1 = patterns.iter().filter_map(|p| compile_pattern(p)).collect();
+        exclusions.0 = patterns.clone();
+    }
+
+    let full_path = path.to_string_lossy().replace('/', "\\");
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+
+    exclusions.1.iter().any(|pattern| {
+        if pattern.file_name_only {
+            pattern.regex.is_match(&file_name)
+        } else {
+            pattern.regex.is_match(&full_path)
+        }
+    })
+}
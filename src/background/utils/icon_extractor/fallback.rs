@@ -0,0 +1,286 @@
+use image::{Rgba, RgbaImage};
+use windows::core::w;
+use windows::Win32::{
+    Foundation::{COLORREF, RECT},
+    Graphics::Gdi::{
+        CreateCompatibleDC, CreateDIBSection, CreateFontW, DeleteDC, DeleteObject, DrawTextW,
+        SelectObject, SetBkMode, SetTextColor, ANTIALIASED_QUALITY, BITMAPINFO, BITMAPINFOHEADER,
+        CLIP_DEFAULT_PRECIS, DEFAULT_CHARSET, DIB_RGB_COLORS, DT_CENTER, DT_NOPREFIX,
+        DT_SINGLELINE, DT_VCENTER, FW_SEMIBOLD, OUT_DEFAULT_PRECIS, TRANSPARENT,
+    },
+};
+
+use crate::error::Result;
+
+/// file name prefix used to mark generated icons on the system icon pack,
+/// so a later successful extraction can replace them.
+pub const FALLBACK_ICON_PREFIX: &str = "fallback_";
+
+const FALLBACK_ICON_SIZE: u32 = 256;
+const FALLBACK_CORNER_RADIUS: f32 = 0.22;
+
+/// Material-like palette, enough contrast with white text on all of them.
+const FALLBACK_PALETTE: [[u8; 3]; 12] = [
+    [0xE5, 0x39, 0x35],
+    [0xD8, 0x1B, 0x60],
+    [0x8E, 0x24, 0xAA],
+    [0x5E, 0x35, 0xB1],
+    [0x39, 0x49, 0xAB],
+    [0x1E, 0x88, 0xE5],
+    [0x00, 0x89, 0x7B],
+    [0x43, 0xA0, 0x47],
+    [0x7C, 0xB3, 0x42],
+    [0xF4, 0x51, 0x1E],
+    [0x6D, 0x4C, 0x41],
+    [0x54, 0x6E, 0x7A],
+];
+
+/// FNV-1a, used instead of `DefaultHasher` because the color needs to be stable across builds.
+fn stable_hash(value: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in value.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn is_grapheme_extender(c: char) -> bool {
+    matches!(c,
+        '\u{200D}' // zero width joiner
+        | '\u{FE00}'..='\u{FE0F}' // variation selectors
+        | '\u{1F3FB}'..='\u{1F3FF}' // skin tone modifiers
+        | '\u{E0020}'..='\u{E007F}' // tags
+        | '\u{20E3}' // combining enclosing keycap
+    )
+}
+
+/// Takes the first user perceived character, approximated enough for emoji sequences.
+fn first_grapheme(word: &str) -> String {
+    let mut result = String::new();
+    let mut chars = word.chars().peekable();
+    let Some(first) = chars.next() else {
+        return result;
+    };
+    result.push(first);
+
+    let mut joined = false;
+    while let Some(&next) = chars.peek() {
+        if is_grapheme_extender(next) {
+            joined = next == '\u{200D}';
+            result.push(next);
+            chars.next();
+        } else if joined {
+            joined = false;
+            result.push(next);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    result
+}
+
+/// Returns the text to be drawn on the fallback icon, one or two initials.
+pub fn get_initials(name: &str) -> String {
+    let words: Vec<&str> = name
+        .split(|c: char| c.is_whitespace() || matches!(c, '-' | '_' | '.' | '(' | ')'))
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let Some(first_word) = words.first() else {
+        return "?".to_owned();
+    };
+
+    let first = first_grapheme(first_word);
+    // only latin-like scripts use two initials, CJK names and emojis are already dense
+    let is_alphabetic = first.chars().all(|c| c.is_ascii_alphanumeric());
+    if !is_alphabetic {
+        return first;
+    }
+
+    let mut initials = first.to_uppercase();
+    if let Some(second) = words
+        .get(1)
+        .map(|w| first_grapheme(w))
+        .filter(|g| g.chars().all(|c| c.is_ascii_alphanumeric()))
+    {
+        initials.push_str(&second.to_uppercase());
+    }
+    initials
+}
+
+/// Renders the text using GDI, so the system font linking handles CJK and emojis.
+/// Returns the coverage of the text per pixel.
+fn render_text_coverage(text: &str, size: u32) -> Result<Vec<u8>> {
+    unsafe {
+        let hdc = CreateCompatibleDC(None);
+        let bmp_info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                biWidth: size as i32,
+                biHeight: -(size as i32),
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: DIB_RGB_COLORS.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut bits: *mut std::ffi::c_void = std::ptr::null_mut();
+        let bitmap = CreateDIBSection(Some(hdc), &bmp_info, DIB_RGB_COLORS, &mut bits, None, 0)?;
+        let old_bitmap = SelectObject(hdc, bitmap.into());
+
+        let font_height = if text.chars().count() > 1 {
+            size as i32 * 2 / 5
+        } else {
+            size as i32 / 2
+        };
+        let font = CreateFontW(
+            font_height,
+            0,
+            0,
+            0,
+            FW_SEMIBOLD.0 as i32,
+            0,
+            0,
+            0,
+            DEFAULT_CHARSET,
+            OUT_DEFAULT_PRECIS,
+            CLIP_DEFAULT_PRECIS,
+            ANTIALIASED_QUALITY,
+            0,
+            w!("Segoe UI"),
+        );
+        let old_font = SelectObject(hdc, font.into());
+
+        SetBkMode(hdc, TRANSPARENT);
+        SetTextColor(hdc, COLORREF(0x00FFFFFF));
+
+        let mut rect = RECT {
+            left: 0,
+            top: 0,
+            right: size as i32,
+            bottom: size as i32,
+        };
+        let mut wide_text: Vec<u16> = text.encode_utf16().collect();
+        DrawTextW(
+            hdc,
+            &mut wide_text,
+            &mut rect,
+            DT_CENTER | DT_VCENTER | DT_SINGLELINE | DT_NOPREFIX,
+        );
+
+        let pixels = std::slice::from_raw_parts(bits as *const u8, (size * size * 4) as usize);
+        // text is white over black so any color channel is the coverage
+        let coverage = pixels
+            .chunks_exact(4)
+            .map(|px| px[0].max(px[1]).max(px[2]))
+            .collect();
+
+        SelectObject(hdc, old_font);
+        SelectObject(hdc, old_bitmap);
+        DeleteObject(font.into()).ok()?;
+        DeleteObject(bitmap.into()).ok()?;
+        DeleteDC(hdc).ok()?;
+        Ok(coverage)
+    }
+}
+
+/// Anti-aliased coverage of a rounded square of `size` at the center of the pixel.
+fn rounded_square_coverage(x: u32, y: u32, size: u32) -> f32 {
+    let half = size as f32 / 2.0;
+    let radius = size as f32 * FALLBACK_CORNER_RADIUS;
+    let px = (x as f32 + 0.5 - half).abs() - (half - radius);
+    let py = (y as f32 + 0.5 - half).abs() - (half - radius);
+    let distance = px.max(0.0).hypot(py.max(0.0)) - radius;
+    (0.5 - distance).clamp(0.0, 1.0)
+}
+
+/// Generates a letter-avatar icon used when every extraction strategy failed.
+/// The background color is picked from the key so it's stable across runs.
+pub fn generate_fallback_icon(name: &str) -> RgbaImage {
+    let size = FALLBACK_ICON_SIZE;
+    let background = FALLBACK_PALETTE[(stable_hash(name) % FALLBACK_PALETTE.len() as u64) as usize];
+    let initials = get_initials(name);
+
+    let coverage = match render_text_coverage(&initials, size) {
+        Ok(coverage) => coverage,
+        Err(err) => {
+            log::warn!("Failed to render fallback icon text for {name}: {err}");
+            vec![0; (size * size) as usize]
+        }
+    };
+
+    RgbaImage::from_fn(size, size, |x, y| {
+        let shape = rounded_square_coverage(x, y, size);
+        if shape == 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let text = coverage[(y * size + x) as usize] as f32 / 255.0;
+        let blend = |channel: u8| (channel as f32 * (1.0 - text) + 255.0 * text).round() as u8;
+        Rgba([
+            blend(background[0]),
+            blend(background[1]),
+            blend(background[2]),
+            (shape * 255.0).round() as u8,
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initials_of_latin_names() {
+        assert_eq!(get_initials("Visual Studio Code"), "VS");
+        assert_eq!(get_initials("firefox"), "F");
+        assert_eq!(get_initials("7-zip"), "7Z");
+        assert_eq!(get_initials("notepad++ (x64)"), "NX");
+    }
+
+    #[test]
+    fn initials_of_dense_scripts_and_emojis() {
+        assert_eq!(get_initials("网易云音乐"), "网");
+        assert_eq!(get_initials("👍🏽 App"), "👍🏽");
+        assert_eq!(get_initials("👨‍👩‍👧 Family"), "👨‍👩‍👧");
+    }
+
+    #[test]
+    fn initials_of_empty_names() {
+        assert_eq!(get_initials(""), "?");
+        assert_eq!(get_initials("  -_ "), "?");
+    }
+
+    #[test]
+    fn hash_is_stable_across_builds() {
+        // FNV-1a test vectors
+        assert_eq!(stable_hash(""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash("a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn fallback_icon_is_deterministic() {
+        let name = "Some Installed App";
+        let icon = generate_fallback_icon(name);
+        assert_eq!(icon, generate_fallback_icon(name));
+        assert_eq!(icon.dimensions(), (FALLBACK_ICON_SIZE, FALLBACK_ICON_SIZE));
+
+        let expected =
+            FALLBACK_PALETTE[(stable_hash(name) % FALLBACK_PALETTE.len() as u64) as usize];
+        // top edge, inside the rounded square and away from the text
+        let pixel = icon.get_pixel(FALLBACK_ICON_SIZE / 2, 8);
+        assert_eq!(pixel.0, [expected[0], expected[1], expected[2], 255]);
+    }
+
+    #[test]
+    fn fallback_icon_has_transparent_corners() {
+        let icon = generate_fallback_icon("App");
+        let last = FALLBACK_ICON_SIZE - 1;
+        for (x, y) in [(0, 0), (last, 0), (0, last), (last, last)] {
+            assert_eq!(icon.get_pixel(x, y).0[3], 0);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use image::{
+    codecs::{
+        ico::{IcoEncoder, IcoFrame},
+        png::PngEncoder,
+    },
+    ExtendedColorType, ImageEncoder, RgbaImage,
+};
+use serde::Deserialize;
+
+use crate::{
+    error::Result, state::application::FULL_STATE, trace_lock, utils::constants::SEELEN_COMMON,
+};
+
+use super::{apply_icon_mask, resize_icon, IconExtractionKey};
+
+/// sizes used by the shell on the different views and scale factors
+const ICO_FRAME_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+
+/// frames of this size or bigger are stored as png, like the shell does
+const PNG_FRAME_MIN_SIZE: u32 = 256;
+
+/// Source of the entries with light and dark variants, ignored by the others
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IcoExportVariant {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// 32 bits DIB as stored inside .ico files: the height of the header includes the AND mask,
+/// rows are bottom-up BGRA, and the mask is empty because the alpha channel is used instead.
+fn encode_dib_frame(frame: &RgbaImage) -> Vec<u8> {
+    let (width, height) = frame.dimensions();
+    let mask_stride = width.div_ceil(32) * 4;
+    let image_size = width * height * 4 + mask_stride * height;
+
+    let mut dib = Vec::with_capacity(40 + image_size as usize);
+    dib.extend_from_slice(&40u32.to_le_bytes()); // biSize
+    dib.extend_from_slice(&(width as i32).to_le_bytes());
+    dib.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+    dib.extend_from_slice(&1u16.to_le_bytes()); // biPlanes
+    dib.extend_from_slice(&32u16.to_le_bytes()); // biBitCount
+    dib.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB
+    dib.extend_from_slice(&image_size.to_le_bytes());
+    dib.extend_from_slice(&[0; 16]); // resolution and palette, unused
+
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let [r, g, b, a] = frame.get_pixel(x, y).0;
+            dib.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+    dib.resize(dib.len() + (mask_stride * height) as usize, 0);
+    dib
+}
+
+/// Writes the image as an .ico with a frame for each of the common shell sizes
+pub fn write_multi_frame_ico(source: &RgbaImage, destination: &Path) -> Result<()> {
+    let mut encoded_frames = Vec::new();
+    for size in ICO_FRAME_SIZES {
+        let frame = resize_icon(source, size);
+        let encoded = match size >= PNG_FRAME_MIN_SIZE {
+            true => {
+                let mut png = Vec::new();
+                PngEncoder::new(&mut png).write_image(
+                    frame.as_raw(),
+                    size,
+                    size,
+                    ExtendedColorType::Rgba8,
+                )?;
+                png
+            }
+            false => encode_dib_frame(&frame),
+        };
+        encoded_frames.push((size, encoded));
+    }
+
+    let frames = encoded_frames
+        .iter()
+        .map(|(size, encoded)| {
+            IcoFrame::with_encoded(encoded.as_slice(), *size, *size, ExtendedColorType::Rgba8)
+        })
+        .collect::<image::ImageResult<Vec<_>>>()?;
+
+    IcoEncoder::new(BufWriter::new(File::create(destination)?)).encode_images(&frames)?;
+    Ok(())
+}
+
+fn validate_destination(destination: &Path) -> Result<()> {
+    if !destination.is_absolute() {
+        return Err("The destination must be an absolute path".into());
+    }
+    if !destination
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ico"))
+    {
+        return Err("The destination must be an .ico file".into());
+    }
+    if !destination.parent().is_some_and(|parent| parent.is_dir()) {
+        return Err("The destination folder doesn't exist".into());
+    }
+    if destination.starts_with(SEELEN_COMMON.user_icons_path()) {
+        return Err("The destination can't be inside the icon packs folder".into());
+    }
+    Ok(())
+}
+
+/// Exports the icon stored on the system pack for the app as a multi-frame .ico
+pub fn export_icon_as_ico(
+    key: &IconExtractionKey,
+    destination: &Path,
+    variant: IcoExportVariant,
+) -> Result<()> {
+    validate_destination(destination)?;
+    let source = {
+        let mutex = FULL_STATE.load().icon_packs().clone();
+        let icon_manager = trace_lock!(mutex);
+        icon_manager.get_stored_app_icon_files(key, matches!(variant, IcoExportVariant::Dark))
+    };
+    let (source, mask) = source.ok_or("There is no stored icon for the app")?;
+
+    let source = image::open(source)?;
+    let mut image = source.to_rgba8();
+    // images with alpha already have their transparency, the mask is their silhouette
+    if let Some(mask) = mask.filter(|_| !source.color().has_alpha()) {
+        apply_icon_mask(&mut image, &image::open(mask)?);
+    }
+    write_multi_frame_ico(&image, destination)
+}
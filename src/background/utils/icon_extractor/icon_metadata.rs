@@ -0,0 +1,68 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use image::RgbaImage;
+use seelen_core::state::Icon;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::constants::SEELEN_COMMON;
+
+use super::sidecar::{system_pack_file, JsonSidecar};
+
+/// file name on the system pack -> metadata of its extraction
+static ICON_METADATA: JsonSidecar<HashMap<String, IconMetadata>> =
+    JsonSidecar::new(icon_metadata_path, retain_existing_icons);
+
+/// Where an extracted icon came from and when, to decide if it is stale
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconMetadata {
+    pub source_path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// bits per pixel of the stored image
+    pub bit_depth: u32,
+    pub extracted_at: SystemTime,
+}
+
+fn icon_metadata_path() -> PathBuf {
+    system_pack_file(".icon_metadata.json")
+}
+
+fn retain_existing_icons(metadata: &mut HashMap<String, IconMetadata>) {
+    let system_icons = SEELEN_COMMON.user_icons_path().join("system");
+    metadata.retain(|file, _| system_icons.join(file).exists());
+}
+
+/// Records the metadata of the image files of the icon just extracted from `source`,
+/// the light and dark variants share the dimensions of `image`.
+pub fn record_icon_metadata(source: &Path, icon: &Icon, image: &RgbaImage) {
+    let metadata = IconMetadata {
+        source_path: source.to_path_buf(),
+        width: image.width(),
+        height: image.height(),
+        bit_depth: image.sample_layout().channels as u32 * 8,
+        extracted_at: SystemTime::now(),
+    };
+
+    let mut stored = ICON_METADATA.lock();
+    for file in [&icon.base, &icon.light, &icon.dark].into_iter().flatten() {
+        stored.insert(file.clone(), metadata.clone());
+    }
+    drop(stored);
+    ICON_METADATA.save();
+}
+
+/// Metadata of a file of the system pack, by its file name
+pub fn get_stored_icon_metadata(file: &str) -> Option<IconMetadata> {
+    ICON_METADATA.lock().get(file).cloned()
+}
+
+/// Forgets all the metadata, used when the system icons are deleted
+pub fn clear_all_icon_metadata() {
+    ICON_METADATA.lock().clear();
+    ICON_METADATA.save();
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf, Prefix},
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use windows::Win32::Storage::FileSystem::GetDriveTypeW;
+
+use crate::windows_api::string_utils::WindowsString;
+
+// https://learn.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-getdrivetypew
+const DRIVE_NO_ROOT_DIR: u32 = 1;
+const DRIVE_REMOVABLE: u32 = 2;
+const DRIVE_REMOTE: u32 = 4;
+const DRIVE_CDROM: u32 = 5;
+
+/// time to wait before trying again a path that timed out
+const RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+static RETRY_AFTER: LazyLock<Mutex<HashMap<PathBuf, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathLocation {
+    /// fixed disks and ram disks
+    Local,
+    /// UNC paths and mapped drives, the shell can block while SMB times out
+    Network,
+    /// usb drives, card readers and optical drives
+    Removable,
+    /// the volume doesn't exist, like a disconnected drive letter
+    Unavailable,
+}
+
+impl PathLocation {
+    /// Classifies the path without touching the file system of the volume itself.
+    pub fn of(path: &Path) -> Self {
+        let root = match path.components().next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::UNC(..) | Prefix::VerbatimUNC(..) => return PathLocation::Network,
+                Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                    format!("{}:\\", letter as char)
+                }
+                // device namespace and others, let the shell handle them
+                _ => return PathLocation::Local,
+            },
+            // relative paths are on the current drive
+            _ => return PathLocation::Local,
+        };
+
+        match unsafe { GetDriveTypeW(WindowsString::from_str(&root).as_pcwstr()) } {
+            DRIVE_REMOTE => PathLocation::Network,
+            DRIVE_REMOVABLE | DRIVE_CDROM => PathLocation::Removable,
+            DRIVE_NO_ROOT_DIR => PathLocation::Unavailable,
+            _ => PathLocation::Local,
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self, PathLocation::Local)
+    }
+}
+
+/// Marks the path to be skipped until `RETRY_DELAY` has elapsed.
+pub fn mark_retry_later(path: &Path) {
+    let mut retry_after = RETRY_AFTER.lock();
+    retry_after.retain(|_, instant| *instant > Instant::now());
+    retry_after.insert(path.to_path_buf(), Instant::now() + RETRY_DELAY);
+}
+
+pub fn is_retry_pending(path: &Path) -> bool {
+    RETRY_AFTER
+        .lock()
+        .get(path)
+        .is_some_and(|instant| *instant > Instant::now())
+}
+
+pub fn clear_retry_later(path: &Path) {
+    RETRY_AFTER.lock().remove(path);
+}
@@ -0,0 +1,103 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+/// Generates a white-on-transparent silhouette of the icon from its alpha channel,
+/// used by the frontend for themed tinting.
+///
+/// `feather` is the sigma of a gaussian blur applied to the silhouette edges, 0 disables it.
+pub fn generate_icon_mask(image: &RgbaImage, feather: f32) -> RgbaImage {
+    // color channels are white even on transparent pixels so blurring doesn't darken the edges
+    let mask = RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        Rgba([255, 255, 255, image.get_pixel(x, y)[3]])
+    });
+
+    if feather > 0.0 {
+        image::imageops::blur(&mask, feather)
+    } else {
+        mask
+    }
+}
+
+/// Multiplies the coverage of the mask into the alpha of the image, for icons coming from
+/// sources without alpha channel like jpeg thumbnails. Both grayscale masks and the
+/// white-on-transparent silhouettes of `generate_icon_mask` are supported.
+pub fn apply_icon_mask(image: &mut RgbaImage, mask: &DynamicImage) {
+    let (width, height) = image.dimensions();
+    let mask = match mask.dimensions() == (width, height) {
+        true => mask.to_luma_alpha8(),
+        false => mask
+            .resize_exact(width, height, FilterType::Triangle)
+            .to_luma_alpha8(),
+    };
+
+    for (pixel, mask_pixel) in image.pixels_mut().zip(mask.pixels()) {
+        let [luma, alpha] = mask_pixel.0;
+        let coverage = luma as u32 * alpha as u32 / 255;
+        pixel[3] = (pixel[3] as u32 * coverage / 255) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn icon_with_alpha(alphas: &[u8]) -> RgbaImage {
+        RgbaImage::from_fn(alphas.len() as u32, 1, |x, _| {
+            Rgba([10, 200, 30, alphas[x as usize]])
+        })
+    }
+
+    #[test]
+    fn mask_is_a_white_silhouette_of_the_alpha() {
+        let mask = generate_icon_mask(&icon_with_alpha(&[0, 64, 255]), 0.0);
+        let pixels: Vec<_> = mask.pixels().map(|p| p.0).collect();
+        assert_eq!(
+            pixels,
+            [
+                [255, 255, 255, 0],
+                [255, 255, 255, 64],
+                [255, 255, 255, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn feathered_mask_softens_the_edges() {
+        let icon = RgbaImage::from_fn(16, 16, |x, _| match x < 8 {
+            true => Rgba([0, 0, 0, 255]),
+            false => Rgba([0, 0, 0, 0]),
+        });
+        let mask = generate_icon_mask(&icon, 1.5);
+        let edge = mask.get_pixel(8, 8);
+        assert!(edge[3] > 0 && edge[3] < 255);
+        // blurring white with transparent white keeps it white
+        assert!(edge.0[..3].iter().all(|channel| *channel >= 254));
+    }
+
+    #[test]
+    fn apply_grayscale_mask() {
+        let mut icon = icon_with_alpha(&[255, 255, 128]);
+        let mask = image::GrayImage::from_fn(3, 1, |x, _| image::Luma([[0, 128, 255][x as usize]]));
+        apply_icon_mask(&mut icon, &DynamicImage::ImageLuma8(mask));
+        let alphas: Vec<_> = icon.pixels().map(|p| p[3]).collect();
+        assert_eq!(alphas, [0, 128, 128]);
+        // colors are untouched
+        assert_eq!(&icon.get_pixel(1, 0).0[..3], &[10, 200, 30]);
+    }
+
+    #[test]
+    fn apply_silhouette_mask() {
+        let mut icon = icon_with_alpha(&[255, 255]);
+        let mask = generate_icon_mask(&icon_with_alpha(&[0, 255]), 0.0);
+        apply_icon_mask(&mut icon, &DynamicImage::ImageRgba8(mask));
+        let alphas: Vec<_> = icon.pixels().map(|p| p[3]).collect();
+        assert_eq!(alphas, [0, 255]);
+    }
+
+    #[test]
+    fn masks_of_other_sizes_are_resized() {
+        let mut icon = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 255]));
+        let mask = image::GrayImage::from_pixel(2, 2, image::Luma([51]));
+        apply_icon_mask(&mut icon, &DynamicImage::ImageLuma8(mask));
+        assert!(icon.pixels().all(|p| p[3] == 51));
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::state::application::FULL_STATE;
+
+/// max amount of files kept on the breakdown of the last batch
+const MAX_BATCH_BREAKDOWN: usize = 500;
+
+static METRICS: LazyLock<Mutex<IconExtractionMetrics>> =
+    LazyLock::new(|| Mutex::new(IconExtractionMetrics::default()));
+
+thread_local! {
+    /// timings of the file being extracted as part of a batch on this thread
+    static FILE_BREAKDOWN: RefCell<Option<Vec<(MetricStage, Duration)>>> = const { RefCell::new(None) };
+}
+
+/// Timed steps of the extraction, they can be nested: the shell lookup includes
+/// the conversion and the crop of the icon it gives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MetricStage {
+    ShellLookup,
+    HiconConversion,
+    Crop,
+    Save,
+    PackWrite,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageMetrics {
+    pub count: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileExtractionTimings {
+    pub path: PathBuf,
+    pub total_us: u64,
+    /// duration in microseconds of each stage, in the order they ended
+    pub stages: Vec<(MetricStage, u64)>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconExtractionMetrics {
+    pub extractions: u64,
+    pub stages: BTreeMap<MetricStage, StageMetrics>,
+    /// per file timings of the last batch, to spot pathological files
+    pub last_batch: Vec<FileExtractionTimings>,
+}
+
+fn is_enabled() -> bool {
+    FULL_STATE.load().icon_extraction.collect_metrics
+}
+
+/// Records the duration of the stage when dropped, does nothing if metrics are disabled.
+pub struct StageTimer {
+    stage: MetricStage,
+    started: Option<Instant>,
+}
+
+impl StageTimer {
+    pub fn start(stage: MetricStage) -> Self {
+        Self {
+            stage,
+            started: is_enabled().then(Instant::now),
+        }
+    }
+}
+
+impl Drop for StageTimer {
+    fn drop(&mut self) {
+        let Some(started) = self.started else {
+            return;
+        };
+        let elapsed = started.elapsed();
+        let micros = elapsed.as_micros() as u64;
+
+        {
+            let mut metrics = METRICS.lock();
+            let stage = metrics.stages.entry(self.stage).or_default();
+            stage.count += 1;
+            stage.total_us += micros;
+            stage.max_us = stage.max_us.max(micros);
+        }
+
+        FILE_BREAKDOWN.with_borrow_mut(|breakdown| {
+            if let Some(breakdown) = breakdown {
+                breakdown.push((self.stage, elapsed));
+            }
+        });
+    }
+}
+
+/// Counts a finished extraction, dumping the metrics to the log every `metrics_log_interval`.
+pub fn record_extraction() {
+    let state = FULL_STATE.load();
+    let settings = &state.icon_extraction;
+    if !settings.collect_metrics {
+        return;
+    }
+
+    let mut metrics = METRICS.lock();
+    metrics.extractions += 1;
+    if settings.metrics_log_interval == 0
+        || metrics.extractions % settings.metrics_log_interval as u64 != 0
+    {
+        return;
+    }
+
+    log::debug!(
+        "Icon extraction metrics after {} extractions:",
+        metrics.extractions
+    );
+    for (stage, stage_metrics) in &metrics.stages {
+        log::debug!(
+            "  {stage:?}: {} calls, {}ms total, {}us avg, {}us max",
+            stage_metrics.count,
+            stage_metrics.total_us / 1000,
+            stage_metrics.total_us / stage_metrics.count.max(1),
+            stage_metrics.max_us,
+        );
+    }
+}
+
+/// Clears the breakdown of the previous batch
+pub fn begin_batch_breakdown() {
+    if is_enabled() {
+        METRICS.lock().last_batch.clear();
+    }
+}
+
+/// Starts collecting the stages of the file extracted on this thread
+pub fn begin_file_breakdown() -> Option<Instant> {
+    if !is_enabled() {
+        return None;
+    }
+    FILE_BREAKDOWN.set(Some(Vec::new()));
+    Some(Instant::now())
+}
+
+/// Adds the stages collected since `begin_file_breakdown` to the breakdown of the batch
+pub fn end_file_breakdown(path: PathBuf, started: Option<Instant>) {
+    let Some(started) = started else {
+        return;
+    };
+    let stages = FILE_BREAKDOWN.take().unwrap_or_default();
+    let mut metrics = METRICS.lock();
+    if metrics.last_batch.len() >= MAX_BATCH_BREAKDOWN {
+        return;
+    }
+    metrics.last_batch.push(FileExtractionTimings {
+        path,
+        total_us: started.elapsed().as_micros() as u64,
+        stages: stages
+            .into_iter()
+            .map(|(stage, elapsed)| (stage, elapsed.as_micros() as u64))
+            .collect(),
+    });
+}
+
+pub fn get_metrics() -> IconExtractionMetrics {
+    METRICS.lock().clone()
+}
@@ -1,9 +1,79 @@
+mod alpha;
+mod appx;
+mod association;
+mod cache;
+mod colors;
+mod compose;
+mod error;
+mod exclusion;
+mod fallback;
+mod ico_export;
+mod icon_metadata;
+mod location;
+mod mask;
+mod metrics;
+mod msc;
+mod negative;
+mod pending;
+mod pool;
+mod preview;
+mod protocol;
 mod queue;
+mod refresh;
+mod report;
+mod shape;
+mod shell_watchdog;
+mod sidecar;
+mod storage;
+mod theme_variants;
+
+use alpha::normalize_alpha;
+use appx::get_icon_from_appx_package;
+pub use association::get_icon_from_registry_association;
+use cache::{cache_icon, get_cached_icon, invalidate_cached_icon};
+pub use colors::{extract_dominant_colors, get_icon_colors, record_icon_colors, IconColors};
+use compose::{compose_on_background, parse_background_color};
+pub use error::{IconExtractionError, IconExtractionResultExt, IconExtractionStage};
+pub use exclusion::is_excluded_from_extraction;
+pub use fallback::{generate_fallback_icon, FALLBACK_ICON_PREFIX};
+pub use ico_export::{export_icon_as_ico, IcoExportVariant};
+pub use icon_metadata::{
+    clear_all_icon_metadata, get_stored_icon_metadata, record_icon_metadata, IconMetadata,
+};
+use location::{clear_retry_later, is_retry_pending, mark_retry_later, PathLocation};
+pub use mask::{apply_icon_mask, generate_icon_mask};
+pub use metrics::{
+    get_metrics as get_extraction_metrics, IconExtractionMetrics, MetricStage, StageTimer,
+};
+use msc::get_msc_icon_location;
+pub use negative::clear_all_without_icon;
+use negative::{clear_without_icon, is_known_without_icon, mark_without_icon};
+use pending::{clear_pending_umid, mark_umid_pending};
+pub use pending::{retry_pending_umid_of_window, retry_pending_umids_with_shortcut};
+pub use pool::{spawn_icon_extractor_pool, IconExtractorPool};
+pub use preview::{
+    preview_all_icons_of_file, preview_icon_for_path, preview_icon_for_window, IconPreviewError,
+};
+use protocol::{get_icon_from_protocol_url, get_url_from_url_file};
+pub use report::{
+    get_failures as get_extraction_failures, IconExtractionFailure, IconExtractionKey,
+};
+pub use shape::{apply_icon_shape, is_full_bleed};
+use shape::{shape_full_bleed_icon, shape_icon, should_shape_icon};
+use shell_watchdog::{is_shell_bypassed, run_shell_call_with_timeout};
+pub use sidecar::{system_pack_file, JsonSidecar};
+pub use storage::save_icon_image;
+use theme_variants::generate_theme_variants;
+
 use image::{GenericImageView, ImageBuffer, RgbaImage};
 use itertools::Itertools;
 use queue::{IconExtractor, IconExtractorRequest};
+pub use refresh::{
+    get_refresh_progress, refresh_all_icons, resume_icons_refresh, IconRefreshProgress,
+};
 use windows::core::PCWSTR;
 use windows::Win32::{
+    Foundation::{HWND, LPARAM, WPARAM},
     Graphics::Gdi::{
         CreateCompatibleDC, DeleteDC, DeleteObject, GetDIBits, SelectObject, BITMAPINFO,
         BITMAPINFOHEADER, DIB_RGB_COLORS,
@@ -12,7 +82,11 @@ use windows::Win32::{
     UI::{
         Controls::{IImageList, ILD_TRANSPARENT},
         Shell::{SHGetFileInfoW, SHGetImageList, SHFILEINFOW, SHGFI_SYSICONINDEX, SHIL_JUMBO},
-        WindowsAndMessaging::{DestroyIcon, GetIconInfoExW, HICON, ICONINFOEXW},
+        WindowsAndMessaging::{
+            DestroyIcon, GetClassLongPtrW, GetIconInfoExW, PrivateExtractIconsW,
+            SendMessageTimeoutW, GCLP_HICON, GCLP_HICONSM, HICON, ICONINFOEXW, ICON_BIG,
+            SMTO_ABORTIFHUNG, SMTO_BLOCK, WM_GETICON,
+        },
     },
 };
 
@@ -20,21 +94,25 @@ use seelen_core::state::Icon;
 
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::{
-    __m128i, _mm_loadu_si128, _mm_setr_epi8, _mm_shuffle_epi8, _mm_storeu_si128,
+    __m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_set1_epi8, _mm_setr_epi8,
+    _mm_setzero_si128, _mm_shuffle_epi8, _mm_storeu_si128, _mm_subs_epu8,
 };
 
 #[cfg(target_arch = "aarch64")]
 use std::arch::aarch64::{
-    uint8x16_t, vld1q_u8, vqtbl1q_u8, vst1q_u8
+    uint8x16_t, vdupq_n_u8, vld1q_u8, vmaxvq_u8, vqsubq_u8, vqtbl1q_u8, vst1q_u8,
 };
 
 use std::io::BufRead;
+use std::os::windows::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::error::Result;
+use crate::log_error;
 use crate::modules::start::application::START_MENU_MANAGER;
 use crate::modules::uwp::UwpManager;
-use crate::state::application::FULL_STATE;
+use crate::state::application::{IconPacksManager, FULL_STATE};
 use crate::trace_lock;
 use crate::utils::constants::SEELEN_COMMON;
 use crate::utils::date_based_hex_id;
@@ -83,7 +161,67 @@ pub fn bgra_to_rgba(data: &mut [u8]) {
     }
 }
 
+/// Checks if any pixel of the RGBA row has an alpha over the threshold
+///
+/// Uses SIMD to check 4 pixels at once
+#[cfg(target_arch = "x86_64")]
+fn find_first_nonzero_alpha_row(row: &[u8], alpha_threshold: u8) -> bool {
+    // Moves the alpha of each pixel to the first 4 bytes, the rest are zeroed
+    let mask: __m128i =
+        unsafe { _mm_setr_epi8(3, 7, 11, 15, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1) };
+    let threshold: __m128i = unsafe { _mm_set1_epi8(alpha_threshold as i8) };
+    let zero: __m128i = unsafe { _mm_setzero_si128() };
+
+    let mut chunks = row.chunks_exact(16);
+    for chunk in &mut chunks {
+        let vector = unsafe { _mm_loadu_si128(chunk.as_ptr() as *const __m128i) };
+        let alpha = unsafe { _mm_shuffle_epi8(vector, mask) };
+        // saturated subtraction is only non zero for alphas over the threshold
+        let over = unsafe { _mm_subs_epu8(alpha, threshold) };
+        if unsafe { _mm_movemask_epi8(_mm_cmpeq_epi8(over, zero)) } != 0xFFFF {
+            return true;
+        }
+    }
+    chunks
+        .remainder()
+        .chunks_exact(4)
+        .any(|pixel| pixel[3] > alpha_threshold)
+}
+
+// Uses NEON intrinsics to check 4 pixels at once
+#[cfg(target_arch = "aarch64")]
+fn find_first_nonzero_alpha_row(row: &[u8], alpha_threshold: u8) -> bool {
+    // Moves the alpha of each pixel to the first 4 bytes, out of range indexes are zeroed
+    let maskplain: [u8; 16] = [
+        3, 7, 11, 15, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+    ];
+    let mask: uint8x16_t = unsafe { vld1q_u8(maskplain.as_ptr()) };
+    let threshold: uint8x16_t = unsafe { vdupq_n_u8(alpha_threshold) };
+
+    let mut chunks = row.chunks_exact(16);
+    for chunk in &mut chunks {
+        let vector: uint8x16_t = unsafe { vld1q_u8(chunk.as_ptr()) };
+        let alpha = unsafe { vqtbl1q_u8(vector, mask) };
+        // saturated subtraction is only non zero for alphas over the threshold
+        if unsafe { vmaxvq_u8(vqsubq_u8(alpha, threshold)) } != 0 {
+            return true;
+        }
+    }
+    chunks
+        .remainder()
+        .chunks_exact(4)
+        .any(|pixel| pixel[3] > alpha_threshold)
+}
+
+/// Bytes of the row `y` of the image
+fn image_row(rgba_image: &RgbaImage, y: u32) -> &[u8] {
+    let stride = rgba_image.width() as usize * 4;
+    let start = y as usize * stride;
+    &rgba_image.as_raw()[start..start + stride]
+}
+
 pub fn convert_hicon_to_rgba_image(hicon: &HICON) -> Result<RgbaImage> {
+    let _timer = StageTimer::start(MetricStage::HiconConversion);
     unsafe {
         let mut icon_info = ICONINFOEXW {
             cbSize: std::mem::size_of::<ICONINFOEXW>() as u32,
@@ -91,7 +229,11 @@ pub fn convert_hicon_to_rgba_image(hicon: &HICON) -> Result<RgbaImage> {
         };
 
         if !GetIconInfoExW(*hicon, &mut icon_info).as_bool() {
-            return Err("Failed to get icon info".into());
+            return Err(IconExtractionError::new(
+                IconExtractionStage::HiconConversion,
+                format!("GetIconInfoExW failed for {hicon:?}"),
+            )
+            .into());
         }
         let hdc_screen = CreateCompatibleDC(None);
         let hdc_mem = CreateCompatibleDC(Some(hdc_screen));
@@ -123,7 +265,11 @@ pub fn convert_hicon_to_rgba_image(hicon: &HICON) -> Result<RgbaImage> {
             DIB_RGB_COLORS,
         ) == 0
         {
-            return Err("Failed to get dibits".into());
+            return Err(IconExtractionError::new(
+                IconExtractionStage::Dib,
+                format!("GetDIBits returned no lines for {hicon:?}"),
+            )
+            .into());
         }
 
         // Clean up
@@ -134,60 +280,56 @@ pub fn convert_hicon_to_rgba_image(hicon: &HICON) -> Result<RgbaImage> {
         DeleteObject(icon_info.hbmMask.into()).ok()?;
 
         if bmp_info.bmiHeader.biBitCount != 32 {
-            return Err("Icon is not 32 bit".into());
+            return Err(IconExtractionError::new(
+                IconExtractionStage::Dib,
+                format!(
+                    "{}bpp bitmap, expected 32bpp",
+                    bmp_info.bmiHeader.biBitCount
+                ),
+            )
+            .into());
         }
 
         bgra_to_rgba(buffer.as_mut_slice());
 
-        let image = ImageBuffer::from_raw(icon_info.xHotspot * 2, icon_info.yHotspot * 2, buffer)
-            .expect("Failed to create image buffer");
+        let mut image =
+            ImageBuffer::from_raw(icon_info.xHotspot * 2, icon_info.yHotspot * 2, buffer)
+                .expect("Failed to create image buffer");
+        normalize_alpha(&mut image);
         Ok(image)
     }
 }
 
 /// this is the best solution having in consideration that a transparent image and have separated pixels
 /// with transparent gaps, so search side by side and crop them is the best approach.
-pub fn crop_transparent_borders(rgba_image: &RgbaImage) -> RgbaImage {
+///
+/// Returns the inclusive bounds `(left, top, right, bottom)` of the pixels over the threshold.
+#[cfg(not(feature = "rayon"))]
+fn find_visible_bounds(
+    rgba_image: &RgbaImage,
+    alpha_threshold: u8,
+) -> Option<(u32, u32, u32, u32)> {
+    find_visible_bounds_sequential(rgba_image, alpha_threshold)
+}
+
+#[cfg(any(not(feature = "rayon"), test))]
+fn find_visible_bounds_sequential(
+    rgba_image: &RgbaImage,
+    alpha_threshold: u8,
+) -> Option<(u32, u32, u32, u32)> {
     let (width, height) = rgba_image.dimensions();
-    let mut top = None;
-    let mut bottom = None;
     let mut left = None;
     let mut right = None;
 
-    'outer: for y in 0..height {
-        for x in 0..width {
-            let pixel = rgba_image.get_pixel(x, y);
-            if pixel.0[3] != 0 {
-                top = Some(y);
-                break 'outer;
-            }
-        }
-    }
-
-    let top = match top {
-        Some(top) => top,
-        None => return RgbaImage::new(1, 1),
-    };
-
-    'outer: for y in (top..height).rev() {
-        for x in 0..width {
-            let pixel = rgba_image.get_pixel(x, y);
-            if pixel.0[3] != 0 {
-                bottom = Some(y);
-                break 'outer;
-            }
-        }
-    }
-
-    let bottom = match bottom {
-        Some(bottom) => bottom,
-        None => return RgbaImage::new(1, 1),
-    };
+    let row_is_visible =
+        |y: u32| find_first_nonzero_alpha_row(image_row(rgba_image, y), alpha_threshold);
+    let top = (0..height).find(|&y| row_is_visible(y))?;
+    let bottom = (top..height).rev().find(|&y| row_is_visible(y))?;
 
     'outer: for x in 0..width {
         for y in top..bottom {
             let pixel = rgba_image.get_pixel(x, y);
-            if pixel.0[3] != 0 {
+            if pixel.0[3] > alpha_threshold {
                 left = Some(x);
                 break 'outer;
             }
@@ -196,13 +338,13 @@ pub fn crop_transparent_borders(rgba_image: &RgbaImage) -> RgbaImage {
 
     let left = match left {
         Some(left) => left,
-        None => return RgbaImage::new(1, 1),
+        None => return None,
     };
 
     'outer: for x in (left..width).rev() {
         for y in top..bottom {
             let pixel = rgba_image.get_pixel(x, y);
-            if pixel.0[3] != 0 {
+            if pixel.0[3] > alpha_threshold {
                 right = Some(x);
                 break 'outer;
             }
@@ -211,18 +353,135 @@ pub fn crop_transparent_borders(rgba_image: &RgbaImage) -> RgbaImage {
 
     let right = match right {
         Some(right) => right,
-        None => return RgbaImage::new(1, 1),
+        None => return None,
+    };
+
+    Some((left, top, right, bottom))
+}
+
+/// Same search as the sequential version, but each side scans its rows/columns in parallel
+#[cfg(feature = "rayon")]
+fn find_visible_bounds(
+    rgba_image: &RgbaImage,
+    alpha_threshold: u8,
+) -> Option<(u32, u32, u32, u32)> {
+    use rayon::prelude::*;
+
+    let (width, height) = rgba_image.dimensions();
+    let is_visible = |x: u32, y: u32| rgba_image.get_pixel(x, y).0[3] > alpha_threshold;
+
+    let row_is_visible =
+        |y: u32| find_first_nonzero_alpha_row(image_row(rgba_image, y), alpha_threshold);
+    let top = (0..height)
+        .into_par_iter()
+        .find_first(|&y| row_is_visible(y))?;
+    let bottom = (top..height)
+        .into_par_iter()
+        .find_last(|&y| row_is_visible(y))?;
+
+    let column_is_visible = |x: u32| (top..bottom).any(|y| is_visible(x, y));
+    let left = (0..width)
+        .into_par_iter()
+        .find_first(|&x| column_is_visible(x))?;
+    let right = (left..width)
+        .into_par_iter()
+        .find_last(|&x| column_is_visible(x))?;
+
+    Some((left, top, right, bottom))
+}
+
+pub fn crop_transparent_borders(rgba_image: &RgbaImage) -> RgbaImage {
+    crop_transparent_borders_threshold(rgba_image, 0)
+}
+
+/// Pixels with `alpha <= alpha_threshold` are considered transparent, so faint
+/// anti-aliasing fringes are cropped too.
+pub fn crop_transparent_borders_threshold(
+    rgba_image: &RgbaImage,
+    alpha_threshold: u8,
+) -> RgbaImage {
+    let _timer = StageTimer::start(MetricStage::Crop);
+    match find_visible_bounds(rgba_image, alpha_threshold) {
+        Some((left, top, right, bottom)) => rgba_image
+            .view(left, top, right - left + 1, bottom - top + 1)
+            .to_image(),
+        None => RgbaImage::new(1, 1),
+    }
+}
+
+/// Scales the icon to fit a `target_size` square keeping its aspect ratio, the rest of the
+/// square is transparent. Lanczos3 keeps small sizes sharp, unlike the default filters.
+pub fn resize_icon(image: &RgbaImage, target_size: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let long_side = width.max(height).max(1);
+    let scale = |side: u32| ((side as u64 * target_size as u64) / long_side as u64).max(1) as u32;
+    let (scaled_width, scaled_height) = (scale(width), scale(height));
+
+    let scaled = match (scaled_width, scaled_height) == (width, height) {
+        true => image.clone(),
+        false => image::imageops::resize(
+            image,
+            scaled_width,
+            scaled_height,
+            image::imageops::FilterType::Lanczos3,
+        ),
     };
+    if scaled_width == target_size && scaled_height == target_size {
+        return scaled;
+    }
 
+    let mut canvas = RgbaImage::new(target_size, target_size);
+    image::imageops::overlay(
+        &mut canvas,
+        &scaled,
+        ((target_size - scaled_width) / 2) as i64,
+        ((target_size - scaled_height) / 2) as i64,
+    );
+    canvas
+}
+
+/// Crops wide images like splash screens to a square centered on the image,
+/// expected to be called after `crop_transparent_borders`.
+pub fn crop_to_centered_square(rgba_image: &RgbaImage) -> RgbaImage {
+    let (width, height) = rgba_image.dimensions();
+    let side = width.min(height);
+    if width == height {
+        return rgba_image.clone();
+    }
     rgba_image
-        .view(left, top, right - left + 1, bottom - top + 1)
+        .view((width - side) / 2, (height - side) / 2, side, side)
         .to_image()
 }
 
+/// Icons are cached in memory, so repeated requests for the same path don't hit the shell.
 pub fn get_icon_from_file(path: &Path) -> Result<RgbaImage> {
+    if let Some(icon) = get_cached_icon(path) {
+        return Ok(icon);
+    }
+    let _timer = StageTimer::start(MetricStage::ShellLookup);
+    let icon = match is_shell_bypassed(path) {
+        true => get_icon_from_resource(path, 0)?,
+        false => {
+            let timeout = Duration::from_millis(FULL_STATE.load().icon_extraction.shell_timeout_ms);
+            let owned = path.to_path_buf();
+            match run_shell_call_with_timeout(path, timeout, move || {
+                get_icon_from_file_uncached(&owned)
+            }) {
+                Some(result) => result?,
+                // doesn't load shell extensions, only works for files with icon resources
+                None => get_icon_from_resource(path, 0)?,
+            }
+        }
+    };
+    cache_icon(path, &icon);
+    Ok(icon)
+}
+
+fn get_icon_from_file_uncached(path: &Path) -> Result<RgbaImage> {
     unsafe {
         let normalized = path
-            .canonicalize()?
+            .canonicalize()
+            .at_stage(IconExtractionStage::ShellLookup, path.display())?
             .to_string_lossy()
             .trim_start_matches(r"\\?\")
             .to_owned();
@@ -238,13 +497,23 @@ pub fn get_icon_from_file(path: &Path) -> Result<RgbaImage> {
         );
 
         if result == 0 {
-            return Err("Failed to get file information".into());
+            return Err(IconExtractionError::new(
+                IconExtractionStage::ShellLookup,
+                "SHGetFileInfoW failed",
+            )
+            .with_key(path.display())
+            .into());
         }
 
         // file_info.iIcon = 0 is a valid icon but it is the default icon for files on Windows
         // so we will handle this as no icon to avoid generate unnecessary artifacts
         if file_info.iIcon == 0 {
-            return Err("Icon index is 0".into());
+            return Err(IconExtractionError::new(
+                IconExtractionStage::ShellLookup,
+                "only the default file icon is available",
+            )
+            .with_key(path.display())
+            .into());
         }
 
         let image_list: IImageList = SHGetImageList(SHIL_JUMBO as i32)?;
@@ -259,6 +528,186 @@ pub fn get_icon_from_file(path: &Path) -> Result<RgbaImage> {
     }
 }
 
+/// Null terminated file name buffer as expected by `PrivateExtractIconsW`
+fn resource_file_name(path: &Path) -> Result<[u16; 260]> {
+    let mut filename = [0u16; 260];
+    let wide = path.as_os_str().encode_wide().collect_vec();
+    if wide.len() >= filename.len() {
+        return Err(
+            IconExtractionError::new(IconExtractionStage::InvalidPath, "path too long")
+                .with_key(path.display())
+                .into(),
+        );
+    }
+    filename[..wide.len()].copy_from_slice(&wide);
+    Ok(filename)
+}
+
+/// Extracts the icon at `index` from the resources of a PE file (exe, dll, cpl, scr, etc),
+/// negative indexes are interpreted as resource ids.
+pub fn get_icon_from_resource(path: &Path, index: i32) -> Result<RgbaImage> {
+    let filename = resource_file_name(path)?;
+    let mut icons = [HICON::default()];
+    let extracted =
+        unsafe { PrivateExtractIconsW(&filename, index, 256, 256, Some(&mut icons), None, 0) };
+    if extracted == 0 || extracted == u32::MAX || icons[0].is_invalid() {
+        return Err(IconExtractionError::new(
+            IconExtractionStage::ShellLookup,
+            format!("no icon at index {index}"),
+        )
+        .with_key(path.display())
+        .into());
+    }
+
+    let image = convert_hicon_to_rgba_image(&icons[0]);
+    unsafe { DestroyIcon(icons[0])? };
+    Ok(crop_transparent_borders(&image?))
+}
+
+/// Extracts all the icons of the resources of a PE file, like shell32.dll or imageres.dll
+/// that contain hundreds of them. Icons that can't be converted are skipped, the returned
+/// indexes are the ones accepted by `get_icon_from_resource`.
+pub fn get_all_icons_from_file(path: &Path) -> Result<Vec<(i32, RgbaImage)>> {
+    let filename = resource_file_name(path)?;
+    // without output buffer the amount of icons on the file is returned
+    let count = unsafe { PrivateExtractIconsW(&filename, 0, 0, 0, None, None, 0) };
+    if count == 0 || count == u32::MAX {
+        return Err(IconExtractionError::new(
+            IconExtractionStage::ShellLookup,
+            "file without icons",
+        )
+        .with_key(path.display())
+        .into());
+    }
+
+    let mut icons = vec![HICON::default(); count as usize];
+    let extracted =
+        unsafe { PrivateExtractIconsW(&filename, 0, 256, 256, Some(&mut icons), None, 0) };
+    if extracted == u32::MAX {
+        return Err(IconExtractionError::new(
+            IconExtractionStage::ShellLookup,
+            "failed to extract the icons",
+        )
+        .with_key(path.display())
+        .into());
+    }
+
+    let mut images = Vec::with_capacity(extracted as usize);
+    for (index, hicon) in icons.iter().take(extracted as usize).enumerate() {
+        if hicon.is_invalid() {
+            continue;
+        }
+        let image = convert_hicon_to_rgba_image(hicon);
+        log_error!(unsafe { DestroyIcon(*hicon) });
+        match image {
+            Ok(image) => images.push((index as i32, crop_transparent_borders(&image))),
+            Err(err) => log::trace!("Skipping icon {index} of {}: {err:?}", path.display()),
+        }
+    }
+    Ok(images)
+}
+
+/// Icon of a running process, for processes whose file can't be found by other means.
+/// The image path is asked to the process itself, then the first icon of its resources
+/// is used, falling back to the shell for images without icon resources.
+pub fn get_icon_from_pid(pid: u32) -> Result<RgbaImage> {
+    let path = WindowsApi::exe_path_by_process(pid)
+        .at_stage(IconExtractionStage::InvalidPath, format!("pid {pid}"))?;
+    let path = PathBuf::from(path);
+    get_icon_from_resource(&path, 0).or_else(|_| get_icon_from_file(&path))
+}
+
+/// Icon declared by an MMC snap-in console file
+pub fn get_icon_from_msc_file(path: &Path) -> Result<RgbaImage> {
+    let (file, index) = get_msc_icon_location(path)?;
+    get_icon_from_resource(&file, index)
+}
+
+/// Timeout used when asking a window for its icon, hung windows shouldn't block the extraction
+const WINDOW_ICON_TIMEOUT_MS: u32 = 500;
+
+/// Gets the icon of a live window, useful for apps where the file based extraction fails
+/// like portable tools or Electron apps launched from arbitrary paths.
+pub fn get_icon_from_window(hwnd: HWND) -> Result<RgbaImage> {
+    unsafe {
+        let mut result: usize = 0;
+        SendMessageTimeoutW(
+            hwnd,
+            WM_GETICON,
+            WPARAM(ICON_BIG as usize),
+            LPARAM(0),
+            SMTO_ABORTIFHUNG | SMTO_BLOCK,
+            WINDOW_ICON_TIMEOUT_MS,
+            Some(&mut result),
+        );
+
+        if result == 0 {
+            result = GetClassLongPtrW(hwnd, GCLP_HICON);
+        }
+
+        if result == 0 {
+            result = GetClassLongPtrW(hwnd, GCLP_HICONSM);
+        }
+
+        if result == 0 {
+            return Err(IconExtractionError::new(
+                IconExtractionStage::ShellLookup,
+                "window has no icon",
+            )
+            .with_key(format!("{hwnd:?}"))
+            .into());
+        }
+
+        // these icons are owned by the window/class so they should not be destroyed
+        let hicon = HICON(result as _);
+        let image = convert_hicon_to_rgba_image(&hicon)?;
+        Ok(crop_transparent_borders(&image))
+    }
+}
+
+/// Icon of the app owning the window, resolved by the app user model id of the window so
+/// windows grouped under a packaged or pinned app show the icon of that app.
+/// Falls back to the icon of the window itself when there is no id or its icon is not found.
+pub fn get_icon_for_hwnd(hwnd: isize) -> Result<RgbaImage> {
+    AppUserModelId::from_hwnd(hwnd)
+        .and_then(|umid| get_icon_from_umid(&umid))
+        .or_else(|_| get_icon_from_window(HWND(hwnd as _)))
+}
+
+/// Icon of the app without storing it, the light variant for packaged apps.
+/// The start menu index is not re-scanned as `find_shortcut_of_umid` does, to not block the caller.
+fn get_icon_from_umid(aumid: &AppUserModelId) -> Result<RgbaImage> {
+    match aumid {
+        AppUserModelId::Appx(app_umid) => {
+            let scale_percent = UwpManager::primary_monitor_scale_percent();
+            let (class, asset) = UwpManager::get_high_quality_icon_path(app_umid, scale_percent)
+                .at_stage(IconExtractionStage::UwpAsset, app_umid)?;
+            let rgba = image::open(&asset.light)
+                .at_stage(IconExtractionStage::UwpAsset, asset.light.display())?
+                .to_rgba8();
+            let cropped = crop_transparent_borders(&rgba);
+            Ok(match class.is_wide() {
+                true => crop_to_centered_square(&cropped),
+                false => cropped,
+            })
+        }
+        AppUserModelId::PropertyStore(app_umid) => {
+            let lnk_path = START_MENU_MANAGER
+                .load()
+                .get_by_file_umid(app_umid)
+                .map(|item| item.path.clone())
+                .ok_or_else(|| {
+                    IconExtractionError::new(
+                        IconExtractionStage::LnkResolution,
+                        "no start menu shortcut has the umid",
+                    )
+                    .with_key(app_umid)
+                })?;
+            get_icon_from_file(&lnk_path)
+        }
+    }
+}
+
 const SQUARE_MARGIN: f32 = 0.1;
 const ASPECT_TOLERANCE: f32 = 0.05;
 const OPACITY_THRESHOLD: u8 = 254;
@@ -313,24 +762,190 @@ fn get_icon_from_url_file(path: &Path) -> Result<RgbaImage> {
 
     let path = match path {
         Some(icon_file) => icon_file,
-        None => return Err("Failed to get icon".into()),
+        None => {
+            return Err(IconExtractionError::new(
+                IconExtractionStage::ShellLookup,
+                "internet shortcut without IconFile",
+            )
+            .with_key(path.display())
+            .into())
+        }
     };
 
     get_icon_from_file(&path)
 }
 
+/// Registers a generated letter-avatar icon for the app, only if enabled on settings.
+/// Existing fallback icons are kept as is, so the color and initials are stable.
+fn save_fallback_icon(
+    icon_manager: &mut IconPacksManager,
+    umid: Option<&str>,
+    path: Option<&Path>,
+    name: &str,
+) -> Result<()> {
+    if !FULL_STATE.load().icon_extraction.fallback_icons
+        || icon_manager.has_fallback_app_icon(umid, path)
+    {
+        return Ok(());
+    }
+
+    let icon = generate_fallback_icon(name);
+    let filename = save_icon_image(
+        &icon,
+        &SEELEN_COMMON.user_icons_path().join("system"),
+        &format!("{FALLBACK_ICON_PREFIX}{}", date_based_hex_id()),
+    )?;
+
+    icon_manager.add_system_app_icon(
+        umid,
+        path,
+        Icon {
+            base: Some(filename),
+            is_aproximately_square: true,
+            ..Default::default()
+        },
+    );
+    icon_manager.write_system_icon_pack()
+}
+
+/// Saves the silhouette mask of the icon as `{name}_mask` if enabled on settings,
+/// returns the filename to be stored on the icon entry.
+fn save_icon_mask(image: &RgbaImage, root: &Path, name: &str) -> Result<Option<String>> {
+    let state = FULL_STATE.load();
+    if !state.icon_extraction.generate_masks {
+        return Ok(None);
+    }
+    let mask = generate_icon_mask(image, state.icon_extraction.mask_feather);
+    Ok(Some(save_icon_image(&mask, root, &format!("{name}_mask"))?))
+}
+
+/// Name used to render the fallback icon of an UWP app, `Publisher.AppName_hash!App` -> `AppName`
+fn display_name_from_umid(umid: &str) -> &str {
+    let family = umid.split(['_', '!']).next().unwrap_or(umid);
+    family.rsplit('.').next().unwrap_or(family)
+}
+
 pub fn extract_and_save_icon_from_file<T: AsRef<Path>>(path: T) {
     IconExtractor::request(IconExtractorRequest::Path(path.as_ref().to_path_buf()));
 }
 
+/// Extracts the icons of all the files writing the system icon pack only once.
+pub fn extract_and_save_icons_from_files(paths: Vec<PathBuf>) {
+    IconExtractor::request(IconExtractorRequest::Batch(paths));
+}
+
 /// returns the path of the icon extracted from the executable or copied if is an UWP app.
 ///
-/// If the icon already exists, it returns the path instead overriding, this is needed for allow user custom icons.
+/// If the icon already exists it is not extracted again unless the file changed since its extraction,
+/// custom icons of the user live on their own pack.
 ///
 /// umid on this case only applys to Property Store umid
 pub fn _extract_and_save_icon_from_file(origin: &Path, umid: Option<String>) -> Result<()> {
+    if is_excluded_from_extraction(origin) {
+        log::trace!(
+            "Icon extraction skipped, excluded path: {}",
+            origin.display()
+        );
+        let state = FULL_STATE.load();
+        if state.icon_extraction.fallback_for_excluded_paths {
+            let name = origin
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let mutex = state.icon_packs().clone();
+            let mut icon_manager = trace_lock!(mutex);
+            save_fallback_icon(&mut icon_manager, umid.as_deref(), Some(origin), &name)?;
+        }
+        return Ok(());
+    }
+
+    match PathLocation::of(origin) {
+        PathLocation::Local => _extract_and_save_local_icon(origin, umid),
+        location => _extract_and_save_remote_icon(origin, umid, location),
+    }
+}
+
+/// The shell can block for a long time on network shares and removable drives while they time out,
+/// so the path is probed on a dedicated thread and abandoned if it doesn't answer in time.
+/// The probe doesn't hold any lock so an abandoned thread can't freeze the extraction queue.
+fn _extract_and_save_remote_icon(
+    origin: &Path,
+    umid: Option<String>,
+    location: PathLocation,
+) -> Result<()> {
+    let settings = FULL_STATE.load().icon_extraction.clone();
+    let skip = settings.skip_remote_paths
+        || location == PathLocation::Unavailable
+        || is_retry_pending(origin);
+
+    if !skip {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let path = origin.to_path_buf();
+        std::thread::spawn(move || {
+            // warms up the shell cache, so the extraction below doesn't hit the volume again
+            let reachable = path.metadata().is_ok() && get_icon_from_file(&path).is_ok();
+            let _ = tx.send(reachable);
+        });
+
+        let timeout = std::time::Duration::from_millis(settings.remote_paths_timeout_ms);
+        match rx.recv_timeout(timeout) {
+            Ok(true) => return _extract_and_save_local_icon(origin, umid),
+            // a disconnected share fails fast, extracting it again would only hit it again
+            Ok(false) => {
+                log::warn!(
+                    "{location:?} path {} is unreachable, will retry later",
+                    origin.display()
+                );
+                mark_retry_later(origin);
+            }
+            Err(_) => {
+                log::warn!(
+                    "Icon extraction timed out for {location:?} path {}, will retry later",
+                    origin.display()
+                );
+                mark_retry_later(origin);
+            }
+        }
+    }
+
+    let is_app_file = origin.extension().is_some_and(|ext| {
+        matches!(
+            ext.to_string_lossy().to_lowercase().as_str(),
+            "exe" | "lnk" | "cpl" | "scr" | "msc"
+        )
+    });
+    if is_app_file {
+        let name = origin
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mutex = FULL_STATE.load().icon_packs().clone();
+        let mut icon_manager = trace_lock!(mutex);
+        save_fallback_icon(&mut icon_manager, umid.as_deref(), Some(origin), &name)?;
+    }
+    Ok(())
+}
+
+/// The file was modified after its icon was extracted, like an executable updated in place.
+/// Icons extracted before the metadata was recorded are not considered stale.
+fn is_stale_icon(icon_manager: &IconPacksManager, origin: &Path) -> bool {
+    let key = IconExtractionKey::Path(origin.to_path_buf());
+    let Some(metadata) = icon_manager.get_icon_metadata(&key) else {
+        return false;
+    };
+    origin
+        .metadata()
+        .and_then(|meta| meta.modified())
+        .is_ok_and(|modified| modified > metadata.extracted_at)
+}
+
+fn _extract_and_save_local_icon(origin: &Path, umid: Option<String>) -> Result<()> {
     if !origin.exists() || origin.is_dir() {
-        return Err(format!("File not found: {}", origin.display()).into());
+        return Err(
+            IconExtractionError::new(IconExtractionStage::InvalidPath, "file not found")
+                .with_key(origin.display())
+                .into(),
+        );
     }
 
     let origin_ext = match origin.extension() {
@@ -344,36 +959,62 @@ pub fn _extract_and_save_icon_from_file(origin: &Path, umid: Option<String>) ->
         return Ok(());
     }
 
-    let is_exe_file = origin_ext == "exe";
+    // control panel applets and screensavers are executables with its own icons
+    let is_exe_file = matches!(origin_ext.as_str(), "exe" | "cpl" | "scr");
     let is_lnk_file = origin_ext == "lnk";
     let is_url_file = origin_ext == "url";
+    let is_msc_file = origin_ext == "msc";
+    // sideloaded packages, not registered on the package manager
+    let is_package_file = matches!(origin_ext.as_str(), "appx" | "msix");
 
+    // the lock is not held while extracting, so several files can be extracted in parallel
     let mutex = FULL_STATE.load().icon_packs().clone();
-    let mut icon_manager = trace_lock!(mutex);
-    if is_exe_file || is_lnk_file || is_url_file {
-        if icon_manager.has_app_icon(None, Some(origin)) {
+    {
+        let icon_manager = trace_lock!(mutex);
+        if is_exe_file || is_lnk_file || is_url_file || is_msc_file || is_package_file {
+            if icon_manager.has_app_icon(None, Some(origin)) {
+                if !is_stale_icon(&icon_manager, origin) {
+                    return Ok(());
+                }
+                // the image in memory is the one before the change
+                invalidate_cached_icon(origin);
+            }
+        } else if icon_manager.get_file_icon(origin).is_some() {
             return Ok(());
         }
-    } else if icon_manager.get_file_icon(origin).is_some() {
+    }
+
+    if (is_exe_file || is_lnk_file) && is_known_without_icon(origin) {
+        log::trace!("Skipping {}, it has no icon", origin.display());
         return Ok(());
     }
 
-    let file_name = origin.file_name().ok_or("Failed to get file name")?;
-    let filestem = origin.file_stem().ok_or("Failed to get file stem")?;
+    let (Some(file_name), Some(filestem)) = (origin.file_name(), origin.file_stem()) else {
+        return Err(IconExtractionError::new(
+            IconExtractionStage::InvalidPath,
+            "path without file name",
+        )
+        .with_key(origin.display())
+        .into());
+    };
 
     let root = SEELEN_COMMON.user_icons_path().join("system");
-    let gen_icon_filename = format!("{}_{}.png", filestem.to_string_lossy(), date_based_hex_id());
-    let mut gen_icon = Icon {
-        base: Some(gen_icon_filename.clone()),
-        ..Default::default()
-    };
+    let gen_icon_name = format!("{}_{}", filestem.to_string_lossy(), date_based_hex_id());
+    let mut gen_icon = Icon::default();
 
     log::trace!("Extracting icon for {file_name:?}");
 
     if origin_ext == "url" {
-        if let Ok(icon) = get_icon_from_url_file(origin) {
+        // launchers like steam don't set an icon file, so the protocol is resolved instead
+        let icon = get_icon_from_url_file(origin).or_else(|_| {
+            let url = get_url_from_url_file(origin)?;
+            get_icon_from_protocol_url(&url, &FULL_STATE.load().icon_extraction.url_protocols)
+        });
+        if let Ok(icon) = icon {
             gen_icon.is_aproximately_square = is_aproximately_a_square(&icon);
-            icon.save(root.join(&gen_icon_filename))?;
+            gen_icon.base = Some(save_icon_image(&icon, &root, &gen_icon_name)?);
+            record_icon_metadata(origin, &gen_icon, &icon);
+            let mut icon_manager = trace_lock!(mutex);
             icon_manager.add_system_app_icon(None, Some(origin), gen_icon);
             icon_manager.write_system_icon_pack()?;
         }
@@ -384,7 +1025,8 @@ pub fn _extract_and_save_icon_from_file(origin: &Path, umid: Option<String>) ->
         let lnk_icon_path = match WindowsApi::resolve_lnk_custom_icon_path(origin) {
             Ok(icon_path) => icon_path,
             Err(_) => {
-                let (target, _) = WindowsApi::resolve_lnk_target(origin)?;
+                let (target, _) = WindowsApi::resolve_lnk_target(origin)
+                    .at_stage(IconExtractionStage::LnkResolution, origin.display())?;
                 target
             }
         };
@@ -393,7 +1035,6 @@ pub fn _extract_and_save_icon_from_file(origin: &Path, umid: Option<String>) ->
             .extension()
             .is_some_and(|ext| ext.to_string_lossy().to_lowercase() != "ico")
         {
-            drop(icon_manager);
             _extract_and_save_icon_from_file(&lnk_icon_path, umid.clone())?;
             let mut icon_manager = trace_lock!(mutex);
             icon_manager.add_system_icon_redirect(umid, origin, &lnk_icon_path);
@@ -402,35 +1043,222 @@ pub fn _extract_and_save_icon_from_file(origin: &Path, umid: Option<String>) ->
         }
     }
 
+    // snap-ins declare its icon on the console file, unparseable ones use the generic file icon
+    let msc_icon = match is_msc_file {
+        true => match get_icon_from_msc_file(origin) {
+            Ok(icon) => Some(icon),
+            Err(err) => {
+                log::trace!("Failed to get icon from {}: {err}", origin.display());
+                if trace_lock!(mutex).get_file_icon(origin).is_some() {
+                    return Ok(());
+                }
+                None
+            }
+        },
+        false => None,
+    };
+    let package_icon = match is_package_file {
+        true => match get_icon_from_appx_package(origin) {
+            Ok(icon) => Some(icon),
+            Err(err) => {
+                log::trace!("Failed to get icon from {}: {err}", origin.display());
+                None
+            }
+        },
+        false => None,
+    };
+    let declared_icon = msc_icon.or(package_icon);
+    let is_app_file = is_exe_file || is_lnk_file || declared_icon.is_some();
+
     // try get the icon directly from the file
-    let icon = match get_icon_from_file(origin) {
+    let icon = match declared_icon {
+        Some(icon) => Ok(icon),
+        None if is_exe_file && origin_ext != "exe" => {
+            get_icon_from_resource(origin, 0).or_else(|_| get_icon_from_file(origin))
+        }
+        None => get_icon_from_file(origin).or_else(|err| match is_app_file {
+            true => Err(err),
+            // types launched by interpreters, like .py, declare their icon on the association
+            false => get_icon_from_registry_association(&origin_ext).map_err(|_| err),
+        }),
+    };
+
+    let icon = match icon {
         Ok(icon) => icon,
         Err(_) => {
             log::trace!("Icon not found for {}", origin.display());
+            if is_exe_file || is_lnk_file {
+                mark_without_icon(origin);
+                save_fallback_icon(
+                    &mut trace_lock!(mutex),
+                    umid.as_deref(),
+                    Some(origin),
+                    &filestem.to_string_lossy(),
+                )?;
+            }
             return Ok(());
         }
     };
 
+    let icon = match is_app_file {
+        true => shape_full_bleed_icon(icon),
+        false => icon,
+    };
     gen_icon.is_aproximately_square = is_aproximately_a_square(&icon);
 
-    if is_exe_file || is_lnk_file {
-        icon.save(root.join(&gen_icon_filename))?;
+    if is_app_file {
+        let variants = match FULL_STATE.load().icon_extraction.monochrome_theme_variants {
+            true => generate_theme_variants(&icon),
+            false => None,
+        };
+
+        if let Some((light, dark)) = variants {
+            let name = &gen_icon_name;
+            gen_icon.light = Some(save_icon_image(&light, &root, &format!("{name}_light"))?);
+            gen_icon.dark = Some(save_icon_image(&dark, &root, &format!("{name}_dark"))?);
+            gen_icon.mask = save_icon_mask(&icon, &root, name)?;
+        } else {
+            gen_icon.base = Some(save_icon_image(&icon, &root, &gen_icon_name)?);
+        }
+        record_icon_metadata(origin, &gen_icon, &icon);
+        let mut icon_manager = trace_lock!(mutex);
         icon_manager.add_system_app_icon(umid.as_deref(), Some(origin), gen_icon);
+        icon_manager.write_system_icon_pack()?;
     } else {
-        let gen_icon_filename = format!("{}_{}.png", origin_ext, date_based_hex_id());
-        icon.save(root.join(&gen_icon_filename))?;
-        gen_icon.base = Some(gen_icon_filename);
+        let gen_icon_name = format!("{}_{}", origin_ext, date_based_hex_id());
+        gen_icon.base = Some(save_icon_image(&icon, &root, &gen_icon_name)?);
+        record_icon_metadata(origin, &gen_icon, &icon);
+        let mut icon_manager = trace_lock!(mutex);
         icon_manager.add_system_file_icon(&origin_ext, gen_icon);
+        icon_manager.write_system_icon_pack()?;
     }
-    icon_manager.write_system_icon_pack()?;
 
     Ok(())
 }
 
+/// Extracts the icon from the file, using the live window icon and then the image of its
+/// process as fallbacks.
+pub fn extract_and_save_icon_from_window<T: AsRef<Path>>(hwnd: HWND, path: T) {
+    IconExtractor::request(IconExtractorRequest::Window {
+        hwnd: hwnd.0 as isize,
+        path: path.as_ref().to_path_buf(),
+    });
+}
+
+/// The icon is registered under the process path key so subsequent runs hit the cache.
+pub fn _extract_and_save_icon_from_window(hwnd: HWND, path: &Path) -> Result<()> {
+    let file_result = _extract_and_save_icon_from_file(path, None);
+
+    let mutex = FULL_STATE.load().icon_packs().clone();
+    // not held while asking the window, it can be hung
+    if trace_lock!(mutex).has_app_icon(None, Some(path)) {
+        return file_result;
+    }
+
+    let icon = get_icon_from_window(hwnd).or_else(|err| {
+        // windows without icon, the process is asked for its image in case the path is inaccessible
+        let (pid, _) = WindowsApi::window_thread_process_id(hwnd);
+        get_icon_from_pid(pid).map_err(|_| err)
+    })?;
+    let filestem = path.file_stem().ok_or_else(|| {
+        IconExtractionError::new(IconExtractionStage::InvalidPath, "path without file name")
+            .with_key(path.display())
+    })?;
+    let gen_icon_filename = save_icon_image(
+        &icon,
+        &SEELEN_COMMON.user_icons_path().join("system"),
+        &format!("{}_{}", filestem.to_string_lossy(), date_based_hex_id()),
+    )?;
+    let is_aproximately_square = is_aproximately_a_square(&icon);
+
+    let mut icon_manager = trace_lock!(mutex);
+    icon_manager.add_system_app_icon(
+        None,
+        Some(path),
+        Icon {
+            base: Some(gen_icon_filename),
+            is_aproximately_square,
+            ..Default::default()
+        },
+    );
+    icon_manager.write_system_icon_pack()
+}
+
+/// Requests again an extraction that failed, skipping the retry delay of remote paths.
+pub fn retry_icon_extraction(key: IconExtractionKey) {
+    match key {
+        IconExtractionKey::Umid(umid) => extract_and_save_icon_umid(&umid.into()),
+        IconExtractionKey::Path(path) => {
+            clear_retry_later(&path);
+            clear_without_icon(&path);
+            invalidate_cached_icon(&path);
+            extract_and_save_icon_from_file(path);
+        }
+    }
+}
+
 pub fn extract_and_save_icon_umid(aumid: &AppUserModelId) {
     IconExtractor::request(IconExtractorRequest::AppUMID(aumid.clone()));
 }
 
+/// written on the system icons folder once the uwp icons were queued, clearing the system
+/// icons removes it so the cache is warmed again
+const UWP_ICONS_WARMED_MARKER: &str = ".uwp_icons_warmed";
+
+/// Queues the extraction of the icons of all the installed uwp apps, so they are already
+/// cached when the apps are opened or pinned. Only done on the first run, later installed
+/// apps get their icons on demand.
+pub fn warm_uwp_icons_cache() {
+    let marker = SEELEN_COMMON
+        .user_icons_path()
+        .join("system")
+        .join(UWP_ICONS_WARMED_MARKER);
+    if marker.exists() {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let packages = match UwpManager::enumerate_installed_packages() {
+            Ok(packages) => packages,
+            Err(err) => {
+                log::error!("Failed to enumerate installed packages: {err:?}");
+                return;
+            }
+        };
+
+        for package in packages {
+            // framework and resource packages have no applications
+            let Ok(manifest) = UwpManager::manifest_from_install_path(&package.install_path) else {
+                continue;
+            };
+            for app in &manifest.applications.application {
+                let umid = format!("{}!{}", package.package_family_name, app.id);
+                extract_and_save_icon_umid(&AppUserModelId::Appx(umid));
+            }
+        }
+        log_error!(std::fs::write(&marker, ""));
+    });
+}
+
+/// Extracts the icons of the apps with open windows, so they are ready for the taskbar
+/// before their windows are listed one by one
+pub fn warm_running_apps_icons() {
+    std::thread::spawn(|| {
+        for (_pid, umid) in AppUserModelId::for_all_running_processes() {
+            extract_and_save_icon_umid(&umid);
+        }
+    });
+}
+
+/// The shortcut of an app installed seconds ago can be missing from the start menu index,
+/// on a miss the umid is left pending and retried once the watcher indexes the shortcut.
+fn find_shortcut_of_umid(umid: &str) -> Option<PathBuf> {
+    START_MENU_MANAGER
+        .load()
+        .get_by_file_umid(umid)
+        .map(|item| item.path.clone())
+}
+
 /// returns the path of the icon extracted from the app with the specified package app user model id.
 pub fn _extract_and_save_icon_umid(aumid: &AppUserModelId) -> Result<()> {
     let icon_manager_mutex = FULL_STATE.load().icon_packs().clone();
@@ -444,51 +1272,256 @@ pub fn _extract_and_save_icon_umid(aumid: &AppUserModelId) -> Result<()> {
                 }
             }
 
-            log::trace!("Extracting icon for {app_umid:?}");
-            let mut gen_icon = Icon::default();
-            let (light_path, dark_path) = UwpManager::get_high_quality_icon_path(app_umid)?;
+            if let Err(err) = _extract_and_save_appx_icon(app_umid, path.as_deref()) {
+                let mut manager = trace_lock!(icon_manager_mutex);
+                save_fallback_icon(
+                    &mut manager,
+                    Some(app_umid),
+                    path.as_deref(),
+                    display_name_from_umid(app_umid),
+                )?;
+                return Err(err);
+            }
+            Ok(())
+        }
+        AppUserModelId::PropertyStore(app_umid) => {
+            let Some(lnk_path) = find_shortcut_of_umid(app_umid) else {
+                mark_umid_pending(app_umid);
+                let mut manager = trace_lock!(icon_manager_mutex);
+                save_fallback_icon(&mut manager, Some(app_umid), None, app_umid)?;
+                return Err(IconExtractionError::new(
+                    IconExtractionStage::LnkResolution,
+                    "no start menu shortcut has the umid",
+                )
+                .with_key(app_umid)
+                .into());
+            };
+            clear_pending_umid(app_umid);
+
+            {
+                let manager = trace_lock!(icon_manager_mutex);
+                if manager.has_app_icon(Some(aumid.as_str()), Some(&lnk_path)) {
+                    return Ok(());
+                }
+            }
 
-            let root = SEELEN_COMMON.user_icons_path().join("system");
-            let name = date_based_hex_id();
+            _extract_and_save_icon_from_file(&lnk_path, Some(app_umid.clone()))?;
+            Ok(())
+        }
+    }
+}
 
-            let light_rgba = image::open(&light_path)?.to_rgba8();
-            let light_rgba = crop_transparent_borders(&light_rgba);
+/// Draws the logo on the background color declared by the app, only if enabled on settings
+fn compose_uwp_background(app_umid: &str, logo: RgbaImage) -> RgbaImage {
+    if !FULL_STATE.load().icon_extraction.compose_uwp_backgrounds {
+        return logo;
+    }
+    let background = UwpManager::get_app_background_color(app_umid)
+        .ok()
+        .flatten()
+        .and_then(|color| parse_background_color(&color));
+    match background {
+        Some(color) => compose_on_background(&logo, color),
+        None => logo,
+    }
+}
 
-            if light_path != dark_path {
-                let dark_rgba = image::open(&dark_path)?.to_rgba8();
-                let dark_rgba = crop_transparent_borders(&dark_rgba);
+/// Bumped when the changes to the extraction make the stored icons look different,
+/// so the system icons are extracted again on the next start.
+pub const ICON_PIPELINE_VERSION: u32 = 1;
+
+/// long side of the stored UWP icons
+const MAX_UWP_ICON_SIZE: u32 = 512;
+
+/// Bounds containing both, missing bounds mean a fully transparent image
+fn union_bounds(
+    a: Option<(u32, u32, u32, u32)>,
+    b: Option<(u32, u32, u32, u32)>,
+) -> Option<(u32, u32, u32, u32)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))),
+        (bounds, None) | (None, bounds) => bounds,
+    }
+}
 
-                light_rgba.save(root.join(format!("{name}_light.png")))?;
-                dark_rgba.save(root.join(format!("{name}_dark.png")))?;
+/// Some packages only ship huge splash screens or logos, storing them as they are costs
+/// megabytes per app and makes the UI decode them for slots of a few pixels.
+fn downscale_uwp_icon(rgba: RgbaImage) -> RgbaImage {
+    let (width, height) = rgba.dimensions();
+    let long_side = width.max(height);
+    if long_side <= MAX_UWP_ICON_SIZE {
+        return rgba;
+    }
+    let scale = |side: u32| ((side as u64 * MAX_UWP_ICON_SIZE as u64) / long_side as u64).max(1);
+    image::imageops::resize(
+        &rgba,
+        scale(width) as u32,
+        scale(height) as u32,
+        image::imageops::FilterType::Lanczos3,
+    )
+}
 
-                gen_icon.light = Some(format!("{name}_light.png"));
-                gen_icon.dark = Some(format!("{name}_dark.png"));
-            } else {
-                light_rgba.save(root.join(format!("{name}.png")))?;
-                gen_icon.base = Some(format!("{name}.png"));
-            }
+fn _extract_and_save_appx_icon(app_umid: &str, path: Option<&Path>) -> Result<()> {
+    log::trace!("Extracting icon for {app_umid:?}");
+    let mut gen_icon = Icon::default();
+    let scale_percent = UwpManager::primary_monitor_scale_percent();
+    let (class, asset) = UwpManager::get_high_quality_icon_path(app_umid, scale_percent)
+        .at_stage(IconExtractionStage::UwpAsset, app_umid)?;
+    log::trace!(
+        "Using UWP {class:?} asset of {:?}px: {}",
+        asset.pixel_size,
+        asset.light.display()
+    );
+    let (light_path, dark_path) = (asset.light, asset.dark);
 
-            gen_icon.is_aproximately_square = is_aproximately_a_square(&light_rgba);
+    let root = SEELEN_COMMON.user_icons_path().join("system");
+    let name = date_based_hex_id();
+
+    let light_rgba = image::open(&light_path)
+        .at_stage(IconExtractionStage::UwpAsset, light_path.display())?
+        .to_rgba8();
+    let dark_rgba = match light_path != dark_path {
+        true => Some(
+            image::open(&dark_path)
+                .at_stage(IconExtractionStage::UwpAsset, dark_path.display())?
+                .to_rgba8(),
+        ),
+        false => None,
+    };
 
-            let mut icon_manager = trace_lock!(icon_manager_mutex);
-            icon_manager.add_system_app_icon(Some(app_umid), path.as_deref(), gen_icon);
-            icon_manager.write_system_icon_pack()
+    // both variants are cropped with the same bounds, so they stay aligned
+    let shared_bounds = match &dark_rgba {
+        Some(dark_rgba) if dark_rgba.dimensions() == light_rgba.dimensions() => union_bounds(
+            find_visible_bounds(&light_rgba, 0),
+            find_visible_bounds(dark_rgba, 0),
+        ),
+        _ => None,
+    };
+    let prepare = |rgba: &RgbaImage| {
+        let cropped = match shared_bounds {
+            Some((left, top, right, bottom)) => rgba
+                .view(left, top, right - left + 1, bottom - top + 1)
+                .to_image(),
+            None => crop_transparent_borders(rgba),
+        };
+        match class.is_wide() {
+            true => crop_to_centered_square(&cropped),
+            false => cropped,
         }
-        AppUserModelId::PropertyStore(app_umid) => {
-            let start = START_MENU_MANAGER.load();
-            let lnk = start
-                .get_by_file_umid(app_umid)
-                .ok_or(format!("No shortcut found for umid {app_umid}"))?;
+    };
 
-            {
-                let manager = trace_lock!(icon_manager_mutex);
-                if manager.has_app_icon(Some(aumid.as_str()), Some(&lnk.path)) {
-                    return Ok(());
+    let light_rgba = prepare(&light_rgba);
+    // plated logos are drawn by the shell over the app background color
+    let light_rgba = match asset.plated && dark_rgba.is_none() {
+        true => compose_uwp_background(app_umid, light_rgba),
+        false => light_rgba,
+    };
+    let light_rgba = downscale_uwp_icon(light_rgba);
+    // decided on the light variant, so both variants get the same shape
+    let shaped = should_shape_icon(&light_rgba);
+    let light_rgba = match shaped {
+        true => shape_icon(&light_rgba),
+        false => light_rgba,
+    };
+
+    if let Some(dark_rgba) = dark_rgba {
+        let dark_rgba = downscale_uwp_icon(prepare(&dark_rgba));
+        let dark_rgba = match shaped {
+            true => shape_icon(&dark_rgba),
+            false => dark_rgba,
+        };
+
+        let (light_name, dark_name) = (format!("{name}_light"), format!("{name}_dark"));
+        gen_icon.light = Some(save_icon_image(&light_rgba, &root, &light_name)?);
+        gen_icon.dark = Some(save_icon_image(&dark_rgba, &root, &dark_name)?);
+        // the package has no base image, the light asset is used as the variants
+        // are the same artwork and the mask only takes the alpha channel
+        gen_icon.mask = save_icon_mask(&light_rgba, &root, &name)?;
+    } else {
+        gen_icon.base = Some(save_icon_image(&light_rgba, &root, &name)?);
+    }
+
+    gen_icon.is_aproximately_square = is_aproximately_a_square(&light_rgba);
+
+    let icon_manager_mutex = FULL_STATE.load().icon_packs().clone();
+    let mut icon_manager = trace_lock!(icon_manager_mutex);
+    icon_manager.add_system_app_icon(Some(app_umid), path, gen_icon);
+    icon_manager
+        .write_system_icon_pack()
+        .at_stage(IconExtractionStage::PackWrite, app_umid)
+}
+
+#[cfg(all(test, feature = "rayon"))]
+mod tests {
+    use std::time::Instant;
+
+    use image::Rgba;
+
+    use super::*;
+
+    /// Sparse pixels of random alpha inside a random rectangle, so the bounds are not
+    /// always the whole image and some pixels fall under the thresholds
+    fn random_icon(size: u32, seed: &mut u32) -> RgbaImage {
+        let mut next = move || {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 17;
+            *seed ^= *seed << 5;
+            *seed
+        };
+        let (x0, y0) = (next() % size, next() % size);
+        let (x1, y1) = (x0 + next() % (size - x0), y0 + next() % (size - y0));
+        let mut image = RgbaImage::new(size, size);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let inside = (x0..=x1).contains(&x) && (y0..=y1).contains(&y);
+            if inside && next() % 7 == 0 {
+                *pixel = Rgba([255, 255, 255, next() as u8]);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn parallel_bounds_match_the_sequential_ones() {
+        let mut seed = 0x2545_F491;
+        for size in [1, 2, 16, 33, 256, 512] {
+            for _ in 0..16 {
+                let image = random_icon(size, &mut seed);
+                for threshold in [0, 128, 254] {
+                    assert_eq!(
+                        find_visible_bounds(&image, threshold),
+                        find_visible_bounds_sequential(&image, threshold)
+                    );
                 }
             }
+        }
+        assert_eq!(find_visible_bounds(&RgbaImage::new(512, 512), 0), None);
+    }
 
-            _extract_and_save_icon_from_file(&lnk.path, Some(app_umid.clone()))?;
-            Ok(())
+    /// cargo test --features rayon -- --ignored --nocapture visible_bounds_timings
+    #[test]
+    #[ignore]
+    fn visible_bounds_timings() {
+        // small glyph centered on a large transparent canvas, the worst case of the scan
+        let image = RgbaImage::from_fn(512, 512, |x, y| {
+            match (200..312).contains(&x) && (200..312).contains(&y) {
+                true => Rgba([255, 255, 255, 255]),
+                false => Rgba([0, 0, 0, 0]),
+            }
+        });
+        const RUNS: u32 = 200;
+
+        let started_at = Instant::now();
+        for _ in 0..RUNS {
+            std::hint::black_box(find_visible_bounds_sequential(&image, 0));
+        }
+        let sequential = started_at.elapsed() / RUNS;
+
+        let started_at = Instant::now();
+        for _ in 0..RUNS {
+            std::hint::black_box(find_visible_bounds(&image, 0));
         }
+        let parallel = started_at.elapsed() / RUNS;
+
+        println!("512x512 visible bounds: sequential {sequential:?}, rayon {parallel:?}");
     }
 }
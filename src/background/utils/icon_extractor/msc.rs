@@ -0,0 +1,50 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::error::Result;
+use crate::windows_api::{string_utils::WindowsString, WindowsApi};
+
+use super::{IconExtractionError, IconExtractionStage};
+
+/// Only the parts of the MMC 2.0+ console file (`<MMC_ConsoleFile>`) needed to get the icon.
+#[derive(Debug, Deserialize)]
+struct MscConsoleFile {
+    #[serde(rename = "VisualAttributes")]
+    visual_attributes: Option<MscVisualAttributes>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MscVisualAttributes {
+    #[serde(rename = "Icon")]
+    icon: Option<MscIcon>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MscIcon {
+    #[serde(rename = "@File")]
+    file: String,
+    #[serde(rename = "@Index", default)]
+    index: i32,
+}
+
+/// Returns the resource file and index of the icon declared by the snap-in, like
+/// `<Icon Index="0" File="%SystemRoot%\system32\filemgmt.dll">`.
+///
+/// Legacy binary console files (MMC 1.x structured storage) are not supported.
+pub fn get_msc_icon_location(path: &Path) -> Result<(PathBuf, i32)> {
+    let file = std::fs::File::open(path)?;
+    let mut reader = std::io::BufReader::new(file);
+    let console: MscConsoleFile = quick_xml::de::from_reader(&mut reader)?;
+
+    let icon = console
+        .visual_attributes
+        .and_then(|attributes| attributes.icon)
+        .ok_or_else(|| {
+            IconExtractionError::new(IconExtractionStage::ShellLookup, "console without icon")
+                .with_key(path.display())
+        })?;
+
+    let file = WindowsApi::resolve_environment_variables(&WindowsString::from_str(&icon.file))?;
+    Ok((PathBuf::from(file.to_os_string()), icon.index))
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::sidecar::{system_pack_file, JsonSidecar};
+
+/// lowercase path -> stamp of the file when its extraction found no icon
+static NO_ICON: JsonSidecar<HashMap<String, FileStamp>> =
+    JsonSidecar::new(no_icon_files_path, |_| {});
+
+/// Size and modification time, any change of the file invalidates the entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    size: u64,
+    modified_ms: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = path.metadata().ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: metadata.len(),
+            modified_ms: modified.as_millis() as u64,
+        })
+    }
+}
+
+fn no_icon_files_path() -> PathBuf {
+    system_pack_file(".no_icon.json")
+}
+
+fn key_of(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+/// The file had no icon the last time it was extracted and didn't change since then
+pub fn is_known_without_icon(path: &Path) -> bool {
+    let key = key_of(path);
+    let mut no_icon = NO_ICON.lock();
+    let Some(stamp) = no_icon.get(&key) else {
+        return false;
+    };
+    if FileStamp::of(path).as_ref() == Some(stamp) {
+        return true;
+    }
+    no_icon.remove(&key);
+    drop(no_icon);
+    NO_ICON.save();
+    false
+}
+
+/// Records that the file has no icon at all, transient errors should not be recorded
+pub fn mark_without_icon(path: &Path) {
+    let Some(stamp) = FileStamp::of(path) else {
+        return;
+    };
+    NO_ICON.lock().insert(key_of(path), stamp);
+    NO_ICON.save();
+}
+
+pub fn clear_without_icon(path: &Path) {
+    if NO_ICON.lock().remove(&key_of(path)).is_some() {
+        NO_ICON.save();
+    }
+}
+
+/// Forgets all the files without icon, used when the system icons are deleted
+pub fn clear_all_without_icon() {
+    NO_ICON.lock().clear();
+    NO_ICON.save();
+}
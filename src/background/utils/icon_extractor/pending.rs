@@ -0,0 +1,86 @@
+use std::{collections::HashSet, sync::LazyLock};
+
+use parking_lot::Mutex;
+
+use crate::{
+    modules::start::application::START_MENU_MANAGER,
+    windows_api::{types::AppUserModelId, window::Window},
+};
+
+use super::extract_and_save_icon_umid;
+
+/// Property store umids without a shortcut on the start menu yet, the extraction is requested
+/// again once the start menu watcher indexes their shortcut or on the next window event of the app.
+static PENDING_UMIDS: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+pub fn mark_umid_pending(umid: &str) {
+    PENDING_UMIDS.lock().insert(umid.to_owned());
+}
+
+pub fn clear_pending_umid(umid: &str) {
+    PENDING_UMIDS.lock().remove(umid);
+}
+
+/// Requests again the extraction of the window's umid if it was left pending.
+pub fn retry_pending_umid_of_window(window: &Window) {
+    if PENDING_UMIDS.lock().is_empty() {
+        return;
+    }
+    if let Some(AppUserModelId::PropertyStore(umid)) = window.app_user_model_id() {
+        if PENDING_UMIDS.lock().remove(&umid) {
+            extract_and_save_icon_umid(&AppUserModelId::PropertyStore(umid));
+        }
+    }
+}
+
+/// Requests again the extraction of the pending umids whose shortcut is now on the start menu,
+/// called after the start menu is re-indexed.
+pub fn retry_pending_umids_with_shortcut() {
+    let start_menu = START_MENU_MANAGER.load();
+    let resolved = take_resolved(&mut PENDING_UMIDS.lock(), |umid| {
+        start_menu.get_by_file_umid(umid).is_some()
+    });
+    for umid in resolved {
+        extract_and_save_icon_umid(&AppUserModelId::PropertyStore(umid));
+    }
+}
+
+/// Removes and returns the umids that can be resolved now
+fn take_resolved(pending: &mut HashSet<String>, is_resolved: impl Fn(&str) -> bool) -> Vec<String> {
+    let resolved: Vec<String> = pending
+        .iter()
+        .filter(|umid| is_resolved(umid))
+        .cloned()
+        .collect();
+    for umid in &resolved {
+        pending.remove(umid);
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn umids_are_retried_once_their_shortcut_is_indexed() {
+        let mut pending = HashSet::from(["Installed.App".to_owned(), "Other.App".to_owned()]);
+        let mut indexed: HashSet<&str> = HashSet::new();
+
+        // first lookup, the installer did not create the shortcut yet
+        assert!(take_resolved(&mut pending, |umid| indexed.contains(umid)).is_empty());
+        assert_eq!(pending.len(), 2);
+
+        // the watcher indexes the new lnk file
+        indexed.insert("Installed.App");
+        assert_eq!(
+            take_resolved(&mut pending, |umid| indexed.contains(umid)),
+            ["Installed.App"]
+        );
+        assert_eq!(pending, HashSet::from(["Other.App".to_owned()]));
+
+        // retried only once
+        assert!(take_resolved(&mut pending, |umid| indexed.contains(umid)).is_empty());
+    }
+}
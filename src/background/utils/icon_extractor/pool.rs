@@ -0,0 +1,71 @@
+use std::{path::PathBuf, thread::JoinHandle};
+
+use crossbeam_channel::Sender;
+
+use crate::error::Result;
+
+use super::{
+    _extract_and_save_icon_from_file,
+    report::{clear_failure, record_failure, IconExtractionKey},
+};
+
+/// Workers extracting icons of files in parallel, used to scan big sets of files
+/// like the start menu without waiting for each shell call in sequence.
+pub struct IconExtractorPool {
+    sender: Option<Sender<PathBuf>>,
+    workers: Vec<JoinHandle<Vec<Result<()>>>>,
+}
+
+pub fn spawn_icon_extractor_pool(num_threads: usize) -> IconExtractorPool {
+    let (sender, receiver) = crossbeam_channel::unbounded::<PathBuf>();
+    let workers = (0..num_threads.max(1))
+        .map(|idx| {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("icon-extractor-{idx}"))
+                .spawn(move || {
+                    let mut results = Vec::new();
+                    for path in receiver {
+                        let key = IconExtractionKey::Path(path.clone());
+                        let result = _extract_and_save_icon_from_file(&path, None);
+                        match &result {
+                            Ok(()) => clear_failure(&key),
+                            Err(err) => record_failure(key, err),
+                        }
+                        results.push(result);
+                    }
+                    results
+                })
+                .expect("Failed to spawn icon extractor worker")
+        })
+        .collect();
+
+    IconExtractorPool {
+        sender: Some(sender),
+        workers,
+    }
+}
+
+impl IconExtractorPool {
+    pub fn submit(&self, path: PathBuf) {
+        if let Some(sender) = &self.sender {
+            if let Err(e) = sender.send(path) {
+                log::error!("Failed to submit icon extraction: {e}");
+            }
+        }
+    }
+
+    /// Waits for all the submitted files, results are not sorted by submission order
+    pub fn join(mut self) -> Vec<Result<()>> {
+        // closing the channel lets the workers finish after the queue is drained
+        self.sender.take();
+        let mut results = Vec::new();
+        for worker in self.workers.drain(..) {
+            match worker.join() {
+                Ok(worker_results) => results.extend(worker_results),
+                Err(_) => results.push(Err("Icon extractor worker panicked".into())),
+            }
+        }
+        results
+    }
+}
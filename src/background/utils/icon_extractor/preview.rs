@@ -0,0 +1,115 @@
+use std::{io::Cursor, path::Path};
+
+use image::{codecs::png::PngEncoder, RgbaImage};
+use serde::Serialize;
+
+use crate::{
+    error::{AppError, Result},
+    state::application::FULL_STATE,
+    windows_api::WindowsApi,
+};
+
+use super::{
+    get_all_icons_from_file, get_icon_for_hwnd, get_icon_from_file, get_icon_from_msc_file,
+    get_icon_from_protocol_url, get_icon_from_resource, get_icon_from_url_file,
+    get_url_from_url_file,
+};
+
+/// extracted icons are at most 256x256, so a bigger response means something went wrong
+const MAX_PREVIEW_BYTES: usize = 1024 * 1024;
+
+/// Errors sent to the settings UI, tagged by `kind` so they can be translated.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum IconPreviewError {
+    NotFound { path: String },
+    ExtractionFailed { message: String },
+    TooLarge { size: usize, max: usize },
+}
+
+impl From<AppError> for IconPreviewError {
+    fn from(err: AppError) -> Self {
+        // only the message, the trace is useless on the UI
+        let message = format!("{err:?}")
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        IconPreviewError::ExtractionFailed { message }
+    }
+}
+
+fn get_icon_for_preview(path: &Path) -> Result<RgbaImage> {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "url" => get_icon_from_url_file(path).or_else(|_| {
+            let url = get_url_from_url_file(path)?;
+            get_icon_from_protocol_url(&url, &FULL_STATE.load().icon_extraction.url_protocols)
+        }),
+        "lnk" => {
+            let icon_path = match WindowsApi::resolve_lnk_custom_icon_path(path) {
+                Ok(icon_path) => icon_path,
+                Err(_) => WindowsApi::resolve_lnk_target(path)?.0,
+            };
+            if icon_path.extension().is_some_and(|ext| ext != "lnk") {
+                get_icon_for_preview(&icon_path).or_else(|_| get_icon_from_file(path))
+            } else {
+                get_icon_from_file(path)
+            }
+        }
+        "cpl" | "scr" => get_icon_from_resource(path, 0).or_else(|_| get_icon_from_file(path)),
+        "msc" => get_icon_from_msc_file(path).or_else(|_| get_icon_from_file(path)),
+        _ => get_icon_from_file(path),
+    }
+}
+
+/// Extracts the icon of the file as png bytes, without adding it to the icon pack.
+pub fn preview_icon_for_path(path: &Path) -> std::result::Result<Vec<u8>, IconPreviewError> {
+    if !path.exists() {
+        return Err(IconPreviewError::NotFound {
+            path: path.to_string_lossy().to_string(),
+        });
+    }
+
+    encode_preview(&get_icon_for_preview(path)?)
+}
+
+/// Extracts every icon of a resource file (dll, exe, ico) as png bytes with its index,
+/// so the user can pick one of them as a custom icon.
+pub fn preview_all_icons_of_file(
+    path: &Path,
+) -> std::result::Result<Vec<(i32, Vec<u8>)>, IconPreviewError> {
+    if !path.exists() {
+        return Err(IconPreviewError::NotFound {
+            path: path.to_string_lossy().to_string(),
+        });
+    }
+
+    get_all_icons_from_file(path)?
+        .into_iter()
+        .map(|(index, icon)| Ok((index, encode_preview(&icon)?)))
+        .collect()
+}
+
+/// Extracts the icon of the app owning the window as png bytes, without adding it to the icon pack.
+pub fn preview_icon_for_window(hwnd: isize) -> std::result::Result<Vec<u8>, IconPreviewError> {
+    encode_preview(&get_icon_for_hwnd(hwnd)?)
+}
+
+fn encode_preview(icon: &RgbaImage) -> std::result::Result<Vec<u8>, IconPreviewError> {
+    let mut bytes = Vec::new();
+    icon.write_with_encoder(PngEncoder::new(Cursor::new(&mut bytes)))
+        .map_err(AppError::from)?;
+
+    if bytes.len() > MAX_PREVIEW_BYTES {
+        return Err(IconPreviewError::TooLarge {
+            size: bytes.len(),
+            max: MAX_PREVIEW_BYTES,
+        });
+    }
+    Ok(bytes)
+}
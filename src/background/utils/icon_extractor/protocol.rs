@@ -0,0 +1,109 @@
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+use image::RgbaImage;
+use winreg::{
+    enums::{HKEY_CLASSES_ROOT, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE},
+    RegKey,
+};
+
+use crate::{
+    error::Result,
+    windows_api::{string_utils::WindowsString, WindowsApi},
+};
+
+use super::{crop_transparent_borders, get_icon_from_file};
+
+/// Reads the `URL=` line of an internet shortcut
+pub fn get_url_from_url_file(path: &Path) -> Result<String> {
+    let file = std::fs::File::open(path)?;
+    let reader = std::io::BufReader::new(file);
+    for line in reader.lines() {
+        if let Some(url) = line?.strip_prefix("URL=") {
+            return Ok(url.trim().to_owned());
+        }
+    }
+    Err("URL not found on internet shortcut".into())
+}
+
+fn get_steam_path() -> Option<PathBuf> {
+    let from_user = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey("Software\\Valve\\Steam")
+        .and_then(|key| key.get_value::<String, _>("SteamPath"));
+    let from_machine = || {
+        RegKey::predef(HKEY_LOCAL_MACHINE)
+            .open_subkey("SOFTWARE\\WOW6432Node\\Valve\\Steam")
+            .and_then(|key| key.get_value::<String, _>("InstallPath"))
+    };
+    from_user
+        .or_else(|_| from_machine())
+        .ok()
+        .map(PathBuf::from)
+}
+
+/// `steam://rungameid/<id>` -> `<steam>/appcache/librarycache/<id>_icon.*`
+fn get_steam_game_icon(url: &str) -> Result<RgbaImage> {
+    let game_id = url
+        .strip_prefix("steam://rungameid/")
+        .map(|rest| rest.trim_end_matches('/'))
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+        .ok_or("Unsupported steam url")?;
+
+    let library_cache = get_steam_path()
+        .ok_or("Steam installation not found")?
+        .join("appcache")
+        .join("librarycache");
+
+    let prefix = format!("{game_id}_icon.");
+    let icon_path = std::fs::read_dir(&library_cache)?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        })
+        .ok_or_else(|| format!("No cached icon for steam game {game_id}"))?;
+
+    Ok(crop_transparent_borders(
+        &image::open(icon_path)?.to_rgba8(),
+    ))
+}
+
+/// Executable registered on `HKCR\<scheme>\shell\open\command`
+fn get_protocol_handler_path(scheme: &str) -> Result<PathBuf> {
+    let command: String = RegKey::predef(HKEY_CLASSES_ROOT)
+        .open_subkey(format!("{scheme}\\shell\\open\\command"))?
+        .get_value("")?;
+
+    let command = command.trim();
+    let executable = match command.strip_prefix('"') {
+        Some(rest) => rest.split('"').next(),
+        None => command.split_whitespace().next(),
+    }
+    .ok_or_else(|| format!("Invalid handler command for {scheme}"))?;
+
+    let executable =
+        WindowsApi::resolve_environment_variables(&WindowsString::from_str(executable))?;
+    Ok(PathBuf::from(executable.to_os_string()))
+}
+
+/// Resolves the icon of a shortcut to a custom protocol, like game launchers do,
+/// only protocols enabled on `protocols` are resolved.
+pub fn get_icon_from_protocol_url(url: &str, protocols: &[String]) -> Result<RgbaImage> {
+    let (scheme, _) = url.split_once("://").ok_or("Url without scheme")?;
+    let scheme = scheme.to_lowercase();
+    if !protocols.iter().any(|p| p.to_lowercase() == scheme) {
+        return Err(format!("Protocol {scheme} is not enabled for resolution").into());
+    }
+
+    if scheme == "steam" {
+        match get_steam_game_icon(url) {
+            Ok(icon) => return Ok(icon),
+            Err(err) => log::trace!("Failed to get steam game icon for {url}: {err}"),
+        }
+    }
+
+    get_icon_from_file(&get_protocol_handler_path(&scheme)?)
+}
@@ -1,8 +1,22 @@
 use std::{path::PathBuf, sync::LazyLock};
 
-use crate::{error::Result, event_manager, log_error, windows_api::types::AppUserModelId};
+use crate::{
+    error::Result,
+    event_manager, log_error,
+    state::application::{IconPacksManager, FULL_STATE},
+    trace_lock,
+    windows_api::types::AppUserModelId,
+};
 
-use super::{_extract_and_save_icon_from_file, _extract_and_save_icon_umid};
+use windows::Win32::Foundation::HWND;
+
+use super::{
+    _extract_and_save_icon_from_file, _extract_and_save_icon_from_window,
+    _extract_and_save_icon_umid,
+    metrics::{begin_batch_breakdown, begin_file_breakdown, end_file_breakdown, record_extraction},
+    refresh::finish_refresh,
+    report::{clear_failure, record_failure, IconExtractionKey},
+};
 
 pub static ICON_EXTRACTOR: LazyLock<IconExtractor> = LazyLock::new(IconExtractor::new);
 
@@ -12,19 +26,59 @@ pub struct IconExtractor {}
 pub enum IconExtractorRequest {
     AppUMID(AppUserModelId),
     Path(PathBuf),
+    /// file path of the process owning the window, the window is used as fallback
+    Window {
+        hwnd: isize,
+        path: PathBuf,
+    },
+    /// several files extracted with a single write of the icon pack at the end
+    Batch(Vec<PathBuf>),
+    /// extracts again an already stored icon, keeping the old one if the extraction fails
+    Refresh(IconExtractionKey),
 }
 
 event_manager!(IconExtractor, IconExtractorRequest);
 
+impl IconExtractorRequest {
+    /// batches are reported per file
+    fn report_key(&self) -> Option<IconExtractionKey> {
+        match self {
+            IconExtractorRequest::AppUMID(umid) => {
+                Some(IconExtractionKey::Umid(umid.as_str().to_owned()))
+            }
+            IconExtractorRequest::Path(path) | IconExtractorRequest::Window { path, .. } => {
+                Some(IconExtractionKey::Path(path.clone()))
+            }
+            IconExtractorRequest::Batch(_) => None,
+            IconExtractorRequest::Refresh(key) => Some(key.clone()),
+        }
+    }
+}
+
 impl IconExtractor {
     fn new() -> Self {
         let extractor = Self {};
         Self::subscribe(|request| {
-            log_error!(Self::process(request));
+            Self::process_and_report(request);
         });
         extractor
     }
 
+    fn process_and_report(request: IconExtractorRequest) {
+        let Some(key) = request.report_key() else {
+            log_error!(Self::process(request));
+            return;
+        };
+        match Self::process(request) {
+            Ok(()) => clear_failure(&key),
+            Err(err) => {
+                log::error!("{err:?}");
+                record_failure(key, &err);
+            }
+        }
+        record_extraction();
+    }
+
     pub fn request(request: IconExtractorRequest) {
         let _ = &*ICON_EXTRACTOR;
         log_error!(Self::event_tx().send(request));
@@ -38,6 +92,31 @@ impl IconExtractor {
             IconExtractorRequest::Path(path) => {
                 _extract_and_save_icon_from_file(&path, None)?;
             }
+            IconExtractorRequest::Window { hwnd, path } => {
+                _extract_and_save_icon_from_window(HWND(hwnd as _), &path)?;
+            }
+            IconExtractorRequest::Batch(paths) => {
+                let _batch = IconPacksManager::batch_write_guard();
+                begin_batch_breakdown();
+                for path in paths {
+                    let started = begin_file_breakdown();
+                    Self::process_and_report(IconExtractorRequest::Path(path.clone()));
+                    end_file_breakdown(path, started);
+                }
+            }
+            IconExtractorRequest::Refresh(key) => {
+                let mutex = FULL_STATE.load().icon_packs().clone();
+                let previous = trace_lock!(mutex).take_system_app_entries(&key);
+                let result = match &key {
+                    IconExtractionKey::Umid(umid) => {
+                        _extract_and_save_icon_umid(&umid.clone().into())
+                    }
+                    IconExtractionKey::Path(path) => _extract_and_save_icon_from_file(path, None),
+                };
+                trace_lock!(mutex).restore_system_app_entries(&key, previous)?;
+                finish_refresh(&key);
+                result?;
+            }
         }
         Ok(())
     }
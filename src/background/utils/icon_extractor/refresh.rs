@@ -0,0 +1,119 @@
+use std::{path::PathBuf, sync::LazyLock, time::Duration};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    log_error,
+    state::application::FULL_STATE,
+    trace_lock,
+    utils::{constants::SEELEN_COMMON, Debouncer},
+};
+
+use super::{
+    queue::{IconExtractor, IconExtractorRequest},
+    IconExtractionKey, ICON_PIPELINE_VERSION,
+};
+
+static REFRESH: LazyLock<Mutex<RefreshState>> =
+    LazyLock::new(|| Mutex::new(load_refresh_state().unwrap_or_default()));
+
+/// the progress is saved once the queue settles, not after each icon
+static SAVE_DEBOUNCER: LazyLock<Debouncer> =
+    LazyLock::new(|| Debouncer::new(Duration::from_millis(2000)));
+
+/// Stored with the system pack, so an interrupted refresh continues on next start
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RefreshState {
+    /// version of the pipeline that produced the system icons, 0 for icons older than the versioning
+    version: u32,
+    total: usize,
+    pending: Vec<IconExtractionKey>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconRefreshProgress {
+    pub total: usize,
+    pub done: usize,
+}
+
+fn refresh_state_path() -> PathBuf {
+    SEELEN_COMMON
+        .user_icons_path()
+        .join("system")
+        .join(".pipeline.json")
+}
+
+fn load_refresh_state() -> Result<RefreshState> {
+    let content = std::fs::read(refresh_state_path())?;
+    Ok(serde_json::from_slice(&content)?)
+}
+
+fn write_refresh_state(state: &RefreshState) -> Result<()> {
+    std::fs::write(refresh_state_path(), serde_json::to_vec(state)?)?;
+    Ok(())
+}
+
+fn queue_refresh(keys: &[IconExtractionKey]) {
+    for key in keys {
+        IconExtractor::request(IconExtractorRequest::Refresh(key.clone()));
+    }
+}
+
+/// On startup, queues the re-extraction of the system icons produced by an older pipeline
+/// and the ones left pending by an interrupted refresh.
+pub fn resume_icons_refresh() -> Result<()> {
+    let mut state = REFRESH.lock();
+    if state.version < ICON_PIPELINE_VERSION {
+        let keys = trace_lock!(FULL_STATE.load().icon_packs()).system_app_keys();
+        log::info!(
+            "Icon extraction pipeline updated to v{ICON_PIPELINE_VERSION}, refreshing {} icons",
+            keys.len()
+        );
+        state.version = ICON_PIPELINE_VERSION;
+        state.total = keys.len();
+        state.pending = keys;
+        write_refresh_state(&state)?;
+    }
+    queue_refresh(&state.pending);
+    Ok(())
+}
+
+/// Re-extracts all the system icons now, whatever the pipeline version they were made with
+pub fn refresh_all_icons() -> Result<()> {
+    let keys = trace_lock!(FULL_STATE.load().icon_packs()).system_app_keys();
+    let mut state = REFRESH.lock();
+    // keys already queued are not queued twice
+    let new_keys: Vec<_> = keys
+        .into_iter()
+        .filter(|key| !state.pending.contains(key))
+        .collect();
+    state.version = ICON_PIPELINE_VERSION;
+    state.total = state.pending.len() + new_keys.len();
+    state.pending.extend(new_keys.iter().cloned());
+    write_refresh_state(&state)?;
+    queue_refresh(&new_keys);
+    Ok(())
+}
+
+/// Called by the queue once the key was processed, successfully or not
+pub(super) fn finish_refresh(key: &IconExtractionKey) {
+    let mut state = REFRESH.lock();
+    state.pending.retain(|pending| pending != key);
+    if state.pending.is_empty() {
+        state.total = 0;
+    }
+    SAVE_DEBOUNCER.call(async move || {
+        log_error!(write_refresh_state(&REFRESH.lock()));
+    });
+}
+
+pub fn get_refresh_progress() -> IconRefreshProgress {
+    let state = REFRESH.lock();
+    IconRefreshProgress {
+        total: state.total,
+        done: state.total - state.pending.len(),
+    }
+}
@@ -0,0 +1,57 @@
+use std::{collections::VecDeque, path::PathBuf, sync::LazyLock};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, utils::now_timestamp_as_millis};
+
+/// max amount of failures kept, the oldest ones are dropped first
+const MAX_REPORT_ENTRIES: usize = 300;
+
+static REPORT: LazyLock<Mutex<VecDeque<IconExtractionFailure>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+/// Key of the failed extraction, used to retry it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value")]
+pub enum IconExtractionKey {
+    Umid(String),
+    Path(PathBuf),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IconExtractionFailure {
+    pub key: IconExtractionKey,
+    /// unix timestamp in milliseconds
+    pub timestamp: u64,
+    /// error message followed by the trace of where it was raised
+    pub errors: Vec<String>,
+}
+
+/// Records the failure, replacing any previous record of the same key.
+pub fn record_failure(key: IconExtractionKey, err: &AppError) {
+    let mut report = REPORT.lock();
+    report.retain(|failure| failure.key != key);
+    if report.len() >= MAX_REPORT_ENTRIES {
+        report.pop_front();
+    }
+    report.push_back(IconExtractionFailure {
+        key,
+        timestamp: now_timestamp_as_millis(),
+        errors: format!("{err:?}")
+            .lines()
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .collect(),
+    });
+}
+
+pub fn clear_failure(key: &IconExtractionKey) {
+    REPORT.lock().retain(|failure| &failure.key != key);
+}
+
+/// Failures sorted from the newest to the oldest
+pub fn get_failures() -> Vec<IconExtractionFailure> {
+    REPORT.lock().iter().rev().cloned().collect()
+}
@@ -0,0 +1,185 @@
+use image::RgbaImage;
+
+use crate::state::{application::FULL_STATE, domain::IconShape};
+
+use super::{crop_to_centered_square, is_aproximately_a_square};
+
+/// pixels at least this opaque count as covering the edge
+const EDGE_OPAQUE_ALPHA: u8 = 250;
+/// fraction of each edge that must be opaque, already rounded icons stay below it
+const FULL_BLEED_EDGE_COVERAGE: f32 = 0.9;
+/// samples per axis used to antialias the shape borders
+const SUPERSAMPLING: u32 = 4;
+
+/// Opaque pixels reach the four edges of the image, like screenshots or square tiles.
+/// Glyphs with transparent surroundings and icons already shaped by their authors don't.
+pub fn is_full_bleed(image: &RgbaImage) -> bool {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return false;
+    }
+
+    let covers = |pixels: &mut dyn Iterator<Item = (u32, u32)>, len: u32| {
+        let opaque = pixels
+            .filter(|(x, y)| image.get_pixel(*x, *y)[3] >= EDGE_OPAQUE_ALPHA)
+            .count();
+        opaque as f32 >= len as f32 * FULL_BLEED_EDGE_COVERAGE
+    };
+
+    covers(&mut (0..width).map(|x| (x, 0)), width)
+        && covers(&mut (0..width).map(|x| (x, height - 1)), width)
+        && covers(&mut (0..height).map(|y| (0, y)), height)
+        && covers(&mut (0..height).map(|y| (width - 1, y)), height)
+}
+
+/// The point lies inside the shape of side `size`, coordinates relative to its top-left corner
+fn is_inside_shape(shape: IconShape, x: f32, y: f32, size: f32, radius: f32) -> bool {
+    if radius <= 0.0 {
+        return true;
+    }
+    // distance to the center of the nearest corner arc, zero on the straight parts
+    let dx = (x - x.clamp(radius, size - radius)).abs() / radius;
+    let dy = (y - y.clamp(radius, size - radius)).abs() / radius;
+    match shape {
+        IconShape::None => true,
+        IconShape::RoundedRect => dx * dx + dy * dy <= 1.0,
+        IconShape::Squircle => dx.powi(4) + dy.powi(4) <= 1.0,
+    }
+}
+
+/// Multiplies the alpha of the square image by the coverage of the shape.
+/// `radius` is a fraction of the side, clamped to 0.5 which is a circle for rounded rects.
+pub fn apply_icon_shape(image: &mut RgbaImage, shape: IconShape, radius: f32) {
+    let size = image.width().min(image.height());
+    let side = size as f32;
+    let radius = radius.clamp(0.0, 0.5) * side;
+    let samples = SUPERSAMPLING * SUPERSAMPLING;
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        // the straight parts are fully covered, only the corners need sampling
+        let (fx, fy) = (x as f32, y as f32);
+        if (fx >= radius && fx + 1.0 <= side - radius)
+            || (fy >= radius && fy + 1.0 <= side - radius)
+        {
+            continue;
+        }
+
+        let mut inside = 0;
+        for sample in 0..samples {
+            let sx = fx + ((sample % SUPERSAMPLING) as f32 + 0.5) / SUPERSAMPLING as f32;
+            let sy = fy + ((sample / SUPERSAMPLING) as f32 + 0.5) / SUPERSAMPLING as f32;
+            if is_inside_shape(shape, sx, sy, side, radius) {
+                inside += 1;
+            }
+        }
+        pixel[3] = (pixel[3] as u32 * inside / samples) as u8;
+    }
+}
+
+/// Whether the extraction settings ask to shape this icon
+pub fn should_shape_icon(image: &RgbaImage) -> bool {
+    FULL_STATE.load().icon_extraction.icon_shape != IconShape::None
+        && is_aproximately_a_square(image)
+        && is_full_bleed(image)
+}
+
+/// Squares the icon and applies the shape of the extraction settings,
+/// the caller is expected to check `should_shape_icon` first.
+pub fn shape_icon(image: &RgbaImage) -> RgbaImage {
+    let state = FULL_STATE.load();
+    let settings = &state.icon_extraction;
+    let mut shaped = crop_to_centered_square(image);
+    apply_icon_shape(&mut shaped, settings.icon_shape, settings.icon_shape_radius);
+    shaped
+}
+
+/// Applies the shape of the extraction settings to full-bleed icons, the rest are left as they are
+pub fn shape_full_bleed_icon(image: RgbaImage) -> RgbaImage {
+    match should_shape_icon(&image) {
+        true => shape_icon(&image),
+        false => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// opaque up to the edges with varying colors, like a screenshot
+    fn screenshot_icon(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            Rgba([(x * 7 % 256) as u8, (y * 13 % 256) as u8, 128, 255])
+        })
+    }
+
+    /// opaque disc on a transparent background
+    fn glyph_icon(size: u32) -> RgbaImage {
+        let center = size as f32 / 2.0;
+        RgbaImage::from_fn(size, size, |x, y| {
+            let distance = (x as f32 + 0.5 - center).hypot(y as f32 + 0.5 - center);
+            match distance < center * 0.8 {
+                true => Rgba([30, 30, 200, 255]),
+                false => Rgba([0, 0, 0, 0]),
+            }
+        })
+    }
+
+    #[test]
+    fn screenshot_style_icons_are_full_bleed() {
+        assert!(is_full_bleed(&screenshot_icon(64)));
+    }
+
+    #[test]
+    fn glyphs_and_shaped_icons_are_not_full_bleed() {
+        assert!(!is_full_bleed(&glyph_icon(64)));
+        assert!(!is_full_bleed(&RgbaImage::new(0, 0)));
+
+        for shape in [IconShape::RoundedRect, IconShape::Squircle] {
+            let mut shaped = screenshot_icon(64);
+            apply_icon_shape(&mut shaped, shape, 0.25);
+            assert!(!is_full_bleed(&shaped), "{shape:?}");
+        }
+    }
+
+    #[test]
+    fn shape_only_clears_the_corners() {
+        for shape in [IconShape::RoundedRect, IconShape::Squircle] {
+            let original = screenshot_icon(64);
+            let mut shaped = original.clone();
+            apply_icon_shape(&mut shaped, shape, 0.25);
+
+            for corner in [(0, 0), (63, 0), (0, 63), (63, 63)] {
+                assert_eq!(shaped.get_pixel(corner.0, corner.1)[3], 0, "{shape:?}");
+            }
+            for inside in [(32, 0), (0, 32), (32, 32), (63, 32), (32, 63)] {
+                assert_eq!(
+                    shaped.get_pixel(inside.0, inside.1),
+                    original.get_pixel(inside.0, inside.1),
+                    "{shape:?} {inside:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn squircle_keeps_more_of_the_corners_than_rounded_rect() {
+        let alpha_sum = |shape| {
+            let mut image = screenshot_icon(64);
+            apply_icon_shape(&mut image, shape, 0.25);
+            image.pixels().map(|pixel| pixel[3] as u32).sum::<u32>()
+        };
+        assert!(alpha_sum(IconShape::Squircle) > alpha_sum(IconShape::RoundedRect));
+    }
+
+    #[test]
+    fn no_shape_or_radius_leaves_the_icon_untouched() {
+        let original = screenshot_icon(32);
+        for (shape, radius) in [(IconShape::None, 0.25), (IconShape::RoundedRect, 0.0)] {
+            let mut image = original.clone();
+            apply_icon_shape(&mut image, shape, radius);
+            assert_eq!(image, original, "{shape:?}");
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{mpsc::RecvTimeoutError, LazyLock},
+    time::Duration,
+};
+
+use image::RgbaImage;
+use parking_lot::Mutex;
+
+use crate::error::Result;
+
+/// after this amount of timeouts the extension skips the shell for the rest of the session
+const MAX_SHELL_TIMEOUTS: u32 = 2;
+
+/// lowercase extension -> shell calls that timed out for files with it
+static SHELL_TIMEOUTS: LazyLock<Mutex<HashMap<String, u32>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// The shell extensions of this kind of files hung repeatedly, so the shell is not used anymore
+pub fn is_shell_bypassed(path: &Path) -> bool {
+    SHELL_TIMEOUTS
+        .lock()
+        .get(&extension_of(path))
+        .is_some_and(|count| *count >= MAX_SHELL_TIMEOUTS)
+}
+
+/// Runs the shell call on its own thread, misbehaving shell extensions (like broken cloud drive
+/// overlay handlers) can hang it for minutes. On timeout the thread is abandoned, not joined,
+/// and `None` is returned.
+pub fn run_shell_call_with_timeout<F>(
+    path: &Path,
+    timeout: Duration,
+    call: F,
+) -> Option<Result<RgbaImage>>
+where
+    F: FnOnce() -> Result<RgbaImage> + Send + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(call());
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => Some(result),
+        Err(RecvTimeoutError::Disconnected) => {
+            Some(Err("Shell icon lookup thread panicked".into()))
+        }
+        Err(RecvTimeoutError::Timeout) => {
+            let extension = extension_of(path);
+            let mut timeouts = SHELL_TIMEOUTS.lock();
+            let count = timeouts.entry(extension.clone()).or_default();
+            *count += 1;
+            log::warn!(
+                "Shell icon lookup timed out after {}ms for {}",
+                timeout.as_millis(),
+                path.display()
+            );
+            if *count == MAX_SHELL_TIMEOUTS {
+                log::warn!("Shell icon lookups of .{extension} files will be skipped, they hang");
+            }
+            None
+        }
+    }
+}
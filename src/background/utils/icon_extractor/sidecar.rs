@@ -0,0 +1,102 @@
+use std::{
+    path::PathBuf,
+    sync::{LazyLock, OnceLock},
+    time::Duration,
+};
+
+use parking_lot::{Mutex, MutexGuard};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::Result,
+    log_error,
+    utils::{constants::SEELEN_COMMON, Debouncer},
+};
+
+/// Json file kept next to the icons, loaded on first use and written some time after the
+/// last change. A missing or invalid file starts empty.
+pub struct JsonSidecar<T> {
+    path: fn() -> PathBuf,
+    /// drops the entries of the icons deleted since the last run
+    prune: fn(&mut T),
+    data: OnceLock<Mutex<T>>,
+    save_debouncer: LazyLock<Debouncer>,
+}
+
+/// Stored with the system pack, so it is deleted with the extracted icons
+pub fn system_pack_file(name: &str) -> PathBuf {
+    SEELEN_COMMON.user_icons_path().join("system").join(name)
+}
+
+fn save_debouncer() -> Debouncer {
+    Debouncer::new(Duration::from_millis(1000))
+}
+
+impl<T> JsonSidecar<T>
+where
+    T: Serialize + DeserializeOwned + Default + Send + 'static,
+{
+    pub const fn new(path: fn() -> PathBuf, prune: fn(&mut T)) -> Self {
+        Self {
+            path,
+            prune,
+            data: OnceLock::new(),
+            save_debouncer: LazyLock::new(save_debouncer),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.data
+            .get_or_init(|| Mutex::new(self.load().unwrap_or_default()))
+            .lock()
+    }
+
+    fn load(&self) -> Result<T> {
+        let content = std::fs::read((self.path)())?;
+        let mut data: T = serde_json::from_slice(&content)?;
+        (self.prune)(&mut data);
+        Ok(data)
+    }
+
+    pub fn write(&self) -> Result<()> {
+        let content = serde_json::to_vec(&*self.lock())?;
+        std::fs::write((self.path)(), content)?;
+        Ok(())
+    }
+
+    /// Writes the file once the changes settle, call it after each change
+    pub fn save(&'static self) {
+        self.save_debouncer.call(async move || {
+            log_error!(self.write());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn test_path() -> PathBuf {
+        std::env::temp_dir().join("slu-json-sidecar-test.json")
+    }
+
+    fn retain_even(data: &mut HashMap<String, u32>) {
+        data.retain(|_, value| *value % 2 == 0);
+    }
+
+    #[test]
+    fn loaded_entries_are_pruned_and_written_back() {
+        std::fs::write(test_path(), r#"{"a":1,"b":2}"#).unwrap();
+        let sidecar = JsonSidecar::new(test_path, retain_even);
+        assert_eq!(*sidecar.lock(), HashMap::from([("b".to_owned(), 2)]));
+
+        sidecar.lock().insert("c".to_owned(), 3);
+        sidecar.write().unwrap();
+        let written: HashMap<String, u32> =
+            serde_json::from_slice(&std::fs::read(test_path()).unwrap()).unwrap();
+        assert_eq!(written.len(), 2);
+        std::fs::remove_file(test_path()).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use image::{
+    codecs::{
+        png::{CompressionType, FilterType, PngEncoder},
+        webp::WebPEncoder,
+    },
+    RgbaImage,
+};
+
+use crate::{
+    error::Result,
+    state::{
+        application::FULL_STATE,
+        domain::{IconStorageFormat, PngCompression},
+    },
+};
+
+use super::{
+    colors::record_icon_colors,
+    metrics::{MetricStage, StageTimer},
+    IconExtractionResultExt, IconExtractionStage,
+};
+
+/// Encodes the image following the storage settings and writes it as `{name}.{ext}` on `root`,
+/// returns the filename to be stored on the icon pack.
+pub fn save_icon_image(image: &RgbaImage, root: &Path, name: &str) -> Result<String> {
+    let _timer = StageTimer::start(MetricStage::Save);
+    let state = FULL_STATE.load();
+    let settings = &state.icon_extraction;
+
+    let filename = format!("{name}.{}", settings.storage_format.extension());
+    let path = root.join(&filename);
+    let writer =
+        BufWriter::new(File::create(&path).at_stage(IconExtractionStage::Save, path.display())?);
+
+    match settings.storage_format {
+        IconStorageFormat::Png => {
+            let compression = match settings.png_compression {
+                PngCompression::Fast => CompressionType::Fast,
+                PngCompression::Default => CompressionType::Default,
+                PngCompression::Best => CompressionType::Best,
+            };
+            let encoder = PngEncoder::new_with_quality(writer, compression, FilterType::Adaptive);
+            image
+                .write_with_encoder(encoder)
+                .at_stage(IconExtractionStage::Save, path.display())?;
+        }
+        IconStorageFormat::Webp => {
+            image
+                .write_with_encoder(WebPEncoder::new_lossless(writer))
+                .at_stage(IconExtractionStage::Save, path.display())?;
+        }
+    }
+    // masks are white silhouettes, their colors mean nothing
+    if !name.ends_with("_mask") {
+        record_icon_colors(&path, image);
+    }
+    Ok(filename)
+}
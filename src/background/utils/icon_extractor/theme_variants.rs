@@ -0,0 +1,258 @@
+use image::{Rgba, RgbaImage};
+
+/// pixels with less alpha than this are ignored on the analysis
+const ANALYSIS_ALPHA_THRESHOLD: u8 = 128;
+/// chroma (max - min channel, 0..1) from where a pixel is considered colored
+const COLORED_PIXEL_CHROMA: f32 = 0.2;
+/// max fraction of colored pixels allowed to consider an icon monochrome
+const COLORED_PIXELS_TOLERANCE: f32 = 0.02;
+const DARK_LUMINANCE_LIMIT: f32 = 0.25;
+const LIGHT_LUMINANCE_LIMIT: f32 = 0.75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconTone {
+    /// dark glyph, invisible on dark themes
+    MonochromeDark,
+    /// light glyph, invisible on light themes
+    MonochromeLight,
+    /// colorful or mid-tone icons, these should never be altered
+    Mixed,
+}
+
+fn relative_luminance(pixel: &Rgba<u8>) -> f32 {
+    (0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32) / 255.0
+}
+
+fn chroma(pixel: &Rgba<u8>) -> f32 {
+    let max = pixel[0].max(pixel[1]).max(pixel[2]);
+    let min = pixel[0].min(pixel[1]).min(pixel[2]);
+    (max - min) as f32 / 255.0
+}
+
+/// Computes the average luminance over opaque pixels, icons with colored pixels are always `Mixed`.
+pub fn analyze_icon_tone(image: &RgbaImage) -> IconTone {
+    let mut opaque = 0u32;
+    let mut colored = 0u32;
+    let mut luminance_sum = 0f32;
+
+    for pixel in image.pixels() {
+        if pixel[3] < ANALYSIS_ALPHA_THRESHOLD {
+            continue;
+        }
+        opaque += 1;
+        luminance_sum += relative_luminance(pixel);
+        if chroma(pixel) > COLORED_PIXEL_CHROMA {
+            colored += 1;
+        }
+    }
+
+    if opaque == 0 || colored as f32 / opaque as f32 > COLORED_PIXELS_TOLERANCE {
+        return IconTone::Mixed;
+    }
+
+    let average = luminance_sum / opaque as f32;
+    if average <= DARK_LUMINANCE_LIMIT {
+        IconTone::MonochromeDark
+    } else if average >= LIGHT_LUMINANCE_LIMIT {
+        IconTone::MonochromeLight
+    } else {
+        IconTone::Mixed
+    }
+}
+
+fn rgb_to_hsl(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    if max == min {
+        return (0.0, 0.0, l);
+    }
+
+    let d = max - min;
+    let s = if l > 0.5 {
+        d / (2.0 - max - min)
+    } else {
+        d / (max + min)
+    };
+    let h = if max == r {
+        (g - b) / d + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h / 6.0, s, l)
+}
+
+fn hue_to_rgb(p: f32, q: f32, mut t: f32) -> f32 {
+    if t < 0.0 {
+        t += 1.0;
+    }
+    if t > 1.0 {
+        t -= 1.0;
+    }
+    if t < 1.0 / 6.0 {
+        return p + (q - p) * 6.0 * t;
+    }
+    if t < 1.0 / 2.0 {
+        return q;
+    }
+    if t < 2.0 / 3.0 {
+        return p + (q - p) * (2.0 / 3.0 - t) * 6.0;
+    }
+    p
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    if s == 0.0 {
+        return (l, l, l);
+    }
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    (
+        hue_to_rgb(p, q, h + 1.0 / 3.0),
+        hue_to_rgb(p, q, h),
+        hue_to_rgb(p, q, h - 1.0 / 3.0),
+    )
+}
+
+/// Inverts the lightness of each pixel, preserving hue, saturation and alpha.
+pub fn invert_luminance(image: &RgbaImage) -> RgbaImage {
+    let mut result = image.clone();
+    for pixel in result.pixels_mut() {
+        let (h, s, l) = rgb_to_hsl(
+            pixel[0] as f32 / 255.0,
+            pixel[1] as f32 / 255.0,
+            pixel[2] as f32 / 255.0,
+        );
+        let (r, g, b) = hsl_to_rgb(h, s, 1.0 - l);
+        pixel[0] = (r * 255.0).round() as u8;
+        pixel[1] = (g * 255.0).round() as u8;
+        pixel[2] = (b * 255.0).round() as u8;
+    }
+    result
+}
+
+/// Returns the (light, dark) theme variants for monochrome icons,
+/// `None` if the icon works on both themes as is.
+pub fn generate_theme_variants(image: &RgbaImage) -> Option<(RgbaImage, RgbaImage)> {
+    match analyze_icon_tone(image) {
+        IconTone::MonochromeDark => Some((image.clone(), invert_luminance(image))),
+        IconTone::MonochromeLight => Some((invert_luminance(image), image.clone())),
+        IconTone::Mixed => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// glyph of the given color over a transparent background
+    fn glyph(color: [u8; 3]) -> RgbaImage {
+        RgbaImage::from_fn(16, 16, |x, y| {
+            match (4..12).contains(&x) && (4..12).contains(&y) {
+                true => Rgba([color[0], color[1], color[2], 255]),
+                false => Rgba([0, 0, 0, 0]),
+            }
+        })
+    }
+
+    #[test]
+    fn tone_of_monochrome_icons() {
+        assert_eq!(
+            analyze_icon_tone(&glyph([0, 0, 0])),
+            IconTone::MonochromeDark
+        );
+        assert_eq!(
+            analyze_icon_tone(&glyph([30, 30, 30])),
+            IconTone::MonochromeDark
+        );
+        assert_eq!(
+            analyze_icon_tone(&glyph([255, 255, 255])),
+            IconTone::MonochromeLight
+        );
+        assert_eq!(
+            analyze_icon_tone(&glyph([230, 230, 230])),
+            IconTone::MonochromeLight
+        );
+    }
+
+    #[test]
+    fn tone_of_mixed_icons() {
+        assert_eq!(analyze_icon_tone(&glyph([128, 128, 128])), IconTone::Mixed);
+        assert_eq!(analyze_icon_tone(&glyph([200, 20, 20])), IconTone::Mixed);
+        assert_eq!(analyze_icon_tone(&RgbaImage::new(16, 16)), IconTone::Mixed);
+    }
+
+    #[test]
+    fn colored_pixels_over_the_tolerance_make_the_icon_mixed() {
+        let mut icon = glyph([0, 0, 0]);
+        icon.put_pixel(4, 4, Rgba([0, 0, 255, 255]));
+        // 1 of 64 opaque pixels is under the tolerance
+        assert_eq!(analyze_icon_tone(&icon), IconTone::MonochromeDark);
+        icon.put_pixel(5, 4, Rgba([0, 0, 255, 255]));
+        assert_eq!(analyze_icon_tone(&icon), IconTone::Mixed);
+    }
+
+    #[test]
+    fn translucent_pixels_are_ignored() {
+        let mut icon = glyph([0, 0, 0]);
+        for x in 0..4 {
+            icon.put_pixel(x, 0, Rgba([255, 0, 0, 100]));
+        }
+        assert_eq!(analyze_icon_tone(&icon), IconTone::MonochromeDark);
+    }
+
+    #[test]
+    fn invert_luminance_keeps_hue_and_alpha() {
+        let mut icon = RgbaImage::new(3, 1);
+        icon.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+        icon.put_pixel(1, 0, Rgba([255, 255, 255, 40]));
+        icon.put_pixel(2, 0, Rgba([128, 0, 0, 255]));
+
+        let inverted = invert_luminance(&icon);
+        assert_eq!(inverted.get_pixel(0, 0).0, [255, 255, 255, 255]);
+        assert_eq!(inverted.get_pixel(1, 0).0, [0, 0, 0, 40]);
+        // dark red becomes light red
+        let [r, g, b, a] = inverted.get_pixel(2, 0).0;
+        assert_eq!((r, a), (255, 255));
+        assert_eq!(g, b);
+        assert!((126..=128).contains(&g));
+    }
+
+    #[test]
+    fn variants_of_dark_icons() {
+        let icon = glyph([0, 0, 0]);
+        let (light, dark) = generate_theme_variants(&icon).unwrap();
+        assert_eq!(light, icon);
+        assert_eq!(dark.get_pixel(8, 8).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn variants_of_light_icons() {
+        let icon = glyph([255, 255, 255]);
+        let (light, dark) = generate_theme_variants(&icon).unwrap();
+        assert_eq!(light.get_pixel(8, 8).0, [0, 0, 0, 255]);
+        assert_eq!(dark, icon);
+    }
+
+    #[test]
+    fn mixed_icons_have_no_variants() {
+        assert!(generate_theme_variants(&glyph([200, 20, 20])).is_none());
+    }
+
+    #[test]
+    fn variants_share_the_alpha_of_the_base_icon() {
+        // masks are derived from the alpha channel only, so any variant gives the same mask
+        let mut icon = glyph([10, 10, 10]);
+        icon.put_pixel(4, 4, Rgba([10, 10, 10, 90]));
+        let (light, dark) = generate_theme_variants(&icon).unwrap();
+        let alpha = |image: &RgbaImage| image.pixels().map(|p| p[3]).collect::<Vec<_>>();
+        assert_eq!(alpha(&light), alpha(&icon));
+        assert_eq!(alpha(&dark), alpha(&icon));
+    }
+}
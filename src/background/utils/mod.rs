@@ -260,6 +260,15 @@ impl Debouncer {
 
     /// Call the function after the delay.
     pub fn call<F, Fut, R>(&self, f: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = R> + Send + 'static,
+    {
+        self.call_after(self.delay, f);
+    }
+
+    /// Call the function after the given delay instead of the default one.
+    pub fn call_after<F, Fut, R>(&self, delay: Duration, f: F)
     where
         F: FnOnce() -> Fut + Send + 'static,
         Fut: std::future::Future<Output = R> + Send + 'static,
@@ -272,7 +281,6 @@ impl Debouncer {
         }
 
         // Set a new timer
-        let delay = self.delay;
         *task = Some(get_tokio_handle().spawn(async move {
             tokio::time::sleep(delay).await;
             f().await;
@@ -24,7 +24,7 @@ use crate::{
     state::application::FULL_STATE,
     utils::{
         constants::SEELEN_COMMON,
-        icon_extractor::{extract_and_save_icon_from_file, extract_and_save_icon_umid},
+        icon_extractor::{extract_and_save_icon_from_window, extract_and_save_icon_umid},
     },
     windows_api::{types::AppUserModelId, window::Window, MonitorEnumerator},
 };
@@ -203,7 +203,7 @@ impl WegItemsImpl {
                             .to_string();
                     } else {
                         // pre-extraction to avoid flickering on the ui
-                        extract_and_save_icon_from_file(&path);
+                        extract_and_save_icon_from_window(window.hwnd(), &path);
                     }
 
                     // System.AppUserModel.RelaunchCommand and System.AppUserModel.RelaunchDisplayNameResource
@@ -231,7 +231,7 @@ impl WegItemsImpl {
             }
         } else {
             // pre-extraction to avoid flickering on the ui
-            extract_and_save_icon_from_file(&path);
+            extract_and_save_icon_from_window(window.hwnd(), &path);
             (path.to_string_lossy().to_string(), None)
         };
 
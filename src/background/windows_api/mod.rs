@@ -72,7 +72,7 @@ use windows::{
                 PKEY_AppUserModel_RelaunchIconResource, PKEY_AppUserModel_ToastActivatorCLSID,
                 PKEY_FileDescription,
             },
-            FileSystem::WIN32_FIND_DATAW,
+            FileSystem::{ReplaceFileW, REPLACEFILE_IGNORE_MERGE_ERRORS, WIN32_FIND_DATAW},
         },
         System::{
             Com::{IPersistFile, STGM_READ},
@@ -653,6 +653,26 @@ impl WindowsApi {
         Ok(out)
     }
 
+    /// https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-replacefilew
+    /// Replaces `target` with `replacement` keeping the attributes and ACLs of the target,
+    /// the previous content of the target is moved to `backup`.
+    pub fn replace_file(target: &Path, replacement: &Path, backup: &Path) -> Result<()> {
+        let target = WindowsString::from_os_string(target.as_os_str());
+        let replacement = WindowsString::from_os_string(replacement.as_os_str());
+        let backup = WindowsString::from_os_string(backup.as_os_str());
+        unsafe {
+            ReplaceFileW(
+                target.as_pcwstr(),
+                replacement.as_pcwstr(),
+                backup.as_pcwstr(),
+                REPLACEFILE_IGNORE_MERGE_ERRORS,
+                None,
+                None,
+            )?
+        };
+        Ok(())
+    }
+
     pub fn get_executable_display_name(path: &Path) -> Result<String> {
         Com::run_with_context(|| unsafe {
             let shell_item = Self::get_shell_item(path)?;
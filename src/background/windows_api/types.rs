@@ -1,4 +1,21 @@
-use super::WindowsApi;
+use std::collections::HashSet;
+
+use windows::Win32::Foundation::HWND;
+use winreg::{
+    enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE},
+    RegKey,
+};
+
+use crate::{
+    error::Result,
+    log_error,
+    modules::{start::application::START_MENU_MANAGER, uwp::UwpManager},
+};
+
+use super::{WindowEnumerator, WindowsApi};
+
+/// apps register their explicit ids here, mostly to receive notifications
+const REGISTERED_UMIDS_KEY: &str = r"Software\Classes\AppUserModelId";
 
 #[derive(Debug, Clone)]
 pub enum AppUserModelId {
@@ -12,6 +29,57 @@ impl AppUserModelId {
     pub fn is_appx(&self) -> bool {
         matches!(self, AppUserModelId::Appx(_))
     }
+
+    /// Id set on the property store of the window, the variant is decided by its format.
+    /// Ids inherited from the process are not resolved, see `Window::app_user_model_id`.
+    pub fn from_hwnd(hwnd: isize) -> Result<Self> {
+        let umid = WindowsApi::get_window_app_user_model_id(HWND(hwnd as _))?;
+        Ok(umid.into())
+    }
+
+    /// Ids set on the visible top-level windows with the process id of their window,
+    /// each id listed once. Used to know the apps to extract icons for on startup.
+    pub fn for_all_running_processes() -> Vec<(u32, AppUserModelId)> {
+        let mut seen = HashSet::new();
+        let mut running = Vec::new();
+        log_error!(WindowEnumerator::new().for_each(|window| {
+            if !window.is_visible() {
+                return;
+            }
+            let Ok(umid) = Self::from_hwnd(window.address()) else {
+                return;
+            };
+            if seen.insert(umid.to_string()) {
+                running.push((window.process().id(), umid));
+            }
+        }));
+        running
+    }
+
+    /// Fails if the id doesn't belong to an installed app: a package installed for the user,
+    /// or an id registered by the app or set on a start menu shortcut.
+    #[allow(dead_code)]
+    pub fn validate(&self) -> Result<()> {
+        self.normalize().map(|_| ())
+    }
+
+    /// The id with the casing used by the system, fails on ids not valid for `validate`
+    #[allow(dead_code)]
+    pub fn normalize(&self) -> Result<AppUserModelId> {
+        match self {
+            AppUserModelId::Appx(umid) => {
+                let canonical = UwpManager::canonical_app_umid(umid.trim())?;
+                Ok(AppUserModelId::Appx(canonical))
+            }
+            AppUserModelId::PropertyStore(umid) => {
+                let umid = umid.trim();
+                find_registered_umid(umid)
+                    .or_else(|| find_shortcut_umid(umid))
+                    .map(AppUserModelId::PropertyStore)
+                    .ok_or_else(|| format!("App user model id {umid} is not registered").into())
+            }
+        }
+    }
 }
 
 impl std::fmt::Display for AppUserModelId {
@@ -36,8 +104,35 @@ impl From<AppUserModelId> for windows_core::HSTRING {
     }
 }
 
+/// Registered id matching `umid` ignoring the case
+fn find_registered_umid(umid: &str) -> Option<String> {
+    [HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE]
+        .into_iter()
+        .find_map(|hive| {
+            RegKey::predef(hive)
+                .open_subkey(REGISTERED_UMIDS_KEY)
+                .ok()?
+                .enum_keys()
+                .flatten()
+                .find(|key| key.eq_ignore_ascii_case(umid))
+        })
+}
+
+/// Id set on a start menu shortcut matching `umid` ignoring the case
+fn find_shortcut_umid(umid: &str) -> Option<String> {
+    START_MENU_MANAGER
+        .load()
+        .list
+        .iter()
+        .filter_map(|item| item.umid.as_ref())
+        .find(|item_umid| item_umid.eq_ignore_ascii_case(umid))
+        .cloned()
+}
+
 impl From<String> for AppUserModelId {
     fn from(value: String) -> Self {
+        // ids read from settings or the registry can come with surrounding whitespace
+        let value = value.trim().to_owned();
         if WindowsApi::is_uwp_package_id(&value) {
             AppUserModelId::Appx(value)
         } else {
@@ -98,11 +98,8 @@ impl Window {
     ///
     /// https://learn.microsoft.com/en-us/windows/win32/properties/props-system-appusermodel-id
     pub fn app_user_model_id(&self) -> Option<AppUserModelId> {
-        if let Ok(umid) = WindowsApi::get_window_app_user_model_id(self.0) {
-            return match WindowsApi::is_uwp_package_id(&umid) {
-                true => Some(AppUserModelId::Appx(umid)),
-                false => Some(AppUserModelId::PropertyStore(umid)),
-            };
+        if let Ok(umid) = AppUserModelId::from_hwnd(self.address()) {
+            return Some(umid);
         }
 
         let process = self.process();
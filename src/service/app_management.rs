@@ -1,4 +1,5 @@
 use std::{
+    path::Path,
     process::Command,
     sync::atomic::{AtomicUsize, Ordering},
 };
@@ -6,8 +7,8 @@ use std::{
 use windows::Win32::UI::Shell::{FOLDERID_LocalAppData, FOLDERID_Windows};
 
 use crate::{
-    enviroment::was_installed_using_msix, error::Result, was_started_from_startup_action,
-    windows_api::WindowsApi,
+    enviroment::was_installed_using_msix, error::Result, is_development,
+    was_started_from_startup_action, windows_api::WindowsApi,
 };
 
 pub static GUI_RESTARTED_COUNTER: AtomicUsize = AtomicUsize::new(0);
@@ -34,6 +35,46 @@ pub fn launch_seelen_ui() -> Result<()> {
     Ok(())
 }
 
+/// Only Seelen UI and this same executable (used as cli) can send actions to the service.
+/// The token embedded in the binaries is not enough as any process could read it.
+/// Skipped on development, so builds from other folders or tools can connect.
+pub fn is_trusted_ipc_client(pid: u32) -> bool {
+    if is_development() {
+        return true;
+    }
+
+    let Ok(service_exe) = std::env::current_exe() else {
+        return false;
+    };
+
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut sys = sysinfo::System::new();
+    sys.refresh_process(pid);
+    let Some(image) = sys.process(pid).and_then(|p| p.exe()) else {
+        log::warn!("Rejected IPC client {pid}, its executable could not be resolved");
+        return false;
+    };
+
+    let is_trusted = is_trusted_image(image, &service_exe);
+    if !is_trusted {
+        log::warn!("Rejected IPC client {pid} running {image:?}");
+    }
+    is_trusted
+}
+
+/// Seelen UI next to the service or the service itself, paths are compared ignoring case
+fn is_trusted_image(image: &Path, service_exe: &Path) -> bool {
+    // msix installs run both executables from the package folder, not from the alias
+    let trusted = [
+        service_exe.with_file_name("seelen-ui.exe"),
+        service_exe.to_path_buf(),
+    ];
+    let image = image.to_string_lossy().to_lowercase();
+    trusted
+        .iter()
+        .any(|path| path.to_string_lossy().to_lowercase() == image)
+}
+
 pub fn kill_seelen_ui_processes() -> Result<()> {
     let mut sys = sysinfo::System::new();
     sys.refresh_processes();
@@ -48,3 +89,27 @@ pub fn kill_seelen_ui_processes() -> Result<()> {
     GUI_RESTARTED_COUNTER.store(0, Ordering::SeqCst);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_seelen_executables_are_trusted() {
+        let service = Path::new(r"C:\Program Files\Seelen\Seelen UI\slu-service.exe");
+        for image in [
+            r"C:\Program Files\Seelen\Seelen UI\seelen-ui.exe",
+            r"C:\Program Files\Seelen\Seelen UI\slu-service.exe",
+            r"c:\program files\seelen\seelen ui\SEELEN-UI.EXE",
+        ] {
+            assert!(is_trusted_image(Path::new(image), service), "{image}");
+        }
+        for image in [
+            r"C:\Users\user\Downloads\seelen-ui.exe",
+            r"C:\Program Files\Seelen\Seelen UI\other.exe",
+            r"C:\Program Files\Seelen\Seelen UI\seelen-ui.exe.bak",
+        ] {
+            assert!(!is_trusted_image(Path::new(image), service), "{image}");
+        }
+    }
+}
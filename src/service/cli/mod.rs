@@ -19,6 +19,7 @@ impl ServiceSubcommands {
     pub const INSTALL: &str = "install";
     pub const UNINSTALL: &str = "uninstall";
     pub const STOP: &str = "stop";
+    pub const STARTUP_STATUS: &str = "startup-status";
 }
 
 pub fn get_cli() -> Command {
@@ -34,6 +35,8 @@ pub fn get_cli() -> Command {
             Command::new(ServiceSubcommands::UNINSTALL)
                 .about("Uninstalls the service (elevation required)."),
             Command::new(ServiceSubcommands::STOP).about("Stops the service."),
+            Command::new(ServiceSubcommands::STARTUP_STATUS)
+                .about("Prints if the service is started on logon (enabled or disabled)."),
         ])
         .args([Arg::new("startup")
             .short('S')
@@ -65,7 +68,14 @@ pub async fn handle_console_client() -> Result<()> {
             TaskSchedulerHelper::remove_service_task()?;
         }
         Some((ServiceSubcommands::STOP, _)) => {
-            ServiceIpc::send(SvcAction::Stop).await?;
+            let ack = ServiceIpc::send_with_response(SvcAction::Stop).await?;
+            println!("Service acknowledged stop signal: {ack}");
+        }
+        Some((ServiceSubcommands::STARTUP_STATUS, _)) => {
+            match TaskSchedulerHelper::is_startup_enabled()? {
+                true => println!("enabled"),
+                false => println!("disabled"),
+            }
         }
         _ => {}
     }
@@ -1,18 +1,119 @@
-use std::sync::LazyLock;
+use std::{
+    sync::{LazyLock, Mutex},
+    time::UNIX_EPOCH,
+};
 
 use positioning::{easings::Easing, AppWinAnimation, Positioner};
-use seelen_core::state::shortcuts::SluShortcutsSettings;
-use slu_ipc::messages::{IpcResponse, SvcAction};
+use seelen_core::{rect::Rect, state::shortcuts::SluShortcutsSettings};
+use slu_ipc::{
+    messages::{IpcResponse, ServiceStatus, SvcAction, SvcEvent},
+    ServiceIpc,
+};
 
-use crate::{error::Result, task_scheduler::TaskSchedulerHelper, windows_api::WindowsApi};
+use crate::{
+    error::Result, snap_zones, task_scheduler::TaskSchedulerHelper, windows_api::WindowsApi,
+};
+
+/// last state of the logon task sent to the subscribers
+static STARTUP_TASK_STATE: Mutex<Option<bool>> = Mutex::new(None);
+
+/// dpi of the monitors at 100% scale, the one of logical pixels
+const LOGICAL_DPI: u32 = 96;
 
 static ANIMATION_INSTANCE: LazyLock<tokio::sync::Mutex<Option<AppWinAnimation>>> =
     LazyLock::new(|| tokio::sync::Mutex::new(None));
 
-async fn _process_action(command: SvcAction) -> Result<()> {
+/// connections are handled concurrently, the scheduler tasks are edited one at a time
+static TASK_SCHEDULER_LOCK: LazyLock<tokio::sync::Mutex<()>> =
+    LazyLock::new(|| tokio::sync::Mutex::new(()));
+
+/// Rects of `SvcAction::SetWindowPositionOnMonitor` are logical
+fn to_physical_rect(rect: Rect, monitor: isize) -> Result<Rect> {
+    let dpi = WindowsApi::monitor_dpi(monitor)?;
+    WindowsApi::scale_rect_on_monitor(&rect, monitor, LOGICAL_DPI, dpi)
+}
+
+fn to_positioning_rect(rect: &Rect) -> positioning::rect::Rect {
+    positioning::rect::Rect {
+        x: rect.left,
+        y: rect.top,
+        width: rect.right - rect.left,
+        height: rect.bottom - rect.top,
+    }
+}
+
+/// Places the windows of the positioner, animated with the duration and easing if given.
+/// The end of the animation is notified to the subscribers as the completion of `action`.
+async fn place_windows(
+    positioner: Positioner,
+    animation: Option<(u64, Easing)>,
+    action: &'static str,
+) -> Result<()> {
+    // the guards avoid playing multiple animations at the same time.
+    let mut guard = ANIMATION_INSTANCE.lock().await;
+    if let Some(mut last) = guard.take() {
+        last.interrupt();
+        last.wait();
+    }
+
+    let Some((duration, easing)) = animation else {
+        positioner.place()?;
+        return Ok(());
+    };
+
+    *guard = Some(positioner.place_animated(duration, easing, move |result| {
+        if let Err(err) = &result {
+            log::error!("Animated window placement failed: {err}");
+        }
+        ServiceIpc::emit(&SvcEvent::ActionCompleted {
+            action: action.to_owned(),
+            error: result.err().map(|err| err.to_string()),
+        });
+    })?);
+    Ok(())
+}
+
+/// Notifies the subscribers if the logon task changed since the last time
+pub fn notify_startup_task_state(enabled: bool) {
+    let mut last = STARTUP_TASK_STATE.lock().unwrap_or_else(|e| e.into_inner());
+    if *last != Some(enabled) {
+        *last = Some(enabled);
+        ServiceIpc::emit(&SvcEvent::TaskStatusChanged { enabled });
+    }
+}
+
+fn service_status() -> ServiceStatus {
+    let started_at = *crate::STARTED_AT;
+    ServiceStatus {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        git_hash: option_env!("SLU_GIT_HASH").map(str::to_owned),
+        started_at: started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        uptime_secs: started_at.elapsed().unwrap_or_default().as_secs(),
+        actions: SvcAction::NAMES
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    }
+}
+
+/// returns the payload to be sent back to the client, if any
+async fn _process_action(command: SvcAction) -> Result<Option<String>> {
     match command {
-        SvcAction::Stop => crate::exit(0),
-        SvcAction::SetStartup(enabled) => TaskSchedulerHelper::set_run_on_logon(enabled)?,
+        SvcAction::Stop => {
+            snap_zones::clear().await;
+            // written to the subscribers before the listener shuts down
+            ServiceIpc::emit(&SvcEvent::ServiceStopping);
+            crate::exit(0);
+            return Ok(Some("stopping".to_owned()));
+        }
+        SvcAction::SetStartup(enabled) => {
+            let _guard = TASK_SCHEDULER_LOCK.lock().await;
+            TaskSchedulerHelper::set_run_on_logon(enabled)?;
+            notify_startup_task_state(enabled);
+        }
         SvcAction::ShowWindow { hwnd, command } => WindowsApi::show_window(hwnd, command)?,
         SvcAction::ShowWindowAsync { hwnd, command } => {
             WindowsApi::show_window_async(hwnd, command)?
@@ -25,46 +126,35 @@ async fn _process_action(command: SvcAction) -> Result<()> {
             rect.bottom - rect.top,
             flags,
         )?,
+        SvcAction::SetWindowPositionOnMonitor {
+            hwnd,
+            rect,
+            flags,
+            monitor,
+        } => {
+            let rect = to_physical_rect(rect, monitor)?;
+            WindowsApi::set_position(
+                hwnd,
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                flags,
+            )?
+        }
         SvcAction::DeferWindowPositions {
             list,
             animated,
             animation_duration,
             easing,
         } => {
-            // the guards avoid playing multiple animations at the same time.
-            let mut guard = ANIMATION_INSTANCE.lock().await;
-            if let Some(mut last) = guard.take() {
-                last.interrupt();
-                last.wait();
-            }
-
             let mut positioner = Positioner::new();
             for (hwnd, rect) in list {
-                positioner.add(
-                    hwnd,
-                    positioning::rect::Rect {
-                        x: rect.left,
-                        y: rect.top,
-                        width: rect.right - rect.left,
-                        height: rect.bottom - rect.top,
-                    },
-                );
+                positioner.add(hwnd, to_positioning_rect(&rect));
             }
-
-            if !animated {
-                positioner.place()?;
-                return Ok(());
-            }
-
             let easing = Easing::from_name(&easing).unwrap_or(Easing::Linear);
-            *guard =
-                Some(
-                    positioner.place_animated(animation_duration, easing, move |result| {
-                        if let Err(err) = result {
-                            log::error!("Animated window placement failed: {err}");
-                        }
-                    })?,
-                );
+            let animation = animated.then_some((animation_duration, easing));
+            place_windows(positioner, animation, "DeferWindowPositions").await?;
         }
         SvcAction::SetForeground(hwnd) => WindowsApi::set_foreground(hwnd)?,
         SvcAction::SetShortcutsConfig(config) => {
@@ -81,13 +171,98 @@ async fn _process_action(command: SvcAction) -> Result<()> {
         SvcAction::StopShortcutRegistration => {
             crate::hotkeys::stop_shortcut_registration().await?;
         }
+        SvcAction::GetProcessPath { hwnd } => {
+            let path = WindowsApi::exe_path_by_window(hwnd)?;
+            return Ok(Some(path.to_string_lossy().to_string()));
+        }
+        SvcAction::GetStatus => return Ok(Some(serde_json::to_string(&service_status())?)),
+        SvcAction::RegisterSnapZones {
+            zones,
+            animation_duration,
+            easing,
+        } => {
+            let easing = Easing::from_name(&easing).unwrap_or(Easing::Linear);
+            let animation = (animation_duration > 0).then_some((animation_duration, easing));
+            snap_zones::register_all(zones, animation).await?;
+        }
+        SvcAction::RegisterSnapZone {
+            zone_id,
+            rect,
+            monitor,
+        } => snap_zones::register(zone_id, rect, monitor).await?,
+        SvcAction::SetWindowSnapZone { hwnd, zone_id } => {
+            let (rect, animation) = snap_zones::resolve(zone_id).await?;
+            let mut positioner = Positioner::new();
+            positioner.add(hwnd, to_positioning_rect(&rect));
+            place_windows(positioner, animation, "SetWindowSnapZone").await?;
+        }
+        // handled by the ipc server, the connection is turned into an event stream
+        SvcAction::Subscribe => return Err("Subscriptions can't be batched".into()),
+        // handled by `process_batch`, only reached by batches inside batches
+        SvcAction::Batch { .. } => return Err("Batches can't be nested".into()),
     }
-    Ok(())
+    Ok(None)
 }
 
-pub async fn process_action(command: SvcAction) -> IpcResponse {
-    match _process_action(command).await {
-        Ok(()) => IpcResponse::Success,
+fn to_response(result: Result<Option<String>>) -> IpcResponse {
+    match result {
+        Ok(None) => IpcResponse::Success,
+        Ok(Some(data)) => IpcResponse::Data(data),
         Err(err) => IpcResponse::Err(err.to_string()),
     }
 }
+
+/// Batches of window positions are applied at once, so the windows don't move one after another.
+/// If that fails or the batch has other actions, they are run one by one.
+async fn process_batch(actions: Vec<SvcAction>, stop_on_error: bool) -> IpcResponse {
+    let positions: Option<Vec<_>> = actions
+        .iter()
+        .map(|action| match action {
+            SvcAction::SetWindowPosition { hwnd, rect, flags } => {
+                Some((*hwnd, rect.clone(), *flags))
+            }
+            SvcAction::SetWindowPositionOnMonitor {
+                hwnd,
+                rect,
+                flags,
+                monitor,
+            } => {
+                // scaling errors are reported when run one by one
+                let rect = to_physical_rect(rect.clone(), *monitor).ok()?;
+                Some((*hwnd, rect, *flags))
+            }
+            _ => None,
+        })
+        .collect();
+
+    if let Some(positions) = positions.filter(|positions| !positions.is_empty()) {
+        match WindowsApi::set_positions_deferred(&positions) {
+            Ok(()) => {
+                return IpcResponse::Batch(actions.iter().map(|_| IpcResponse::Success).collect())
+            }
+            // applied one by one to know which window failed
+            Err(err) => log::debug!("Deferred window positioning failed: {err}"),
+        }
+    }
+
+    let mut responses = Vec::with_capacity(actions.len());
+    for action in actions {
+        let response = to_response(_process_action(action).await);
+        let failed = matches!(response, IpcResponse::Err(_));
+        responses.push(response);
+        if failed && stop_on_error {
+            break;
+        }
+    }
+    IpcResponse::Batch(responses)
+}
+
+pub async fn process_action(command: SvcAction) -> IpcResponse {
+    match command {
+        SvcAction::Batch {
+            actions,
+            stop_on_error,
+        } => process_batch(actions, stop_on_error).await,
+        command => to_response(_process_action(command).await),
+    }
+}
@@ -8,6 +8,7 @@ mod error;
 mod hotkeys;
 mod logger;
 mod shutdown;
+mod snap_zones;
 mod string_utils;
 mod task_scheduler;
 mod windows_api;
@@ -17,8 +18,11 @@ use error::Result;
 use itertools::Itertools;
 use logger::SluServiceLogger;
 use shutdown::restore_native_taskbar;
-use slu_ipc::{AppIpc, ServiceIpc, IPC};
-use std::sync::{atomic::AtomicBool, LazyLock, OnceLock};
+use slu_ipc::{AppIpc, ServiceIpc, ServiceIpcHandle, ServiceIpcLimits, IPC};
+use std::{
+    sync::{atomic::AtomicBool, LazyLock, OnceLock},
+    time::SystemTime,
+};
 use string_utils::WindowsString;
 use task_scheduler::TaskSchedulerHelper;
 use tokio::sync::mpsc::Sender;
@@ -26,7 +30,7 @@ use windows::Win32::{Security::SE_TCB_NAME, UI::WindowsAndMessaging::SW_MINIMIZE
 use windows_api::WindowsApi;
 
 use crate::{
-    app_management::launch_seelen_ui,
+    app_management::{is_trusted_ipc_client, launch_seelen_ui},
     enviroment::{add_installation_dir_to_path, remove_installation_dir_from_path},
     hotkeys::stop_app_shortcuts,
 };
@@ -41,6 +45,18 @@ static EXIT_CHANNEL: OnceLock<Sender<u32>> = OnceLock::new();
 
 pub static STARTUP: AtomicBool = AtomicBool::new(false);
 
+/// forced at the start of `main`, reported on the service status
+pub static STARTED_AT: LazyLock<SystemTime> = LazyLock::new(SystemTime::now);
+
+/// the logon task can be disabled from the task scheduler, it is checked with this interval
+const STARTUP_TASK_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// the app keeps its connection open between requests, it is closed after this time unused
+const IPC_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[cfg(not(debug_assertions))]
+const GUI_HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
 pub fn get_runtime_handle() -> tokio::runtime::Handle {
     ASYNC_RUNTIME_HANDLE
         .get()
@@ -69,12 +85,14 @@ pub fn exit(code: u32) {
 #[cfg(not(debug_assertions))]
 /// will stop the service after `max_attempts` attempts
 fn restart_gui_on_crash(max_attempts: usize) {
+    AppIpc::start_heartbeat(GUI_HEARTBEAT_INTERVAL);
     tokio::spawn(async move {
         use crate::app_management::GUI_RESTARTED_COUNTER;
         use std::sync::atomic::Ordering;
 
         while GUI_RESTARTED_COUNTER.load(Ordering::SeqCst) < max_attempts {
-            if !AppIpc::can_stablish_connection() {
+            tokio::time::sleep(GUI_HEARTBEAT_INTERVAL * 2).await;
+            if !AppIpc::is_alive(GUI_HEARTBEAT_INTERVAL) {
                 GUI_RESTARTED_COUNTER.fetch_add(1, Ordering::SeqCst);
                 log::trace!("Seelen UI was closed unexpectedly, restarting...");
 
@@ -83,12 +101,24 @@ fn restart_gui_on_crash(max_attempts: usize) {
                     break;
                 }
             }
-            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
         }
         exit(1);
     });
 }
 
+/// Notifies the event subscribers when the logon task is enabled or disabled outside of the app
+fn watch_startup_task() {
+    tokio::spawn(async {
+        loop {
+            match TaskSchedulerHelper::is_startup_enabled() {
+                Ok(enabled) => crate::cli::processing::notify_startup_task_state(enabled),
+                Err(err) => log::debug!("Failed to check the startup task: {err}"),
+            }
+            tokio::time::sleep(STARTUP_TASK_CHECK_INTERVAL).await;
+        }
+    });
+}
+
 #[cfg(debug_assertions)]
 fn stop_service_on_seelen_ui_closed() {
     // it's ok closing the GUI before the service on development
@@ -101,10 +131,19 @@ fn stop_service_on_seelen_ui_closed() {
     });
 }
 
-pub fn setup() -> Result<()> {
+pub fn setup() -> Result<ServiceIpcHandle> {
     WindowsApi::set_process_dpi_aware()?;
     WindowsApi::enable_privilege(SE_TCB_NAME)?;
-    ServiceIpc::start(crate::cli::processing::process_action)?;
+    let ipc = ServiceIpc::start(
+        crate::cli::processing::process_action,
+        is_trusted_ipc_client,
+        ServiceIpcLimits {
+            idle_timeout: IPC_IDLE_TIMEOUT,
+            ..Default::default()
+        },
+    )?;
+
+    watch_startup_task();
 
     if was_started_from_startup_action() {
         WindowsApi::wait_for_native_shell();
@@ -120,7 +159,7 @@ pub fn setup() -> Result<()> {
     {
         restart_gui_on_crash(5);
     }
-    Ok(())
+    Ok(ipc)
 }
 
 fn is_svc_already_running() -> bool {
@@ -139,6 +178,7 @@ fn is_svc_already_running() -> bool {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    LazyLock::force(&STARTED_AT);
     if is_local_dev() {
         let window = WindowsApi::get_console_window();
         let _ = WindowsApi::show_window(window.0 as _, SW_MINIMIZE.0);
@@ -160,16 +200,17 @@ async fn main() -> Result<()> {
 
     let _ = SluServiceLogger::uninstall_old_logging();
     SluServiceLogger::init()?;
-    TaskSchedulerHelper::create_service_task()?;
+    TaskSchedulerHelper::ensure_service_task()?;
 
     log::info!("Starting Seelen UI Service");
     log::info!("Arguments: {:?}", std::env::args().collect_vec());
-    setup()?;
+    let ipc = setup()?;
 
     // wait for stop signal
     let exit_code = rx.recv().await.unwrap_or_default();
 
     // shutdown tasks:
+    ipc.shutdown().await;
     restore_native_taskbar()?;
     stop_app_shortcuts();
     log::info!("Seelen UI Service exited with code {exit_code}");
@@ -0,0 +1,82 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use positioning::easings::Easing;
+use seelen_core::rect::Rect;
+
+use crate::{error::Result, windows_api::WindowsApi};
+
+static SNAP_ZONES: LazyLock<tokio::sync::Mutex<SnapZones>> =
+    LazyLock::new(|| tokio::sync::Mutex::new(SnapZones::default()));
+
+/// Zones registered by the app, windows are moved to them by id
+#[derive(Default)]
+struct SnapZones {
+    zones: HashMap<u32, SnapZone>,
+    /// none to place the windows without animation
+    animation: Option<(u64, Easing)>,
+}
+
+struct SnapZone {
+    rect: Rect,
+    monitor: isize,
+    /// dpi of the monitor when the rect was registered or last rescaled
+    dpi: u32,
+}
+
+impl SnapZone {
+    fn new(rect: Rect, monitor: isize) -> Result<Self> {
+        let dpi = WindowsApi::monitor_dpi(monitor)?;
+        Ok(Self { rect, monitor, dpi })
+    }
+
+    /// Scales the rect from the origin of its monitor if the dpi changed since the last time
+    fn rescale_to_current_dpi(&mut self) -> Result<()> {
+        let dpi = WindowsApi::monitor_dpi(self.monitor)?;
+        if dpi == self.dpi {
+            return Ok(());
+        }
+        self.rect = WindowsApi::scale_rect_on_monitor(&self.rect, self.monitor, self.dpi, dpi)?;
+        self.dpi = dpi;
+        Ok(())
+    }
+}
+
+/// Replaces all the zones, each one is assigned to the monitor it is on
+pub async fn register_all(
+    zones: HashMap<u32, Rect>,
+    animation: Option<(u64, Easing)>,
+) -> Result<()> {
+    let mut registered = HashMap::with_capacity(zones.len());
+    for (zone_id, rect) in zones {
+        let monitor = WindowsApi::monitor_from_rect(&rect);
+        registered.insert(zone_id, SnapZone::new(rect, monitor)?);
+    }
+
+    let mut snap_zones = SNAP_ZONES.lock().await;
+    snap_zones.zones = registered;
+    snap_zones.animation = animation;
+    Ok(())
+}
+
+/// Adds or replaces a single zone, fails if the monitor is not valid
+pub async fn register(zone_id: u32, rect: Rect, monitor: isize) -> Result<()> {
+    let zone = SnapZone::new(rect, monitor)?;
+    SNAP_ZONES.lock().await.zones.insert(zone_id, zone);
+    Ok(())
+}
+
+pub async fn clear() {
+    *SNAP_ZONES.lock().await = SnapZones::default();
+}
+
+/// Rect of the zone on the current dpi of its monitor and the animation to move windows to it
+pub async fn resolve(zone_id: u32) -> Result<(Rect, Option<(u64, Easing)>)> {
+    let mut snap_zones = SNAP_ZONES.lock().await;
+    let animation = snap_zones.animation;
+    let zone = snap_zones
+        .zones
+        .get_mut(&zone_id)
+        .ok_or_else(|| format!("Snap zone {zone_id} is not registered"))?;
+    zone.rescale_to_current_dpi()?;
+    Ok((zone.rect.clone(), animation))
+}
@@ -1,5 +1,7 @@
+use std::path::{Path, PathBuf};
+
 use windows::Win32::{
-    Foundation::{VARIANT_FALSE, VARIANT_TRUE},
+    Foundation::{VARIANT_BOOL, VARIANT_FALSE, VARIANT_TRUE},
     System::TaskScheduler::{
         IExecAction2, ITaskFolder, ITaskService, TaskScheduler, TASK_ACTION_EXEC,
         TASK_CREATE_OR_UPDATE, TASK_LOGON_INTERACTIVE_TOKEN, TASK_RUNLEVEL_HIGHEST,
@@ -46,14 +48,20 @@ impl TaskSchedulerHelper {
         Ok(())
     }
 
-    /// this task handles the startup of the service and the app on login
-    pub fn create_service_task() -> Result<()> {
+    /// path that the service task should execute
+    fn service_path() -> Result<PathBuf> {
         let service_path = if was_installed_using_msix() {
             WindowsApi::known_folder(FOLDERID_LocalAppData)?
                 .join("Microsoft\\WindowsApps\\slu-service.exe")
         } else {
             std::env::current_exe()?
         };
+        Ok(service_path)
+    }
+
+    /// this task handles the startup of the service and the app on login
+    pub fn create_service_task() -> Result<()> {
+        let service_path = Self::service_path()?;
         Com::run_with_context(|| unsafe {
             let task_service = Self::get_task_service()?;
             // remove old task as backwards compatibility
@@ -116,6 +124,82 @@ impl TaskSchedulerHelper {
         })
     }
 
+    /// returns the path executed by the service task, `None` if the task doesn't exist
+    pub fn registered_startup_path() -> Result<Option<PathBuf>> {
+        Com::run_with_context(|| unsafe {
+            let task_service = Self::get_task_service()?;
+            let Ok(seelen_folder) = task_service.GetFolder(&GROUP_FOLDER.into()) else {
+                return Ok(None);
+            };
+            let Ok(task) = seelen_folder.GetTask(&SERVICE_TASK_NAME.into()) else {
+                return Ok(None);
+            };
+
+            // task scheduler collections start counting from 1
+            let action: IExecAction2 = task.Definition()?.Actions()?.get_Item(1)?.cast()?;
+            let mut action_path = BSTR::new();
+            action.Path(&mut action_path)?;
+            Ok(Some(PathBuf::from(action_path.to_string())))
+        })
+    }
+
+    /// updates the executable of the service task, keeping its triggers and settings
+    pub fn update_startup_path(new_path: &Path) -> Result<()> {
+        Com::run_with_context(|| unsafe {
+            let task_service = Self::get_task_service()?;
+            let seelen_folder = task_service.GetFolder(&GROUP_FOLDER.into())?;
+            let task = seelen_folder.GetTask(&SERVICE_TASK_NAME.into())?;
+            let task = task.Definition()?;
+
+            let action: IExecAction2 = task.Actions()?.get_Item(1)?.cast()?;
+            action.SetPath(&new_path.to_string_lossy().to_string().into())?;
+
+            let mut task_xml = BSTR::new();
+            task.XmlText(&mut task_xml)?;
+            Self::register_task(&seelen_folder, SERVICE_TASK_NAME, &task_xml)?;
+            Ok(())
+        })
+    }
+
+    /// creates the service task if missing, or repairs it if the binary was relocated
+    pub fn ensure_service_task() -> Result<()> {
+        let service_path = Self::service_path()?;
+        match Self::registered_startup_path()? {
+            None => Self::create_service_task(),
+            Some(registered) => {
+                let registered = registered.to_string_lossy().to_lowercase();
+                if registered != service_path.to_string_lossy().to_lowercase() {
+                    log::info!("Service task points to {registered}, updating it");
+                    Self::update_startup_path(&service_path)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// startup is enabled if the service task exists, is not disabled and has a logon trigger
+    pub fn is_startup_enabled() -> Result<bool> {
+        Com::run_with_context(|| unsafe {
+            let task_service = Self::get_task_service()?;
+            let Ok(seelen_folder) = task_service.GetFolder(&GROUP_FOLDER.into()) else {
+                return Ok(false);
+            };
+            let Ok(task) = seelen_folder.GetTask(&SERVICE_TASK_NAME.into()) else {
+                return Ok(false);
+            };
+
+            let mut enabled = VARIANT_BOOL::default();
+            task.Enabled(&mut enabled)?;
+            if enabled == VARIANT_FALSE {
+                return Ok(false);
+            }
+
+            let mut triggers_count = 0;
+            task.Definition()?.Triggers()?.Count(&mut triggers_count)?;
+            Ok(triggers_count > 0)
+        })
+    }
+
     pub fn remove_service_task() -> Result<()> {
         Com::run_with_context(|| unsafe {
             let task_service: ITaskService = Com::create_instance(&TaskScheduler)?;
@@ -9,8 +9,12 @@ use std::{
 };
 
 use com::Com;
+use seelen_core::rect::Rect;
 use windows::Win32::{
-    Foundation::{HANDLE, HWND, LUID},
+    Foundation::{CloseHandle, HANDLE, HWND, LUID, RECT},
+    Graphics::Gdi::{
+        GetMonitorInfoW, MonitorFromRect, HMONITOR, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+    },
     Security::{
         AdjustTokenPrivileges, LookupPrivilegeValueW, SE_PRIVILEGE_ENABLED,
         TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_QUERY,
@@ -18,15 +22,23 @@ use windows::Win32::{
     System::{
         Com::IPersistFile,
         Console::GetConsoleWindow,
-        Threading::{AttachThreadInput, GetCurrentProcess, GetCurrentThreadId, OpenProcessToken},
+        Threading::{
+            AttachThreadInput, GetCurrentProcess, GetCurrentThreadId, OpenProcess,
+            OpenProcessToken, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+            PROCESS_QUERY_LIMITED_INFORMATION,
+        },
     },
     UI::{
-        HiDpi::{SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2},
+        HiDpi::{
+            GetDpiForMonitor, SetProcessDpiAwarenessContext,
+            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, MDT_EFFECTIVE_DPI,
+        },
         Shell::{IShellLinkW, SHGetKnownFolderPath, ShellLink, KF_FLAG_DEFAULT},
         WindowsAndMessaging::{
-            BringWindowToTop, FindWindowW, GetClassNameW, GetForegroundWindow,
-            GetWindowThreadProcessId, IsIconic, SetWindowPos, ShowWindow, ShowWindowAsync,
-            SET_WINDOW_POS_FLAGS, SHOW_WINDOW_CMD, SWP_NOACTIVATE, SWP_NOZORDER, SW_RESTORE,
+            BeginDeferWindowPos, BringWindowToTop, DeferWindowPos, EndDeferWindowPos, FindWindowW,
+            GetClassNameW, GetForegroundWindow, GetWindowThreadProcessId, IsIconic, SetWindowPos,
+            ShowWindow, ShowWindowAsync, SET_WINDOW_POS_FLAGS, SHOW_WINDOW_CMD, SWP_NOACTIVATE,
+            SWP_NOZORDER, SW_RESTORE,
         },
     },
 };
@@ -50,6 +62,25 @@ impl WindowsApi {
         (process_id, thread_id)
     }
 
+    /// Path of the executable of the process that owns the window
+    /// https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-queryfullprocessimagenamew
+    pub fn exe_path_by_window(addr: isize) -> Result<PathBuf> {
+        let (process_id, _) = Self::window_thread_process_id(HWND(addr as _));
+        if process_id == 0 {
+            return Err(format!("Window {addr:#x} doesn't exist").into());
+        }
+
+        let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id)? };
+        let mut path = WindowsString::new_to_fill(1024);
+        let mut len = path.inner.len() as u32;
+        let result = unsafe {
+            QueryFullProcessImageNameW(handle, PROCESS_NAME_WIN32, path.as_pwstr(), &mut len)
+        };
+        unsafe { CloseHandle(handle)? };
+        result?;
+        Ok(PathBuf::from(path.to_os_string()))
+    }
+
     pub fn show_window(addr: isize, command: i32) -> Result<()> {
         // BOOL is returned but does not signify whether or not the operation was succesful
         // https://docs.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-showwindow
@@ -131,6 +162,87 @@ impl WindowsApi {
         Ok(())
     }
 
+    /// Moves all the windows in a single step, `list` contains the window, its rect and the flags.
+    /// If any of them can't be moved none is.
+    pub fn set_positions_deferred(list: &[(isize, Rect, u32)]) -> Result<()> {
+        unsafe {
+            let mut hdwp = BeginDeferWindowPos(list.len() as i32)?;
+            for (hwnd, rect, flags) in list {
+                hdwp = DeferWindowPos(
+                    hdwp,
+                    HWND(*hwnd as _),
+                    None,
+                    rect.left,
+                    rect.top,
+                    rect.right - rect.left,
+                    rect.bottom - rect.top,
+                    SET_WINDOW_POS_FLAGS(*flags) | SWP_NOACTIVATE | SWP_NOZORDER,
+                )?;
+            }
+            EndDeferWindowPos(hdwp).filter_fake_error()?;
+        }
+        Ok(())
+    }
+
+    /// Monitor with the largest intersection with the rect, or the nearest to it
+    pub fn monitor_from_rect(rect: &Rect) -> isize {
+        let rect = RECT {
+            left: rect.left,
+            top: rect.top,
+            right: rect.right,
+            bottom: rect.bottom,
+        };
+        unsafe { MonitorFromRect(&rect, MONITOR_DEFAULTTONEAREST) }.0 as isize
+    }
+
+    pub fn monitor_rect(monitor: isize) -> Result<Rect> {
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        unsafe { GetMonitorInfoW(HMONITOR(monitor as _), &mut info).ok()? };
+        let rect = info.rcMonitor;
+        Ok(Rect {
+            left: rect.left,
+            top: rect.top,
+            right: rect.right,
+            bottom: rect.bottom,
+        })
+    }
+
+    /// Scales the rect from the top-left corner of the monitor
+    pub fn scale_rect_on_monitor(
+        rect: &Rect,
+        monitor: isize,
+        from_dpi: u32,
+        to_dpi: u32,
+    ) -> Result<Rect> {
+        let origin = Self::monitor_rect(monitor)?;
+        let scale = |value: i32, origin: i32| {
+            origin + ((value - origin) as i64 * to_dpi as i64 / from_dpi as i64) as i32
+        };
+        Ok(Rect {
+            left: scale(rect.left, origin.left),
+            top: scale(rect.top, origin.top),
+            right: scale(rect.right, origin.left),
+            bottom: scale(rect.bottom, origin.top),
+        })
+    }
+
+    pub fn monitor_dpi(monitor: isize) -> Result<u32> {
+        let mut dpi_x: u32 = 0;
+        let mut _dpi_y: u32 = 0;
+        unsafe {
+            GetDpiForMonitor(
+                HMONITOR(monitor as _),
+                MDT_EFFECTIVE_DPI,
+                &mut dpi_x,
+                &mut _dpi_y,
+            )?
+        };
+        Ok(dpi_x)
+    }
+
     pub fn set_process_dpi_aware() -> Result<()> {
         unsafe { SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)? };
         Ok(())